@@ -1,43 +1,363 @@
+use std::cell::RefCell;
 use std::rc::Rc;
-use web_sys::WebGl2RenderingContext as GL;
-use web_sys::*;
-use wasm_bindgen::JsCast;
+use nalgebra::{Unit, Vector3};
 
-use crate::render::{Camera, Globe, Renderer, Render};
+use crate::astronomy;
+use crate::backend::GlBackend;
+use crate::error::Result;
+use crate::geo::GeoPosition;
+use crate::geometry::Mesh;
+use crate::image::Image;
+use crate::options::WebClientOptions;
+use crate::render::{BufferUsage, Camera, CreateArray, Globe, GlobeLayer, Marker, OrbitController};
+use crate::render::{TextureFormat, TextureReport};
+use crate::scene::{Node, NodeId, Scene};
+use crate::shader::{ShaderCache, ShaderRegistry};
 
 //#[derive(Clone)]
 pub struct App {
     time: f64,
+    /// UTC time shown, in milliseconds since the Unix epoch.
+    clock: f64,
     camera: Camera,
-    renderables: Vec<Box<dyn Render>>
+    orbit: OrbitController,
+    globe_radius: f32,
+    globe: Rc<RefCell<Globe>>,
+    globe_node: NodeId,
+    scene: Scene,
+    shaders: ShaderCache
 }
 
 impl App {
-    pub fn new(gl: Rc<GL>) -> Self {
-        let canvas: HtmlCanvasElement = gl.canvas().unwrap().dyn_into().unwrap();
-        let w = canvas.width() as f32;
-        let h = canvas.height() as f32;
-        let mut camera = Camera::new(30.0, w / h, 1.0, 1000.0);
-        camera.set_position(0.0, 0.0, 500.0);
-        camera.set_target(0.0, 0.0, 0.0);
-        let globe = Globe::new(gl.clone(), 200.0, 40, 30);
-
-        App {
+    pub fn new(gl: &dyn GlBackend, options: &WebClientOptions) -> Result<Self> {
+        let (w, h) = gl.drawing_buffer_size();
+        let camera_options = &options.camera;
+        let mut camera = Camera::new(
+            camera_options.fov.to_radians(),
+            w as f32 / h as f32,
+            camera_options.near,
+            camera_options.far
+        );
+        let [x, y, z] = camera_options.position;
+        camera.set_position(x, y, z);
+        let [x, y, z] = camera_options.target;
+        camera.set_target(x, y, z);
+
+        let globe_options = &options.globe;
+        let mesh = Mesh::uv_sphere(
+            globe_options.radius,
+            globe_options.width_segments,
+            globe_options.height_segments
+        )?;
+        let mut shaders = ShaderCache::new(ShaderRegistry::embedded());
+        let mut globe = Globe::new(gl, &mut shaders, mesh, &options.globe_maps())?;
+        globe.set_bump_scale(globe_options.bump_scale)?;
+        globe.cloud_speed = globe_options.cloud_speed;
+        for name in &globe_options.disabled_layers {
+            globe.set_layer_enabled(name.parse()?, false)?;
+        }
+
+        // Stay far enough out for the near plane to clear the surface, and
+        // close enough for the far side of the globe to stay in view.
+        let orbit = OrbitController::new(
+            &camera,
+            globe_options.radius + camera_options.near,
+            camera_options.far - globe_options.radius
+        );
+
+        let globe = Rc::new(RefCell::new(globe));
+        let mut scene = Scene::new();
+        let globe_node = scene.add(scene.root(), Node::with_renderable(globe.clone()))?;
+
+        let app = App {
             time: 0.0,
+            clock: 0.0,
             camera,
-            renderables: vec![Box::new(globe)]
+            orbit,
+            globe_radius: globe_options.radius,
+            globe,
+            globe_node,
+            scene,
+            shaders
+        };
+
+        Ok(app)
+    }
+
+    /// Sets the UTC time, in milliseconds since the Unix epoch, which places
+    /// the sun. The clock then advances in real time.
+    pub fn set_time(&mut self, utc_millis: f64) {
+        self.clock = utc_millis;
+    }
+
+    /// Seconds since the app started.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// World space direction towards the sun at the current UTC time.
+    pub fn sun_direction(&self) -> Unit<Vector3<f32>> {
+        let direction = astronomy::subsolar_point(self.clock).direction();
+        match self.scene.world_matrix(self.globe_node) {
+            Ok(globe_world) => Unit::new_normalize(globe_world.transform_vector(&direction)),
+            Err(_) => direction
+        }
+    }
+
+    /// Follows a change of the drawing buffer size.
+    pub fn resize(&mut self, gl: &dyn GlBackend) {
+        let (w, h) = gl.drawing_buffer_size();
+        if w > 0 && h > 0 {
+            self.camera.set_aspect_ratio(w as f32 / h as f32);
         }
     }
 
-    pub fn update(&mut self, dt: f64) {
+    pub fn update(&mut self, dt: f64) -> Result<()> {
         self.time += dt;
+        self.clock += dt * 1000.0;
+        self.orbit.update(dt as f32);
+        self.orbit.apply(&mut self.camera);
+        self.globe.borrow_mut().update(dt as f32)
+    }
+
+    /// Sets a uniform of the globe shaders from plain numbers.
+    pub fn set_uniform(&mut self, name: &str, data: &[f32]) -> Result<()> {
+        self.globe.borrow_mut().set_uniform(name, data)
+    }
+
+    /// Replaces the sources of a shader program, see `ShaderCache::reload`.
+    pub fn reload_shader(&mut self, gl: &dyn GlBackend, name: &str, vs_source: &str, fs_source: &str) -> Result<()> {
+        self.shaders.reload(gl, name, vs_source, fs_source)
+    }
+
+    /// How far the maps of the globe have loaded.
+    pub fn texture_report(&self) -> TextureReport {
+        TextureReport::of(self.globe.borrow().textures())
+    }
+
+    /// Rebuilds the shaders and the globe after the context was restored.
+    /// The backend has to be reset first, see `GlBackend::reset`.
+    pub fn restore(&mut self, gl: &dyn GlBackend) -> Result<()> {
+        self.shaders.restore(gl)?;
+        self.globe.borrow_mut().restore(gl)
+    }
+
+    /// Draws a grid of data over the globe, see `Globe::set_overlay`.
+    pub fn set_overlay<T: CreateArray>(
+        &mut self,
+        gl: &dyn GlBackend,
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        data: &[T]
+    ) -> Result<()> {
+        self.globe.borrow_mut().set_overlay(gl, format, width, height, data)
+    }
+
+    /// Draws an image over the globe, see `Globe::set_overlay_image`.
+    pub fn set_overlay_image(&mut self, gl: &dyn GlBackend, image: &Image) -> Result<()> {
+        self.globe.borrow_mut().set_overlay_image(gl, image)
+    }
+
+    /// Replaces a rectangle of the overlay.
+    pub fn update_overlay<T: CreateArray>(
+        &mut self,
+        gl: &dyn GlBackend,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        data: &[T]
+    ) -> Result<()> {
+        self.globe.borrow_mut().update_overlay(gl, x, y, width, height, data)
+    }
+
+    /// Replaces the markers over the globe. Markers that are going to move
+    /// about every frame are `streaming`.
+    pub fn set_markers(&mut self, gl: &dyn GlBackend, markers: &[Marker], streaming: bool) -> Result<()> {
+        let usage = if streaming { BufferUsage::Stream } else { BufferUsage::Dynamic };
+        self.globe.borrow_mut().set_markers(gl, markers, usage)
+    }
+
+    /// Moves the markers from `first` on, adding the ones past the last.
+    pub fn move_markers(&mut self, gl: &dyn GlBackend, first: usize, markers: &[Marker]) -> Result<()> {
+        self.globe.borrow_mut().update_markers(gl, first, markers)
+    }
+
+    pub fn set_layer_enabled(&mut self, layer: GlobeLayer, enabled: bool) -> Result<()> {
+        self.globe.borrow_mut().set_layer_enabled(layer, enabled)
+    }
+
+    /// Flies the camera to `altitude` above the given latitude / longitude.
+    pub fn fly_to(&mut self, position: &GeoPosition, duration: f32) -> Result<()> {
+        let globe_world = self.scene.world_matrix(self.globe_node)?;
+        let destination = globe_world.transform_point(&position.to_cartesian(self.globe_radius));
+        self.orbit.fly_to(&destination, duration);
+        Ok(())
+    }
+
+    pub fn is_flying(&self) -> bool {
+        self.orbit.is_flying()
+    }
+
+    pub fn get_orbit_mut(&mut self) -> &mut OrbitController {
+        &mut self.orbit
     }
 
     pub fn get_camera(&self) -> &Camera {
         &self.camera
     }
 
-    pub fn get_renderables(&self) -> &[Box<dyn Render>] {
-        self.renderables.as_slice()
+    pub fn globe_node(&self) -> NodeId {
+        self.globe_node
+    }
+
+    pub fn get_scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    pub fn get_scene_mut(&mut self) -> &mut Scene {
+        &mut self.scene
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+    use nalgebra::{Point3, UnitQuaternion};
+    use crate::backend::{GlCall, MockBackend, TextureState};
+    use crate::error::Error;
+    use crate::render::Renderer;
+
+    #[test]
+    fn resize_follows_the_drawing_buffer() {
+        let gl = MockBackend::new();
+        let mut app = App::new(&gl, &WebClientOptions::default()).unwrap();
+        assert_eq!(app.get_camera().projection().aspect(), 1.0);
+
+        gl.set_drawing_buffer_size(1600, 900);
+        app.resize(&gl);
+        assert_eq!(app.get_camera().projection().aspect(), 1600.0 / 900.0);
+
+        // A collapsed canvas keeps the last usable projection.
+        gl.set_drawing_buffer_size(0, 900);
+        app.resize(&gl);
+        assert_eq!(app.get_camera().projection().aspect(), 1600.0 / 900.0);
+    }
+
+    #[test]
+    fn update_moves_the_camera_with_the_orbit() {
+        let gl = MockBackend::new();
+        let mut app = App::new(&gl, &WebClientOptions::default()).unwrap();
+
+        app.get_orbit_mut().wheel(-1.0e6);
+        for _ in 0..10 {
+            app.update(0.1).unwrap();
+        }
+
+        // Clamped to the globe radius plus the near plane distance.
+        assert!((app.get_camera().position().z - 201.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn set_time_places_the_sun() {
+        let gl = MockBackend::new();
+        let mut app = App::new(&gl, &WebClientOptions::default()).unwrap();
+
+        // 2021-06-21 12:00 UTC, around the June solstice: the sun is over
+        // the Tropic of Cancer, close to the Greenwich meridian.
+        app.set_time(1_624_276_800_000.0);
+        let position = GeoPosition::from_cartesian(&Point3::from(app.sun_direction().into_inner()), 0.0);
+        assert!((position.latitude - 23.44).abs() < 0.05);
+        assert!(position.longitude.abs() < 1.0);
+
+        // The sun follows the globe when its node is rotated.
+        let globe_node = app.globe_node();
+        app.get_scene_mut().set_rotation(globe_node, UnitQuaternion::from_axis_angle(&Vector3::y_axis(), FRAC_PI_2)).unwrap();
+        let position = GeoPosition::from_cartesian(&Point3::from(app.sun_direction().into_inner()), 0.0);
+        assert!((position.latitude - 23.44).abs() < 0.05);
+        assert!((position.longitude - 90.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn fly_to_ends_above_the_destination() {
+        let gl = MockBackend::new();
+        let mut app = App::new(&gl, &WebClientOptions::default()).unwrap();
+        let paris = GeoPosition::new(48.9, 2.35, 150.0);
+
+        app.fly_to(&paris, 1.0).unwrap();
+        for _ in 0..11 {
+            app.update(0.1).unwrap();
+        }
+
+        let reached = GeoPosition::from_cartesian(app.get_camera().position(), 200.0);
+        assert!((reached.latitude - paris.latitude).abs() < 1e-2);
+        assert!((reached.longitude - paris.longitude).abs() < 1e-2);
+        assert!((reached.altitude - paris.altitude).abs() < 1e-2);
+    }
+
+    #[test]
+    fn broken_shader_reloads_keep_the_globe_drawing() {
+        let gl = MockBackend::new();
+        let mut app = App::new(&gl, &WebClientOptions::default()).unwrap();
+        let vs = ShaderRegistry::embedded().get("globe_vs.glsl").unwrap().to_string();
+
+        let error = app.reload_shader(&gl, "globe_atmosphere", &vs, "#version 300 es\n#error typo\n");
+        assert!(matches!(error, Err(Error::ShaderCompile { .. })));
+
+        gl.clear_calls();
+        Renderer::new([0.0; 4]).render(&gl, app.get_camera(), app.get_scene(), 0.0, app.sun_direction()).unwrap();
+        let draws = gl.calls().iter().filter(|c| matches!(c, GlCall::DrawElements { .. })).count();
+        assert_eq!(draws, 3);
+    }
+
+    #[test]
+    fn the_globe_is_rebuilt_after_a_context_loss() {
+        let gl = MockBackend::new();
+        let mut app = App::new(&gl, &WebClientOptions::default()).unwrap();
+        let mut renderer = Renderer::new([0.0; 4]);
+        renderer.init(&gl).unwrap();
+        let stats = gl.resources().stats();
+        let images = |gl: &MockBackend| gl.calls().iter().filter(|c| matches!(c, GlCall::LoadImage(..))).count();
+        let loaded = images(&gl);
+
+        gl.reset();
+        gl.clear_calls();
+        app.restore(&gl).unwrap();
+        renderer.init(&gl).unwrap();
+        assert_eq!(images(&gl), loaded);
+        assert_eq!(gl.resources().stats().programs, stats.programs);
+        assert_eq!(gl.resources().stats().buffers, stats.buffers);
+        assert_eq!(gl.resources().stats().textures, stats.textures);
+        assert_eq!(gl.resources().stats().vertex_arrays, stats.vertex_arrays);
+
+        gl.clear_calls();
+        renderer.render(&gl, app.get_camera(), app.get_scene(), 0.0, app.sun_direction()).unwrap();
+        let calls = gl.calls();
+        // Nothing from the lost context is deleted.
+        assert!(!calls.iter().any(|c| matches!(c, GlCall::DeleteProgram(_) | GlCall::DeleteBuffer(_))));
+        let draws: Vec<_> = calls.iter().filter(|c| matches!(c, GlCall::DrawElements { .. })).collect();
+        assert_eq!(draws.len(), 3);
+        assert!(draws.iter().all(|c| !matches!(c, GlCall::DrawElements { count: 0, .. })));
+    }
+
+    #[test]
+    fn the_texture_report_covers_every_map() {
+        let gl = MockBackend::new();
+        let app = App::new(&gl, &WebClientOptions::default()).unwrap();
+        let report = app.texture_report();
+        assert_eq!((report.pending, report.loaded), (6, 0));
+
+        for (i, call) in gl.calls().iter().enumerate() {
+            if let GlCall::LoadImage(texture, ..) = call {
+                let state = if i % 2 == 0 { TextureState::Loaded } else { TextureState::Failed("404".to_string()) };
+                gl.finish_image(*texture, state);
+            }
+        }
+        let report = app.texture_report();
+        assert!(report.is_settled());
+        assert_eq!(report.loaded + report.failed.len(), 6);
     }
 }
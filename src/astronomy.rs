@@ -0,0 +1,114 @@
+//! Position of the sun relative to the Earth.
+//!
+//! Uses the low precision solar coordinates from the Astronomical Almanac,
+//! good to about 0.01° in declination and a few seconds in the equation of
+//! time between 1950 and 2050, which is plenty to place the terminator.
+
+use crate::geo::GeoPosition;
+
+
+const MILLIS_PER_DAY: f64 = 86_400_000.0;
+
+/// Unix time of the J2000.0 epoch, 2000-01-01 12:00 TT, in milliseconds.
+/// The 64 s between TT and UTC are below the precision used here.
+const J2000_MILLIS: f64 = 946_728_000_000.0;
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SunPosition {
+    /// Latitude of the subsolar point, in degrees.
+    pub declination: f64,
+    /// Apparent minus mean solar time, in minutes.
+    pub equation_of_time: f64
+}
+
+impl SunPosition {
+    pub fn at(utc_millis: f64) -> Self {
+        let days = (utc_millis - J2000_MILLIS) / MILLIS_PER_DAY;
+
+        let mean_anomaly = (357.529 + 0.985_600_28 * days).to_radians();
+        let mean_longitude = 280.459 + 0.985_647_36 * days;
+        let ecliptic_longitude = (mean_longitude
+            + 1.915 * mean_anomaly.sin()
+            + 0.020 * (2.0 * mean_anomaly).sin()).to_radians();
+        let obliquity = (23.439 - 0.000_000_36 * days).to_radians();
+
+        let right_ascension = (obliquity.cos() * ecliptic_longitude.sin())
+            .atan2(ecliptic_longitude.cos())
+            .to_degrees();
+        let declination = (obliquity.sin() * ecliptic_longitude.sin()).asin().to_degrees();
+
+        // The sun moves 1° in right ascension every 4 minutes.
+        let equation_of_time = wrap_degrees(mean_longitude - right_ascension) * 4.0;
+
+        SunPosition { declination, equation_of_time }
+    }
+}
+
+
+/// The point on the Earth where the sun is at the zenith at `utc_millis`
+/// (milliseconds since the Unix epoch).
+pub fn subsolar_point(utc_millis: f64) -> GeoPosition {
+    let sun = SunPosition::at(utc_millis);
+
+    // At 12:00 mean solar time the sun is over the meridian, corrected by
+    // the equation of time.
+    let hours = (utc_millis / 3_600_000.0).rem_euclid(24.0);
+    let longitude = wrap_degrees(-15.0 * (hours - 12.0) - sun.equation_of_time / 4.0);
+
+    GeoPosition::new(sun.declination as f32, longitude as f32, 0.0)
+}
+
+
+/// Wraps an angle to `-180..180` degrees.
+fn wrap_degrees(degrees: f64) -> f64 {
+    (degrees + 180.0).rem_euclid(360.0) - 180.0
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64, tolerance: f64) {
+        assert!((a - b).abs() < tolerance, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn solstices() {
+        // 2021-06-21 03:32 UTC
+        assert_close(SunPosition::at(1_624_246_320_000.0).declination, 23.44, 0.01);
+        // 2021-12-21 15:59 UTC
+        assert_close(SunPosition::at(1_640_102_340_000.0).declination, -23.44, 0.01);
+    }
+
+    #[test]
+    fn equinoxes() {
+        // 2021-03-20 09:37 UTC
+        assert_close(SunPosition::at(1_616_233_020_000.0).declination, 0.0, 0.01);
+        // 2021-09-22 19:21 UTC
+        assert_close(SunPosition::at(1_632_338_460_000.0).declination, 0.0, 0.01);
+    }
+
+    #[test]
+    fn equation_of_time_extremes() {
+        // Sundials run fastest in early November and slowest in mid February.
+        let november = SunPosition::at(1_635_940_800_000.0);
+        assert_close(november.equation_of_time, 16.4, 0.1);
+
+        let february = SunPosition::at(1_613_044_800_000.0);
+        assert_close(february.equation_of_time, -14.2, 0.1);
+    }
+
+    #[test]
+    fn subsolar_longitude_follows_the_clock() {
+        // 2021-11-03 12:00 UTC: the sun crossed Greenwich 16.4 minutes earlier.
+        let noon = subsolar_point(1_635_940_800_000.0);
+        assert_close(noon.longitude as f64, -16.4 / 4.0, 0.05);
+        assert_close(noon.latitude as f64, -15.21, 0.05);
+
+        // Six hours later it is a quarter turn further west.
+        let evening = subsolar_point(1_635_940_800_000.0 + 6.0 * 3_600_000.0);
+        assert_close(evening.longitude as f64, -90.0 - 16.4 / 4.0, 0.05);
+    }
+}
@@ -0,0 +1,269 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::{Resource, Resources};
+use crate::error::Result;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BufferId(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureId(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerId(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderId(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProgramId(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VertexArrayId(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct UniformLocationId(pub u32);
+
+
+/// `EXT_texture_filter_anisotropic` texture parameter.
+pub const TEXTURE_MAX_ANISOTROPY_EXT: u32 = 0x84FE;
+/// `EXT_texture_filter_anisotropic` limit, read with `getParameter`.
+pub const MAX_TEXTURE_MAX_ANISOTROPY_EXT: u32 = 0x84FF;
+
+
+/// Typed view over the data handed to buffer and texture uploads.
+#[derive(Clone, Copy, Debug)]
+pub enum ArrayData<'a> {
+    U8(&'a [u8]),
+    I16(&'a [i16]),
+    U16(&'a [u16]),
+    U32(&'a [u32]),
+    F32(&'a [f32])
+}
+
+impl ArrayData<'_> {
+    pub fn byte_len(&self) -> usize {
+        match self {
+            ArrayData::U8(data) => data.len(),
+            ArrayData::I16(data) => data.len() * 2,
+            ArrayData::U16(data) => data.len() * 2,
+            ArrayData::U32(data) => data.len() * 4,
+            ArrayData::F32(data) => data.len() * 4
+        }
+    }
+}
+
+/// An active attribute or uniform of a linked program, as reported by
+/// `getActiveAttrib` / `getActiveUniform`. Array names have their `[0]`
+/// suffix removed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ActiveInfo {
+    pub name: String,
+    /// `GL::FLOAT_VEC3`, `GL::SAMPLER_2D`, ...
+    pub gl_type: u32,
+    /// Number of elements, more than 1 for arrays.
+    pub size: i32
+}
+
+/// Progress of an image fetched into a texture.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TextureState {
+    Pending,
+    Loaded,
+    /// The image could not be fetched, decoded or uploaded. The texture
+    /// keeps showing its placeholder.
+    Failed(String)
+}
+
+/// How an image fetched by `GlBackend::load_image` is uploaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageUpload {
+    /// `GL::RGBA8` or `GL::SRGB8_ALPHA8`.
+    pub internal_format: u32,
+    /// Flips the rows, so that the first one ends up at `t = 1`.
+    pub flip_y: bool,
+    pub premultiply_alpha: bool,
+    pub generate_mipmaps: bool
+}
+
+/// The state of an image load, shared between a texture and the callbacks
+/// of the image.
+#[derive(Clone, Debug)]
+pub struct ImageLoad(Rc<RefCell<TextureState>>);
+
+impl ImageLoad {
+    pub fn new() -> Self {
+        ImageLoad(Rc::new(RefCell::new(TextureState::Pending)))
+    }
+
+    pub fn state(&self) -> TextureState {
+        self.0.borrow().clone()
+    }
+
+    pub fn set(&self, state: TextureState) {
+        self.0.replace(state);
+    }
+}
+
+/// The subset of the WebGL2 API used by the renderer.
+///
+/// Objects are referred to through opaque ids so that implementations which
+/// do not talk to a browser (such as the recording mock used in tests) can
+/// hand out their own handles.
+pub trait GlBackend {
+    // State
+    fn clear_color(&self, r: f32, g: f32, b: f32, a: f32);
+    fn clear(&self, mask: u32);
+    fn viewport(&self, x: i32, y: i32, width: i32, height: i32);
+    fn enable(&self, cap: u32);
+    fn disable(&self, cap: u32);
+    fn blend_func(&self, sfactor: u32, dfactor: u32);
+    fn cull_face(&self, mode: u32);
+    fn drawing_buffer_size(&self) -> (i32, i32);
+    fn get_error(&self) -> u32;
+
+    // Buffers and vertex arrays
+    fn create_buffer(&self) -> Option<BufferId>;
+    fn bind_buffer(&self, target: u32, buffer: Option<BufferId>);
+    fn buffer_data(&self, target: u32, data: ArrayData, usage: u32);
+    /// Allocates `size` bytes of uninitialized storage.
+    fn buffer_data_size(&self, target: u32, size: i32, usage: u32);
+    fn buffer_sub_data(&self, target: u32, offset: i32, data: ArrayData);
+    fn copy_buffer_sub_data(&self, read_target: u32, write_target: u32, read_offset: i32, write_offset: i32, size: i32);
+    fn delete_buffer(&self, buffer: BufferId);
+    fn bind_buffer_base(&self, target: u32, index: u32, buffer: Option<BufferId>);
+    fn create_vertex_array(&self) -> Option<VertexArrayId>;
+    fn delete_vertex_array(&self, vao: VertexArrayId);
+    fn bind_vertex_array(&self, vao: Option<VertexArrayId>);
+    fn vertex_attrib_pointer(&self, index: u32, size: i32, data_type: u32, normalized: bool, stride: i32, offset: i32);
+    fn enable_vertex_attrib_array(&self, index: u32);
+    fn disable_vertex_attrib_array(&self, index: u32);
+    fn draw_elements(&self, mode: u32, count: i32, data_type: u32, offset: i32);
+
+    // Shaders and programs
+    fn create_shader(&self, shader_type: u32) -> Option<ShaderId>;
+    fn shader_source(&self, shader: ShaderId, source: &str);
+    fn compile_shader(&self, shader: ShaderId);
+    fn shader_compile_status(&self, shader: ShaderId) -> bool;
+    fn shader_info_log(&self, shader: ShaderId) -> Option<String>;
+    fn delete_shader(&self, shader: ShaderId);
+    fn create_program(&self) -> Option<ProgramId>;
+    fn attach_shader(&self, program: ProgramId, shader: ShaderId);
+    fn bind_attrib_location(&self, program: ProgramId, index: u32, name: &str);
+    fn link_program(&self, program: ProgramId);
+    fn program_link_status(&self, program: ProgramId) -> bool;
+    fn program_info_log(&self, program: ProgramId) -> Option<String>;
+    fn use_program(&self, program: Option<ProgramId>);
+    fn delete_program(&self, program: ProgramId);
+    fn get_attrib_location(&self, program: ProgramId, name: &str) -> i32;
+    fn get_uniform_location(&self, program: ProgramId, name: &str) -> Option<UniformLocationId>;
+    fn active_attributes(&self, program: ProgramId) -> Vec<ActiveInfo>;
+    /// Uniforms outside of uniform blocks.
+    fn active_uniforms(&self, program: ProgramId) -> Vec<ActiveInfo>;
+    fn active_uniform_blocks(&self, program: ProgramId) -> Vec<String>;
+    fn uniform_block_binding(&self, program: ProgramId, block_index: u32, binding: u32);
+
+    // Uniforms
+    fn uniform1i(&self, location: Option<UniformLocationId>, value: i32);
+    fn uniform2i(&self, location: Option<UniformLocationId>, x: i32, y: i32);
+    fn uniform3i(&self, location: Option<UniformLocationId>, x: i32, y: i32, z: i32);
+    fn uniform4i(&self, location: Option<UniformLocationId>, x: i32, y: i32, z: i32, w: i32);
+    fn uniform1f(&self, location: Option<UniformLocationId>, value: f32);
+    fn uniform2f(&self, location: Option<UniformLocationId>, x: f32, y: f32);
+    fn uniform3f(&self, location: Option<UniformLocationId>, x: f32, y: f32, z: f32);
+    fn uniform4f(&self, location: Option<UniformLocationId>, x: f32, y: f32, z: f32, w: f32);
+    fn uniform_matrix3fv(&self, location: Option<UniformLocationId>, transpose: bool, data: &[f32]);
+    fn uniform_matrix4fv(&self, location: Option<UniformLocationId>, transpose: bool, data: &[f32]);
+
+    // Textures
+    fn create_texture(&self) -> Option<TextureId>;
+    fn delete_texture(&self, texture: TextureId);
+    fn active_texture(&self, unit: u32);
+    fn bind_texture(&self, target: u32, texture: Option<TextureId>);
+    fn tex_parameteri(&self, target: u32, pname: u32, param: i32);
+    fn tex_parameterf(&self, target: u32, pname: u32, param: f32);
+    /// Highest `TEXTURE_MAX_ANISOTROPY_EXT`, or 1 without
+    /// `EXT_texture_filter_anisotropic`.
+    fn max_anisotropy(&self) -> f32;
+    #[allow(clippy::too_many_arguments)]
+    fn tex_image_2d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: i32,
+        width: i32,
+        height: i32,
+        border: i32,
+        format: u32,
+        data_type: u32,
+        pixels: Option<ArrayData>
+    );
+    #[allow(clippy::too_many_arguments)]
+    fn tex_image_3d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: i32,
+        width: i32,
+        height: i32,
+        depth: i32,
+        border: i32,
+        format: u32,
+        data_type: u32,
+        pixels: Option<ArrayData>
+    );
+    #[allow(clippy::too_many_arguments)]
+    fn tex_sub_image_2d(
+        &self,
+        target: u32,
+        level: i32,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        format: u32,
+        data_type: u32,
+        pixels: ArrayData
+    );
+    fn pixel_storei(&self, pname: u32, param: i32);
+
+    /// Fetches the image at `src` and uploads it into level 0 of `texture`
+    /// as `upload` describes, once it has loaded. Browsers that have
+    /// `createImageBitmap` decode it off the main thread. The load is
+    /// cancelled when the texture is deleted.
+    fn load_image(&self, texture: TextureId, src: &str, upload: ImageUpload) -> Result<ImageLoad>;
+    /// Texture units the shader stages can read from altogether.
+    fn max_combined_texture_image_units(&self) -> u32;
+
+    // Samplers
+    fn create_sampler(&self) -> Option<SamplerId>;
+    fn delete_sampler(&self, sampler: SamplerId);
+    fn bind_sampler(&self, unit: u32, sampler: Option<SamplerId>);
+    fn sampler_parameteri(&self, sampler: SamplerId, pname: u32, param: i32);
+    fn sampler_parameterf(&self, sampler: SamplerId, pname: u32, param: f32);
+
+    // Resources
+    /// Tracks the objects owned through `Handle`s.
+    fn resources(&self) -> &Rc<Resources>;
+
+    /// Forgets every object after the context was lost: they are gone
+    /// along with it, and must not be deleted or used any more. Ids are
+    /// not reused, so stale ones never refer to new objects: calls with
+    /// them do nothing, and queries answer as for a deleted object.
+    fn reset(&self);
+
+    /// Deletes the objects whose last handle has been dropped.
+    fn delete_released(&self) {
+        for resource in self.resources().take_released() {
+            match resource {
+                Resource::Buffer(buffer) => self.delete_buffer(buffer),
+                Resource::Texture(texture) => self.delete_texture(texture),
+                Resource::Sampler(sampler) => self.delete_sampler(sampler),
+                Resource::Program(program) => self.delete_program(program),
+                Resource::VertexArray(vao) => self.delete_vertex_array(vao)
+            }
+        }
+    }
+}
@@ -0,0 +1,707 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use web_sys::WebGl2RenderingContext as GL;
+
+use super::context::*;
+use super::Resources;
+use crate::error::Result;
+
+
+/// A single call recorded by `MockBackend`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GlCall {
+    ClearColor(f32, f32, f32, f32),
+    Clear(u32),
+    Viewport(i32, i32, i32, i32),
+    Enable(u32),
+    Disable(u32),
+    BlendFunc(u32, u32),
+    CullFace(u32),
+    CreateBuffer(BufferId),
+    BindBuffer(u32, Option<BufferId>),
+    BufferData { target: u32, data_type: u32, len: usize, usage: u32 },
+    BufferDataSize { target: u32, size: i32, usage: u32 },
+    BufferSubData { target: u32, offset: i32, data_type: u32, len: usize },
+    CopyBufferSubData { read_target: u32, write_target: u32, read_offset: i32, write_offset: i32, size: i32 },
+    DeleteBuffer(BufferId),
+    DeleteVertexArray(VertexArrayId),
+    DeleteShader(ShaderId),
+    DeleteTexture(TextureId),
+    BindBufferBase(u32, u32, Option<BufferId>),
+    CreateVertexArray(VertexArrayId),
+    BindVertexArray(Option<VertexArrayId>),
+    VertexAttribPointer { index: u32, size: i32, data_type: u32, normalized: bool, stride: i32, offset: i32 },
+    EnableVertexAttribArray(u32),
+    DisableVertexAttribArray(u32),
+    DrawElements { mode: u32, count: i32, data_type: u32, offset: i32 },
+    CreateShader(ShaderId, u32),
+    ShaderSource(ShaderId),
+    CompileShader(ShaderId),
+    CreateProgram(ProgramId),
+    AttachShader(ProgramId, ShaderId),
+    BindAttribLocation(ProgramId, u32, String),
+    LinkProgram(ProgramId),
+    UseProgram(Option<ProgramId>),
+    DeleteProgram(ProgramId),
+    UniformBlockBinding(ProgramId, u32, u32),
+    Uniform1i(Option<UniformLocationId>, i32),
+    Uniform2i(Option<UniformLocationId>, i32, i32),
+    Uniform3i(Option<UniformLocationId>, i32, i32, i32),
+    Uniform4i(Option<UniformLocationId>, i32, i32, i32, i32),
+    Uniform1f(Option<UniformLocationId>, f32),
+    Uniform2f(Option<UniformLocationId>, f32, f32),
+    Uniform3f(Option<UniformLocationId>, f32, f32, f32),
+    Uniform4f(Option<UniformLocationId>, f32, f32, f32, f32),
+    UniformMatrix3fv(Option<UniformLocationId>, Vec<f32>),
+    UniformMatrix4fv(Option<UniformLocationId>, Vec<f32>),
+    CreateTexture(TextureId),
+    ActiveTexture(u32),
+    BindTexture(u32, Option<TextureId>),
+    TexParameteri(u32, u32, i32),
+    /// `pixels` holds the bytes of the data, if any.
+    TexImage2D { target: u32, level: i32, internal_format: i32, width: i32, height: i32, format: u32, data_type: u32, pixels: Option<Vec<u8>> },
+    TexImage3D { target: u32, level: i32, internal_format: i32, width: i32, height: i32, depth: i32, format: u32, data_type: u32, pixels: Option<Vec<u8>> },
+    TexParameterf(u32, u32, f32),
+    TexSubImage2D { target: u32, x: i32, y: i32, width: i32, height: i32, format: u32, data_type: u32, len: usize },
+    PixelStorei(u32, i32),
+    CreateSampler(SamplerId),
+    DeleteSampler(SamplerId),
+    BindSampler(u32, Option<SamplerId>),
+    SamplerParameteri(SamplerId, u32, i32),
+    SamplerParameterf(SamplerId, u32, f32),
+    LoadImage(TextureId, String, ImageUpload)
+}
+
+
+/// `GlBackend` that records every call instead of drawing anything.
+///
+/// Attribute and uniform locations are resolved by scanning the declarations
+/// in the shader sources attached to a program, so lookups of names that a
+/// shader does not declare fail the same way they would in a browser.
+/// Shaders containing an `#error` directive fail to compile, and programs
+/// whose fragment inputs are not written by the vertex shader fail to link.
+pub struct MockBackend {
+    calls: RefCell<Vec<GlCall>>,
+    next_id: Cell<u32>,
+    drawing_buffer_size: Cell<(i32, i32)>,
+    error: Cell<u32>,
+    max_anisotropy: Cell<f32>,
+    max_texture_units: Cell<u32>,
+    shader_sources: RefCell<HashMap<ShaderId, (u32, String)>>,
+    shader_logs: RefCell<HashMap<ShaderId, String>>,
+    program_shaders: RefCell<HashMap<ProgramId, Vec<ShaderId>>>,
+    program_logs: RefCell<HashMap<ProgramId, String>>,
+    attributes: RefCell<HashMap<ProgramId, Vec<ActiveInfo>>>,
+    attribute_bindings: RefCell<HashMap<ProgramId, HashMap<String, u32>>>,
+    uniforms: RefCell<HashMap<ProgramId, Vec<ActiveInfo>>>,
+    uniform_blocks: RefCell<HashMap<ProgramId, Vec<String>>>,
+    /// Name and program of each location handed out.
+    uniform_locations: RefCell<HashMap<UniformLocationId, (ProgramId, String)>>,
+    images: RefCell<HashMap<TextureId, ImageLoad>>,
+    resources: Rc<Resources>
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        MockBackend {
+            calls: RefCell::new(Vec::new()),
+            next_id: Cell::new(1),
+            drawing_buffer_size: Cell::new((1024, 1024)),
+            error: Cell::new(GL::NO_ERROR),
+            max_anisotropy: Cell::new(16.0),
+            max_texture_units: Cell::new(32),
+            shader_sources: RefCell::new(HashMap::new()),
+            shader_logs: RefCell::new(HashMap::new()),
+            program_shaders: RefCell::new(HashMap::new()),
+            program_logs: RefCell::new(HashMap::new()),
+            attributes: RefCell::new(HashMap::new()),
+            attribute_bindings: RefCell::new(HashMap::new()),
+            uniforms: RefCell::new(HashMap::new()),
+            uniform_blocks: RefCell::new(HashMap::new()),
+            uniform_locations: RefCell::new(HashMap::new()),
+            images: RefCell::new(HashMap::new()),
+            resources: Rc::new(Resources::default())
+        }
+    }
+
+    pub fn calls(&self) -> Vec<GlCall> {
+        self.calls.borrow().clone()
+    }
+
+    pub fn clear_calls(&self) {
+        self.calls.borrow_mut().clear();
+    }
+
+    /// Simulates the canvas backing store being resized.
+    pub fn set_drawing_buffer_size(&self, width: i32, height: i32) {
+        self.drawing_buffer_size.set((width, height));
+    }
+
+    /// Makes the next `get_error` call report `error`.
+    pub fn set_error(&self, error: u32) {
+        self.error.set(error);
+    }
+
+    /// Simulates a GPU with a different `EXT_texture_filter_anisotropic`
+    /// limit, 1 standing for no extension.
+    pub fn set_max_anisotropy(&self, max: f32) {
+        self.max_anisotropy.set(max);
+    }
+
+    /// Simulates a GPU with fewer or more texture units.
+    pub fn set_max_texture_units(&self, units: u32) {
+        self.max_texture_units.set(units);
+    }
+
+    /// Completes the image load of `texture`, as its `onload` or `onerror`
+    /// callback would.
+    pub fn finish_image(&self, texture: TextureId, state: TextureState) {
+        if let Some(load) = self.images.borrow().get(&texture) {
+            load.set(state);
+        }
+    }
+
+    /// Name of the uniform a location returned by this backend refers to.
+    pub fn uniform_name(&self, location: UniformLocationId) -> Option<String> {
+        self.uniform_locations.borrow().get(&location).map(|(_, name)| name.clone())
+    }
+
+    fn record(&self, call: GlCall) {
+        self.calls.borrow_mut().push(call);
+    }
+
+    fn next_id(&self) -> u32 {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        id
+    }
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// The data as little-endian bytes.
+fn bytes(data: ArrayData) -> Vec<u8> {
+    match data {
+        ArrayData::U8(data) => data.to_vec(),
+        ArrayData::I16(data) => data.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        ArrayData::U16(data) => data.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        ArrayData::U32(data) => data.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        ArrayData::F32(data) => data.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+}
+
+/// GL data type and element count of an upload.
+fn describe(data: ArrayData) -> (u32, usize) {
+    match data {
+        ArrayData::U8(data) => (GL::UNSIGNED_BYTE, data.len()),
+        ArrayData::I16(data) => (GL::SHORT, data.len()),
+        ArrayData::U16(data) => (GL::UNSIGNED_SHORT, data.len()),
+        ArrayData::U32(data) => (GL::UNSIGNED_INT, data.len()),
+        ArrayData::F32(data) => (GL::FLOAT, data.len())
+    }
+}
+
+
+/// Variable declared by a GLSL statement such as `in vec3 a_normal;` or
+/// `uniform highp float u_values[4];`.
+fn declared(tokens: &[&str]) -> Option<ActiveInfo> {
+    let declaration = tokens.last()?.trim_end_matches(';');
+    let mut parts = declaration.splitn(2, '[');
+    let name = parts.next().filter(|name| !name.is_empty() && *name != "{")?;
+    let size = parts.next()
+        .and_then(|size| size.trim_end_matches(']').parse().ok())
+        .unwrap_or(1);
+    let glsl_type = tokens.len().checked_sub(2).map(|i| tokens[i])?;
+    Some(ActiveInfo { name: name.to_string(), gl_type: gl_type(glsl_type), size })
+}
+
+/// GL enum of a GLSL type name, 0 for the ones the mock does not know.
+fn gl_type(glsl_type: &str) -> u32 {
+    match glsl_type {
+        "float" => GL::FLOAT,
+        "vec2" => GL::FLOAT_VEC2,
+        "vec3" => GL::FLOAT_VEC3,
+        "vec4" => GL::FLOAT_VEC4,
+        "int" => GL::INT,
+        "ivec2" => GL::INT_VEC2,
+        "ivec3" => GL::INT_VEC3,
+        "ivec4" => GL::INT_VEC4,
+        "uint" => GL::UNSIGNED_INT,
+        "bool" => GL::BOOL,
+        "mat3" => GL::FLOAT_MAT3,
+        "mat4" => GL::FLOAT_MAT4,
+        "sampler2D" => GL::SAMPLER_2D,
+        "samplerCube" => GL::SAMPLER_CUBE,
+        "sampler2DArray" => GL::SAMPLER_2D_ARRAY,
+        "sampler3D" => GL::SAMPLER_3D,
+        "usampler2D" => GL::UNSIGNED_INT_SAMPLER_2D,
+        _ => 0
+    }
+}
+
+/// Lines of `source` left once `#define`, `#ifdef`, `#ifndef`, `#else` and
+/// `#endif` are applied. Other conditionals are taken as true.
+fn active_lines(source: &str) -> Vec<&str> {
+    let mut defines = HashSet::new();
+    // Whether the enclosing block and the current branch are active.
+    let mut stack: Vec<(bool, bool)> = Vec::new();
+    let mut lines = Vec::new();
+    for line in source.lines() {
+        let active = stack.last().is_none_or(|(outer, branch)| *outer && *branch);
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("#define") if active => {
+                defines.extend(tokens.next().map(|name| name.to_string()));
+            },
+            Some("#ifdef") => stack.push((active, tokens.next().is_some_and(|name| defines.contains(name)))),
+            Some("#ifndef") => stack.push((active, !tokens.next().is_some_and(|name| defines.contains(name)))),
+            Some("#if") => stack.push((active, true)),
+            Some("#else") => {
+                if let Some(top) = stack.last_mut() {
+                    top.1 = !top.1;
+                }
+            },
+            Some("#endif") => {
+                stack.pop();
+            },
+            _ if active => lines.push(line),
+            _ => {}
+        }
+    }
+    lines
+}
+
+
+impl GlBackend for MockBackend {
+    fn clear_color(&self, r: f32, g: f32, b: f32, a: f32) {
+        self.record(GlCall::ClearColor(r, g, b, a));
+    }
+
+    fn clear(&self, mask: u32) {
+        self.record(GlCall::Clear(mask));
+    }
+
+    fn viewport(&self, x: i32, y: i32, width: i32, height: i32) {
+        self.record(GlCall::Viewport(x, y, width, height));
+    }
+
+    fn enable(&self, cap: u32) {
+        self.record(GlCall::Enable(cap));
+    }
+
+    fn disable(&self, cap: u32) {
+        self.record(GlCall::Disable(cap));
+    }
+
+    fn blend_func(&self, sfactor: u32, dfactor: u32) {
+        self.record(GlCall::BlendFunc(sfactor, dfactor));
+    }
+
+    fn cull_face(&self, mode: u32) {
+        self.record(GlCall::CullFace(mode));
+    }
+
+    fn drawing_buffer_size(&self) -> (i32, i32) {
+        self.drawing_buffer_size.get()
+    }
+
+    fn get_error(&self) -> u32 {
+        self.error.replace(GL::NO_ERROR)
+    }
+
+    fn create_buffer(&self) -> Option<BufferId> {
+        let buffer = BufferId(self.next_id());
+        self.record(GlCall::CreateBuffer(buffer));
+        Some(buffer)
+    }
+
+    fn bind_buffer(&self, target: u32, buffer: Option<BufferId>) {
+        self.record(GlCall::BindBuffer(target, buffer));
+    }
+
+    fn buffer_data(&self, target: u32, data: ArrayData, usage: u32) {
+        let (data_type, len) = describe(data);
+        self.record(GlCall::BufferData { target, data_type, len, usage });
+    }
+
+    fn buffer_data_size(&self, target: u32, size: i32, usage: u32) {
+        self.record(GlCall::BufferDataSize { target, size, usage });
+    }
+
+    fn buffer_sub_data(&self, target: u32, offset: i32, data: ArrayData) {
+        let (data_type, len) = describe(data);
+        self.record(GlCall::BufferSubData { target, offset, data_type, len });
+    }
+
+    fn copy_buffer_sub_data(&self, read_target: u32, write_target: u32, read_offset: i32, write_offset: i32, size: i32) {
+        self.record(GlCall::CopyBufferSubData { read_target, write_target, read_offset, write_offset, size });
+    }
+
+    fn delete_buffer(&self, buffer: BufferId) {
+        self.record(GlCall::DeleteBuffer(buffer));
+    }
+
+    fn bind_buffer_base(&self, target: u32, index: u32, buffer: Option<BufferId>) {
+        self.record(GlCall::BindBufferBase(target, index, buffer));
+    }
+
+    fn create_vertex_array(&self) -> Option<VertexArrayId> {
+        let vao = VertexArrayId(self.next_id());
+        self.record(GlCall::CreateVertexArray(vao));
+        Some(vao)
+    }
+
+    fn delete_vertex_array(&self, vao: VertexArrayId) {
+        self.record(GlCall::DeleteVertexArray(vao));
+    }
+
+    fn bind_vertex_array(&self, vao: Option<VertexArrayId>) {
+        self.record(GlCall::BindVertexArray(vao));
+    }
+
+    fn vertex_attrib_pointer(&self, index: u32, size: i32, data_type: u32, normalized: bool, stride: i32, offset: i32) {
+        self.record(GlCall::VertexAttribPointer { index, size, data_type, normalized, stride, offset });
+    }
+
+    fn enable_vertex_attrib_array(&self, index: u32) {
+        self.record(GlCall::EnableVertexAttribArray(index));
+    }
+
+    fn disable_vertex_attrib_array(&self, index: u32) {
+        self.record(GlCall::DisableVertexAttribArray(index));
+    }
+
+    fn draw_elements(&self, mode: u32, count: i32, data_type: u32, offset: i32) {
+        self.record(GlCall::DrawElements { mode, count, data_type, offset });
+    }
+
+    fn create_shader(&self, shader_type: u32) -> Option<ShaderId> {
+        let shader = ShaderId(self.next_id());
+        self.shader_sources.borrow_mut().insert(shader, (shader_type, String::new()));
+        self.record(GlCall::CreateShader(shader, shader_type));
+        Some(shader)
+    }
+
+    fn shader_source(&self, shader: ShaderId, source: &str) {
+        if let Some(entry) = self.shader_sources.borrow_mut().get_mut(&shader) {
+            entry.1 = source.to_string();
+        }
+        self.record(GlCall::ShaderSource(shader));
+    }
+
+    fn compile_shader(&self, shader: ShaderId) {
+        let sources = self.shader_sources.borrow();
+        let errors: Vec<String> = sources[&shader].1.lines()
+            .enumerate()
+            .filter_map(|(i, line)| line.trim().strip_prefix("#error")
+                .map(|message| format!("ERROR: 0:{}: '#error' : {}", i + 1, message.trim())))
+            .collect();
+        self.shader_logs.borrow_mut().insert(shader, errors.join("\n"));
+        self.record(GlCall::CompileShader(shader));
+    }
+
+    fn shader_compile_status(&self, shader: ShaderId) -> bool {
+        self.shader_logs.borrow().get(&shader).is_none_or(|log| log.is_empty())
+    }
+
+    fn shader_info_log(&self, shader: ShaderId) -> Option<String> {
+        self.shader_logs.borrow().get(&shader).cloned()
+    }
+
+    fn delete_shader(&self, shader: ShaderId) {
+        self.record(GlCall::DeleteShader(shader));
+    }
+
+    fn create_program(&self) -> Option<ProgramId> {
+        let program = ProgramId(self.next_id());
+        self.record(GlCall::CreateProgram(program));
+        Some(program)
+    }
+
+    fn attach_shader(&self, program: ProgramId, shader: ShaderId) {
+        self.program_shaders.borrow_mut().entry(program).or_default().push(shader);
+        self.record(GlCall::AttachShader(program, shader));
+    }
+
+    fn bind_attrib_location(&self, program: ProgramId, index: u32, name: &str) {
+        self.attribute_bindings.borrow_mut().entry(program).or_default().insert(name.to_string(), index);
+        self.record(GlCall::BindAttribLocation(program, index, name.to_string()));
+    }
+
+    fn link_program(&self, program: ProgramId) {
+        let mut attributes = Vec::new();
+        let mut uniforms = Vec::new();
+        let mut blocks = Vec::new();
+        let mut varyings_out = Vec::new();
+        let mut varyings_in = Vec::new();
+        let sources = self.shader_sources.borrow();
+        for shader in self.program_shaders.borrow().get(&program).into_iter().flatten() {
+            let (shader_type, source) = &sources[shader];
+            for line in active_lines(source) {
+                let tokens: Vec<&str> = line.split_whitespace()
+                    .skip_while(|token| token.starts_with("layout"))
+                    .collect();
+                match tokens.first() {
+                    Some(&"uniform") if tokens.last() == Some(&"{") => {
+                        blocks.extend(tokens.get(1).map(|name| name.to_string()));
+                    },
+                    Some(&"in") if *shader_type == GL::VERTEX_SHADER => {
+                        attributes.extend(declared(&tokens));
+                    },
+                    Some(&"in") => {
+                        varyings_in.extend(declared(&tokens).map(|v| v.name));
+                    },
+                    Some(&"out") if *shader_type == GL::VERTEX_SHADER => {
+                        varyings_out.extend(declared(&tokens).map(|v| v.name));
+                    },
+                    Some(&"uniform") => {
+                        uniforms.extend(declared(&tokens));
+                    },
+                    _ => {}
+                }
+            }
+        }
+        let log: Vec<String> = varyings_in.iter()
+            .filter(|name| !varyings_out.contains(name))
+            .map(|name| format!("ERROR: Varying '{}' is not written by the vertex shader", name))
+            .collect();
+        self.program_logs.borrow_mut().insert(program, log.join("\n"));
+        self.attributes.borrow_mut().insert(program, attributes);
+        self.uniforms.borrow_mut().insert(program, uniforms);
+        self.uniform_blocks.borrow_mut().insert(program, blocks);
+        self.record(GlCall::LinkProgram(program));
+    }
+
+    fn program_link_status(&self, program: ProgramId) -> bool {
+        self.program_logs.borrow().get(&program).is_none_or(|log| log.is_empty())
+    }
+
+    fn program_info_log(&self, program: ProgramId) -> Option<String> {
+        self.program_logs.borrow().get(&program).cloned()
+    }
+
+    fn use_program(&self, program: Option<ProgramId>) {
+        self.record(GlCall::UseProgram(program));
+    }
+
+    fn delete_program(&self, program: ProgramId) {
+        self.record(GlCall::DeleteProgram(program));
+        self.uniform_locations.borrow_mut().retain(|_, (p, _)| *p != program);
+        self.resources.remove_uniform_locations(program);
+    }
+
+    /// Declaration order, unless the location was bound before linking.
+    fn get_attrib_location(&self, program: ProgramId, name: &str) -> i32 {
+        let index = self.attributes.borrow()
+            .get(&program)
+            .and_then(|attributes| attributes.iter().position(|a| a.name == name));
+        let bound = self.attribute_bindings.borrow()
+            .get(&program)
+            .and_then(|bindings| bindings.get(name).copied());
+        match (index, bound) {
+            (Some(_), Some(location)) => location as i32,
+            (Some(index), None) => index as i32,
+            (None, _) => -1
+        }
+    }
+
+    fn get_uniform_location(&self, program: ProgramId, name: &str) -> Option<UniformLocationId> {
+        let declared = self.uniforms.borrow()
+            .get(&program)
+            .is_some_and(|uniforms| uniforms.iter().any(|u| u.name == name));
+        if !declared {
+            return None;
+        }
+        let location = UniformLocationId(self.next_id());
+        self.uniform_locations.borrow_mut().insert(location, (program, name.to_string()));
+        self.resources.add_uniform_location(program);
+        Some(location)
+    }
+
+    fn active_attributes(&self, program: ProgramId) -> Vec<ActiveInfo> {
+        self.attributes.borrow().get(&program).cloned().unwrap_or_default()
+    }
+
+    fn active_uniforms(&self, program: ProgramId) -> Vec<ActiveInfo> {
+        self.uniforms.borrow().get(&program).cloned().unwrap_or_default()
+    }
+
+    fn active_uniform_blocks(&self, program: ProgramId) -> Vec<String> {
+        self.uniform_blocks.borrow().get(&program).cloned().unwrap_or_default()
+    }
+
+    fn uniform_block_binding(&self, program: ProgramId, block_index: u32, binding: u32) {
+        self.record(GlCall::UniformBlockBinding(program, block_index, binding));
+    }
+
+    fn uniform1i(&self, location: Option<UniformLocationId>, value: i32) {
+        self.record(GlCall::Uniform1i(location, value));
+    }
+
+    fn uniform2i(&self, location: Option<UniformLocationId>, x: i32, y: i32) {
+        self.record(GlCall::Uniform2i(location, x, y));
+    }
+
+    fn uniform3i(&self, location: Option<UniformLocationId>, x: i32, y: i32, z: i32) {
+        self.record(GlCall::Uniform3i(location, x, y, z));
+    }
+
+    fn uniform4i(&self, location: Option<UniformLocationId>, x: i32, y: i32, z: i32, w: i32) {
+        self.record(GlCall::Uniform4i(location, x, y, z, w));
+    }
+
+    fn uniform1f(&self, location: Option<UniformLocationId>, value: f32) {
+        self.record(GlCall::Uniform1f(location, value));
+    }
+
+    fn uniform2f(&self, location: Option<UniformLocationId>, x: f32, y: f32) {
+        self.record(GlCall::Uniform2f(location, x, y));
+    }
+
+    fn uniform3f(&self, location: Option<UniformLocationId>, x: f32, y: f32, z: f32) {
+        self.record(GlCall::Uniform3f(location, x, y, z));
+    }
+
+    fn uniform4f(&self, location: Option<UniformLocationId>, x: f32, y: f32, z: f32, w: f32) {
+        self.record(GlCall::Uniform4f(location, x, y, z, w));
+    }
+
+    fn uniform_matrix3fv(&self, location: Option<UniformLocationId>, _transpose: bool, data: &[f32]) {
+        self.record(GlCall::UniformMatrix3fv(location, data.to_vec()));
+    }
+
+    fn uniform_matrix4fv(&self, location: Option<UniformLocationId>, _transpose: bool, data: &[f32]) {
+        self.record(GlCall::UniformMatrix4fv(location, data.to_vec()));
+    }
+
+    fn create_texture(&self) -> Option<TextureId> {
+        let texture = TextureId(self.next_id());
+        self.record(GlCall::CreateTexture(texture));
+        Some(texture)
+    }
+
+    fn delete_texture(&self, texture: TextureId) {
+        self.record(GlCall::DeleteTexture(texture));
+    }
+
+    fn active_texture(&self, unit: u32) {
+        self.record(GlCall::ActiveTexture(unit));
+    }
+
+    fn bind_texture(&self, target: u32, texture: Option<TextureId>) {
+        self.record(GlCall::BindTexture(target, texture));
+    }
+
+    fn tex_parameteri(&self, target: u32, pname: u32, param: i32) {
+        self.record(GlCall::TexParameteri(target, pname, param));
+    }
+
+    fn tex_parameterf(&self, target: u32, pname: u32, param: f32) {
+        self.record(GlCall::TexParameterf(target, pname, param));
+    }
+
+    fn max_anisotropy(&self) -> f32 {
+        self.max_anisotropy.get()
+    }
+
+    fn tex_image_2d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: i32,
+        width: i32,
+        height: i32,
+        _border: i32,
+        format: u32,
+        data_type: u32,
+        pixels: Option<ArrayData>
+    ) {
+        let pixels = pixels.map(bytes);
+        self.record(GlCall::TexImage2D { target, level, internal_format, width, height, format, data_type, pixels });
+    }
+
+    fn tex_image_3d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: i32,
+        width: i32,
+        height: i32,
+        depth: i32,
+        _border: i32,
+        format: u32,
+        data_type: u32,
+        pixels: Option<ArrayData>
+    ) {
+        let pixels = pixels.map(bytes);
+        self.record(GlCall::TexImage3D { target, level, internal_format, width, height, depth, format, data_type, pixels });
+    }
+
+    fn tex_sub_image_2d(
+        &self,
+        target: u32,
+        _level: i32,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        format: u32,
+        data_type: u32,
+        pixels: ArrayData
+    ) {
+        let (_, len) = describe(pixels);
+        self.record(GlCall::TexSubImage2D { target, x, y, width, height, format, data_type, len });
+    }
+
+    fn pixel_storei(&self, pname: u32, param: i32) {
+        self.record(GlCall::PixelStorei(pname, param));
+    }
+
+    fn load_image(&self, texture: TextureId, src: &str, upload: ImageUpload) -> Result<ImageLoad> {
+        self.record(GlCall::LoadImage(texture, src.to_string(), upload));
+        let load = ImageLoad::new();
+        self.images.borrow_mut().insert(texture, load.clone());
+        Ok(load)
+    }
+
+    fn max_combined_texture_image_units(&self) -> u32 {
+        self.max_texture_units.get()
+    }
+
+    fn create_sampler(&self) -> Option<SamplerId> {
+        let sampler = SamplerId(self.next_id());
+        self.record(GlCall::CreateSampler(sampler));
+        Some(sampler)
+    }
+
+    fn delete_sampler(&self, sampler: SamplerId) {
+        self.record(GlCall::DeleteSampler(sampler));
+    }
+
+    fn bind_sampler(&self, unit: u32, sampler: Option<SamplerId>) {
+        self.record(GlCall::BindSampler(unit, sampler));
+    }
+
+    fn sampler_parameteri(&self, sampler: SamplerId, pname: u32, param: i32) {
+        self.record(GlCall::SamplerParameteri(sampler, pname, param));
+    }
+
+    fn sampler_parameterf(&self, sampler: SamplerId, pname: u32, param: f32) {
+        self.record(GlCall::SamplerParameterf(sampler, pname, param));
+    }
+
+    fn resources(&self) -> &Rc<Resources> {
+        &self.resources
+    }
+
+    fn reset(&self) {
+        self.uniform_locations.borrow_mut().clear();
+        self.resources.clear();
+    }
+}
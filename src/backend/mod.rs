@@ -0,0 +1,11 @@
+mod context;
+#[cfg(test)]
+mod mock;
+mod resources;
+mod webgl;
+
+pub(in crate) use self::context::*;
+#[cfg(test)]
+pub(in crate) use self::mock::*;
+pub(in crate) use self::resources::*;
+pub(in crate) use self::webgl::*;
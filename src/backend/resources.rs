@@ -0,0 +1,246 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use serde::Serialize;
+
+use super::context::*;
+
+
+/// A GPU object of any kind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Resource {
+    Buffer(BufferId),
+    Texture(TextureId),
+    Sampler(SamplerId),
+    Program(ProgramId),
+    VertexArray(VertexArrayId)
+}
+
+/// Ids of objects whose lifetime is managed through `Handle`.
+pub trait GlObject: Copy + Eq + fmt::Debug {
+    fn resource(self) -> Resource;
+}
+
+impl GlObject for BufferId {
+    fn resource(self) -> Resource {
+        Resource::Buffer(self)
+    }
+}
+
+impl GlObject for TextureId {
+    fn resource(self) -> Resource {
+        Resource::Texture(self)
+    }
+}
+
+impl GlObject for SamplerId {
+    fn resource(self) -> Resource {
+        Resource::Sampler(self)
+    }
+}
+
+impl GlObject for ProgramId {
+    fn resource(self) -> Resource {
+        Resource::Program(self)
+    }
+}
+
+impl GlObject for VertexArrayId {
+    fn resource(self) -> Resource {
+        Resource::VertexArray(self)
+    }
+}
+
+
+/// Live GPU objects, to spot leaks when scenes are swapped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceStats {
+    pub buffers: usize,
+    pub textures: usize,
+    pub samplers: usize,
+    pub programs: usize,
+    pub vertex_arrays: usize,
+    /// Uniform locations looked up in live programs. They go with their
+    /// program.
+    pub uniform_locations: usize,
+    /// Buffer storage and texture images, as far as their size is known.
+    pub bytes: usize
+}
+
+
+/// Keeps count of the objects handed out as `Handle`s, and collects those
+/// whose last handle was dropped until the backend deletes them.
+#[derive(Debug, Default)]
+pub struct Resources {
+    /// Live objects and the bytes they hold.
+    live: RefCell<HashMap<Resource, usize>>,
+    released: RefCell<Vec<Resource>>,
+    /// Uniform locations handed out per program, until it is deleted.
+    uniform_locations: RefCell<HashMap<ProgramId, usize>>
+}
+
+impl Resources {
+    pub fn track<T: GlObject>(self: &Rc<Self>, id: T) -> Handle<T> {
+        self.live.borrow_mut().insert(id.resource(), 0);
+        Handle(Rc::new(Tracked { id, resources: self.clone() }))
+    }
+
+    /// Records the memory held by a live object.
+    pub fn set_size(&self, resource: Resource, bytes: usize) {
+        if let Some(size) = self.live.borrow_mut().get_mut(&resource) {
+            *size = bytes;
+        }
+    }
+
+    /// Counts a uniform location the backend looked up in `program`.
+    pub fn add_uniform_location(&self, program: ProgramId) {
+        *self.uniform_locations.borrow_mut().entry(program).or_insert(0) += 1;
+    }
+
+    /// Forgets the uniform locations of `program`, once the backend has
+    /// deleted it.
+    pub fn remove_uniform_locations(&self, program: ProgramId) {
+        self.uniform_locations.borrow_mut().remove(&program);
+    }
+
+    pub fn stats(&self) -> ResourceStats {
+        let mut stats = ResourceStats::default();
+        for (resource, bytes) in self.live.borrow().iter() {
+            match resource {
+                Resource::Buffer(_) => stats.buffers += 1,
+                Resource::Texture(_) => stats.textures += 1,
+                Resource::Sampler(_) => stats.samplers += 1,
+                Resource::Program(_) => stats.programs += 1,
+                Resource::VertexArray(_) => stats.vertex_arrays += 1
+            }
+            stats.bytes += bytes;
+        }
+        stats.uniform_locations = self.uniform_locations.borrow().values().sum();
+        stats
+    }
+
+    /// Objects released since the last call.
+    pub fn take_released(&self) -> Vec<Resource> {
+        self.released.take()
+    }
+
+    /// Whether `resource` has been tracked and not released since, nor
+    /// lost with the context.
+    pub fn is_live(&self, resource: Resource) -> bool {
+        self.live.borrow().contains_key(&resource)
+    }
+
+    /// Forgets every object, after the context that held them was lost.
+    /// Handles still around from before are dropped without deleting
+    /// anything.
+    pub fn clear(&self) {
+        self.live.borrow_mut().clear();
+        self.released.borrow_mut().clear();
+        self.uniform_locations.borrow_mut().clear();
+    }
+
+    fn release(&self, resource: Resource) {
+        if self.live.borrow_mut().remove(&resource).is_some() {
+            self.released.borrow_mut().push(resource);
+        }
+    }
+}
+
+
+/// Shared ownership of a GPU object. When the last clone is dropped the
+/// object is released, and deleted by the next
+/// `GlBackend::delete_released`.
+#[derive(Clone)]
+pub struct Handle<T: GlObject>(Rc<Tracked<T>>);
+
+struct Tracked<T: GlObject> {
+    id: T,
+    resources: Rc<Resources>
+}
+
+impl<T: GlObject> Drop for Tracked<T> {
+    fn drop(&mut self) {
+        self.resources.release(self.id.resource());
+    }
+}
+
+impl<T: GlObject> Handle<T> {
+    pub fn id(&self) -> T {
+        self.0.id
+    }
+}
+
+impl<T: GlObject> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl<T: GlObject> Eq for Handle<T> {}
+
+impl<T: GlObject> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Handle").field(&self.id()).finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn objects_are_released_with_their_last_handle() {
+        let resources = Rc::new(Resources::default());
+        let buffer = resources.track(BufferId(1));
+        let texture = resources.track(TextureId(2));
+        resources.set_size(Resource::Buffer(BufferId(1)), 64);
+        resources.set_size(Resource::Texture(TextureId(2)), 16);
+
+        assert_eq!(resources.stats(), ResourceStats { buffers: 1, textures: 1, bytes: 80, ..Default::default() });
+
+        let shared = buffer.clone();
+        drop(buffer);
+        assert!(resources.take_released().is_empty());
+        drop(shared);
+        drop(texture);
+
+        assert_eq!(resources.stats(), ResourceStats::default());
+        assert_eq!(
+            resources.take_released(),
+            vec![Resource::Buffer(BufferId(1)), Resource::Texture(TextureId(2))]
+        );
+        assert!(resources.take_released().is_empty());
+    }
+
+    #[test]
+    fn uniform_locations_go_with_their_program() {
+        let resources = Rc::new(Resources::default());
+        resources.add_uniform_location(ProgramId(1));
+        resources.add_uniform_location(ProgramId(1));
+        resources.add_uniform_location(ProgramId(2));
+        assert_eq!(resources.stats().uniform_locations, 3);
+
+        resources.remove_uniform_locations(ProgramId(1));
+        assert_eq!(resources.stats().uniform_locations, 1);
+
+        resources.clear();
+        assert_eq!(resources.stats(), ResourceStats::default());
+    }
+
+    #[test]
+    fn handles_from_a_lost_context_are_dropped_silently() {
+        let resources = Rc::new(Resources::default());
+        let stale = resources.track(ProgramId(1));
+        let released = resources.track(BufferId(2));
+        drop(released);
+
+        resources.clear();
+        drop(stale);
+
+        assert_eq!(resources.stats(), ResourceStats::default());
+        assert!(resources.take_released().is_empty());
+    }
+}
@@ -0,0 +1,743 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::rc::Rc;
+use js_sys::{Object, Reflect};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::WebGl2RenderingContext as GL;
+use web_sys::*;
+
+use super::context::*;
+use super::{Resource, Resources};
+use crate::error::{Error, Result};
+use crate::render::CreateArray;
+
+
+struct Handles<T> {
+    next_id: Cell<u32>,
+    objects: RefCell<HashMap<u32, T>>
+}
+
+impl<T: Clone> Handles<T> {
+    fn new() -> Self {
+        Handles { next_id: Cell::new(1), objects: RefCell::new(HashMap::new()) }
+    }
+
+    fn insert(&self, object: T) -> u32 {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.objects.borrow_mut().insert(id, object);
+        id
+    }
+
+    fn get(&self, id: u32) -> Option<T> {
+        self.objects.borrow().get(&id).cloned()
+    }
+
+    fn remove(&self, id: u32) -> Option<T> {
+        self.objects.borrow_mut().remove(&id)
+    }
+
+    fn retain(&self, keep: impl Fn(&T) -> bool) {
+        self.objects.borrow_mut().retain(|_, object| keep(object));
+    }
+
+    fn clear(&self) {
+        self.objects.borrow_mut().clear();
+    }
+}
+
+
+/// An image being fetched into a texture. Dropping it cancels the upload.
+enum ImageRequest {
+    /// Fetched and decoded off the main thread by `createImageBitmap`.
+    Bitmap { cancelled: Rc<Cell<bool>> },
+    /// Decoded by an image element, in browsers without `createImageBitmap`.
+    Element {
+        image: HtmlImageElement,
+        _onload: Closure<dyn Fn()>,
+        _onerror: Closure<dyn Fn()>
+    }
+}
+
+impl Drop for ImageRequest {
+    fn drop(&mut self) {
+        match self {
+            ImageRequest::Bitmap { cancelled } => cancelled.set(true),
+            ImageRequest::Element { image, .. } => {
+                image.set_onload(None);
+                image.set_onerror(None);
+            }
+        }
+    }
+}
+
+
+fn active_info(info: &WebGlActiveInfo) -> ActiveInfo {
+    let name = info.name();
+    ActiveInfo {
+        name: name.strip_suffix("[0]").unwrap_or(&name).to_string(),
+        gl_type: info.type_(),
+        size: info.size()
+    }
+}
+
+async fn fetch_image_bitmap(window: &Window, src: &str, options: &ImageBitmapOptions) -> std::result::Result<ImageBitmap, String> {
+    let response: Response = JsFuture::from(window.fetch_with_str(src)).await
+        .map_err(|e| format!("{:?}", e))?
+        .unchecked_into();
+    if !response.ok() {
+        return Err(format!("the image could not be fetched: {} {}", response.status(), response.status_text()));
+    }
+    let blob = response.blob().map_err(|e| format!("{:?}", e))?;
+    let blob: Blob = JsFuture::from(blob).await
+        .map_err(|e| format!("{:?}", e))?
+        .unchecked_into();
+    let bitmap = window.create_image_bitmap_with_blob_and_image_bitmap_options(&blob, options)
+        .map_err(|e| format!("{:?}", e))?;
+    let bitmap = JsFuture::from(bitmap).await
+        .map_err(|_| "the image could not be decoded".to_string())?;
+    Ok(bitmap.unchecked_into())
+}
+
+/// Completes the upload of a `width` x `height` image bound to
+/// `TEXTURE_2D`, generating its mipmaps if asked to.
+#[allow(clippy::too_many_arguments)]
+fn finish_upload(
+    gl: &GL,
+    resources: &Resources,
+    texture: TextureId,
+    upload: ImageUpload,
+    load: &ImageLoad,
+    result: std::result::Result<(), JsValue>,
+    width: u32,
+    height: u32
+) {
+    if let Err(e) = result {
+        gl.bind_texture(GL::TEXTURE_2D, None);
+        load.set(TextureState::Failed(format!("{:?}", e)));
+        return;
+    }
+    let mut bytes = width as usize * height as usize * 4;
+    if upload.generate_mipmaps {
+        gl.generate_mipmap(GL::TEXTURE_2D);
+        // The mipmaps add a third.
+        bytes = bytes * 4 / 3;
+    }
+    gl.bind_texture(GL::TEXTURE_2D, None);
+    resources.set_size(Resource::Texture(texture), bytes);
+    load.set(TextureState::Loaded);
+}
+
+
+/// `GlBackend` forwarding to a browser `WebGl2RenderingContext`.
+pub struct WebGlBackend {
+    gl: Rc<GL>,
+    buffers: Handles<WebGlBuffer>,
+    textures: Handles<WebGlTexture>,
+    samplers: Handles<WebGlSampler>,
+    shaders: Handles<WebGlShader>,
+    programs: Handles<WebGlProgram>,
+    vertex_arrays: Handles<WebGlVertexArrayObject>,
+    /// Along with the program they were looked up in, and deleted with.
+    uniform_locations: Handles<(ProgramId, WebGlUniformLocation)>,
+    /// Images being fetched into textures, until the texture is deleted.
+    images: RefCell<HashMap<TextureId, ImageRequest>>,
+    /// Read on first use, which enables the extension.
+    max_anisotropy: Cell<Option<f32>>,
+    resources: Rc<Resources>
+}
+
+impl WebGlBackend {
+    pub fn new(gl: GL) -> Self {
+        WebGlBackend {
+            gl: Rc::new(gl),
+            buffers: Handles::new(),
+            textures: Handles::new(),
+            samplers: Handles::new(),
+            shaders: Handles::new(),
+            programs: Handles::new(),
+            vertex_arrays: Handles::new(),
+            uniform_locations: Handles::new(),
+            images: RefCell::new(HashMap::new()),
+            max_anisotropy: Cell::new(None),
+            resources: Rc::new(Resources::default())
+        }
+    }
+
+    /// `None` for ids from before `reset`, see `GlBackend::reset`.
+    fn shader(&self, shader: ShaderId) -> Option<WebGlShader> {
+        self.shaders.get(shader.0)
+    }
+
+    fn program(&self, program: ProgramId) -> Option<WebGlProgram> {
+        self.programs.get(program.0)
+    }
+
+    fn uniform_location(&self, location: Option<UniformLocationId>) -> Option<WebGlUniformLocation> {
+        location.and_then(|l| self.uniform_locations.get(l.0)).map(|(_, location)| location)
+    }
+
+    /// Fetches `src` and decodes it with `createImageBitmap`, which flips
+    /// and premultiplies it as it decodes.
+    fn load_image_bitmap(&self, window: Window, texture_id: TextureId, src: &str, upload: ImageUpload, load: ImageLoad) -> ImageRequest {
+        let options = ImageBitmapOptions::new();
+        options.set_image_orientation(if upload.flip_y { ImageOrientation::FlipY } else { ImageOrientation::FromImage });
+        options.set_premultiply_alpha(if upload.premultiply_alpha { PremultiplyAlpha::Premultiply } else { PremultiplyAlpha::None });
+
+        let cancelled = Rc::new(Cell::new(false));
+        let cancelled_clone = cancelled.clone();
+        let gl = self.gl.clone();
+        let resources = self.resources.clone();
+        let texture = self.textures.get(texture_id.0);
+        let src = src.to_string();
+        spawn_local(async move {
+            let bitmap = fetch_image_bitmap(&window, &src, &options).await;
+            if cancelled_clone.get() {
+                if let Ok(bitmap) = bitmap {
+                    bitmap.close();
+                }
+                return;
+            }
+            let bitmap = match bitmap {
+                Ok(bitmap) => bitmap,
+                Err(reason) => {
+                    load.set(TextureState::Failed(reason));
+                    return;
+                }
+            };
+            gl.bind_texture(GL::TEXTURE_2D, texture.as_ref());
+            let result = gl.tex_image_2d_with_u32_and_u32_and_image_bitmap(
+                GL::TEXTURE_2D,
+                0,
+                upload.internal_format as i32,
+                GL::RGBA,
+                GL::UNSIGNED_BYTE,
+                &bitmap
+            );
+            finish_upload(&gl, &resources, texture_id, upload, &load, result, bitmap.width(), bitmap.height());
+            bitmap.close();
+        });
+
+        ImageRequest::Bitmap { cancelled }
+    }
+
+    fn load_image_element(&self, texture_id: TextureId, src: &str, upload: ImageUpload, load: ImageLoad) -> Result<ImageRequest> {
+        let image = HtmlImageElement::new().map_err(|e| Error::TextureLoad {
+            src: src.to_string(),
+            reason: format!("{:?}", e)
+        })?;
+
+        let gl = self.gl.clone();
+        let image_clone = image.clone();
+        let resources = self.resources.clone();
+        let load_clone = load.clone();
+        let texture = self.textures.get(texture_id.0);
+        let onload = Closure::wrap(Box::new(move || {
+            gl.bind_texture(GL::TEXTURE_2D, texture.as_ref());
+            // The unpack state is global, and only holds for this upload.
+            gl.pixel_storei(GL::UNPACK_FLIP_Y_WEBGL, upload.flip_y as i32);
+            gl.pixel_storei(GL::UNPACK_PREMULTIPLY_ALPHA_WEBGL, upload.premultiply_alpha as i32);
+            let result = gl.tex_image_2d_with_u32_and_u32_and_html_image_element(
+                GL::TEXTURE_2D,
+                0,
+                upload.internal_format as i32,
+                GL::RGBA,
+                GL::UNSIGNED_BYTE,
+                &image_clone
+            );
+            gl.pixel_storei(GL::UNPACK_FLIP_Y_WEBGL, 0);
+            gl.pixel_storei(GL::UNPACK_PREMULTIPLY_ALPHA_WEBGL, 0);
+            finish_upload(&gl, &resources, texture_id, upload, &load_clone, result, image_clone.natural_width(), image_clone.natural_height());
+        }) as Box<dyn Fn()>);
+
+        let onerror = Closure::wrap(Box::new(move || {
+            load.set(TextureState::Failed("the image could not be fetched or decoded".to_string()));
+        }) as Box<dyn Fn()>);
+
+        image.set_onload(Some(onload.as_ref().unchecked_ref()));
+        image.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+        image.set_src(src);
+
+        Ok(ImageRequest::Element { image, _onload: onload, _onerror: onerror })
+    }
+}
+
+
+fn create_view(data: ArrayData) -> Object {
+    fn view<T: CreateArray>(data: &[T]) -> Object {
+        let data_location = data.as_ptr() as u32 / size_of::<T>() as u32;
+        T::create_array(data_location, data_location + data.len() as u32)
+    }
+
+    match data {
+        ArrayData::U8(data) => view(data),
+        ArrayData::I16(data) => view(data),
+        ArrayData::U16(data) => view(data),
+        ArrayData::U32(data) => view(data),
+        ArrayData::F32(data) => view(data)
+    }
+}
+
+
+impl GlBackend for WebGlBackend {
+    fn clear_color(&self, r: f32, g: f32, b: f32, a: f32) {
+        self.gl.clear_color(r, g, b, a);
+    }
+
+    fn clear(&self, mask: u32) {
+        self.gl.clear(mask);
+    }
+
+    fn viewport(&self, x: i32, y: i32, width: i32, height: i32) {
+        self.gl.viewport(x, y, width, height);
+    }
+
+    fn enable(&self, cap: u32) {
+        self.gl.enable(cap);
+    }
+
+    fn disable(&self, cap: u32) {
+        self.gl.disable(cap);
+    }
+
+    fn blend_func(&self, sfactor: u32, dfactor: u32) {
+        self.gl.blend_func(sfactor, dfactor);
+    }
+
+    fn cull_face(&self, mode: u32) {
+        self.gl.cull_face(mode);
+    }
+
+    fn drawing_buffer_size(&self) -> (i32, i32) {
+        (self.gl.drawing_buffer_width(), self.gl.drawing_buffer_height())
+    }
+
+    fn get_error(&self) -> u32 {
+        self.gl.get_error()
+    }
+
+    fn create_buffer(&self) -> Option<BufferId> {
+        self.gl.create_buffer().map(|b| BufferId(self.buffers.insert(b)))
+    }
+
+    fn bind_buffer(&self, target: u32, buffer: Option<BufferId>) {
+        self.gl.bind_buffer(target, buffer.and_then(|b| self.buffers.get(b.0)).as_ref());
+    }
+
+    fn buffer_data(&self, target: u32, data: ArrayData, usage: u32) {
+        self.gl.buffer_data_with_array_buffer_view(target, &create_view(data), usage);
+    }
+
+    fn buffer_data_size(&self, target: u32, size: i32, usage: u32) {
+        self.gl.buffer_data_with_i32(target, size, usage);
+    }
+
+    fn buffer_sub_data(&self, target: u32, offset: i32, data: ArrayData) {
+        self.gl.buffer_sub_data_with_i32_and_array_buffer_view(target, offset, &create_view(data));
+    }
+
+    fn copy_buffer_sub_data(&self, read_target: u32, write_target: u32, read_offset: i32, write_offset: i32, size: i32) {
+        self.gl.copy_buffer_sub_data_with_i32_and_i32_and_i32(read_target, write_target, read_offset, write_offset, size);
+    }
+
+    fn delete_buffer(&self, buffer: BufferId) {
+        self.gl.delete_buffer(self.buffers.remove(buffer.0).as_ref());
+    }
+
+    fn bind_buffer_base(&self, target: u32, index: u32, buffer: Option<BufferId>) {
+        self.gl.bind_buffer_base(target, index, buffer.and_then(|b| self.buffers.get(b.0)).as_ref());
+    }
+
+    fn create_vertex_array(&self) -> Option<VertexArrayId> {
+        self.gl.create_vertex_array().map(|v| VertexArrayId(self.vertex_arrays.insert(v)))
+    }
+
+    fn delete_vertex_array(&self, vao: VertexArrayId) {
+        self.gl.delete_vertex_array(self.vertex_arrays.remove(vao.0).as_ref());
+    }
+
+    fn bind_vertex_array(&self, vao: Option<VertexArrayId>) {
+        self.gl.bind_vertex_array(vao.and_then(|v| self.vertex_arrays.get(v.0)).as_ref());
+    }
+
+    fn vertex_attrib_pointer(&self, index: u32, size: i32, data_type: u32, normalized: bool, stride: i32, offset: i32) {
+        self.gl.vertex_attrib_pointer_with_i32(index, size, data_type, normalized, stride, offset);
+    }
+
+    fn enable_vertex_attrib_array(&self, index: u32) {
+        self.gl.enable_vertex_attrib_array(index);
+    }
+
+    fn disable_vertex_attrib_array(&self, index: u32) {
+        self.gl.disable_vertex_attrib_array(index);
+    }
+
+    fn draw_elements(&self, mode: u32, count: i32, data_type: u32, offset: i32) {
+        self.gl.draw_elements_with_i32(mode, count, data_type, offset);
+    }
+
+    fn create_shader(&self, shader_type: u32) -> Option<ShaderId> {
+        self.gl.create_shader(shader_type).map(|s| ShaderId(self.shaders.insert(s)))
+    }
+
+    fn shader_source(&self, shader: ShaderId, source: &str) {
+        if let Some(shader) = self.shader(shader) {
+            self.gl.shader_source(&shader, source);
+        }
+    }
+
+    fn compile_shader(&self, shader: ShaderId) {
+        if let Some(shader) = self.shader(shader) {
+            self.gl.compile_shader(&shader);
+        }
+    }
+
+    fn shader_compile_status(&self, shader: ShaderId) -> bool {
+        self.shader(shader)
+            .and_then(|shader| self.gl.get_shader_parameter(&shader, GL::COMPILE_STATUS).as_bool())
+            .unwrap_or(false)
+    }
+
+    fn shader_info_log(&self, shader: ShaderId) -> Option<String> {
+        self.shader(shader).and_then(|shader| self.gl.get_shader_info_log(&shader))
+    }
+
+    fn delete_shader(&self, shader: ShaderId) {
+        self.gl.delete_shader(self.shaders.remove(shader.0).as_ref());
+    }
+
+    fn create_program(&self) -> Option<ProgramId> {
+        self.gl.create_program().map(|p| ProgramId(self.programs.insert(p)))
+    }
+
+    fn attach_shader(&self, program: ProgramId, shader: ShaderId) {
+        if let (Some(program), Some(shader)) = (self.program(program), self.shader(shader)) {
+            self.gl.attach_shader(&program, &shader);
+        }
+    }
+
+    fn bind_attrib_location(&self, program: ProgramId, index: u32, name: &str) {
+        if let Some(program) = self.program(program) {
+            self.gl.bind_attrib_location(&program, index, name);
+        }
+    }
+
+    fn link_program(&self, program: ProgramId) {
+        if let Some(program) = self.program(program) {
+            self.gl.link_program(&program);
+        }
+    }
+
+    fn program_link_status(&self, program: ProgramId) -> bool {
+        self.program(program)
+            .and_then(|program| self.gl.get_program_parameter(&program, GL::LINK_STATUS).as_bool())
+            .unwrap_or(false)
+    }
+
+    fn program_info_log(&self, program: ProgramId) -> Option<String> {
+        self.program(program).and_then(|program| self.gl.get_program_info_log(&program))
+    }
+
+    fn use_program(&self, program: Option<ProgramId>) {
+        self.gl.use_program(program.and_then(|p| self.programs.get(p.0)).as_ref());
+    }
+
+    fn delete_program(&self, program: ProgramId) {
+        self.gl.delete_program(self.programs.remove(program.0).as_ref());
+        self.uniform_locations.retain(|(p, _)| *p != program);
+        self.resources.remove_uniform_locations(program);
+    }
+
+    fn get_attrib_location(&self, program: ProgramId, name: &str) -> i32 {
+        self.program(program).map_or(-1, |program| self.gl.get_attrib_location(&program, name))
+    }
+
+    fn get_uniform_location(&self, program: ProgramId, name: &str) -> Option<UniformLocationId> {
+        let location = self.gl.get_uniform_location(&self.program(program)?, name)?;
+        self.resources.add_uniform_location(program);
+        Some(UniformLocationId(self.uniform_locations.insert((program, location))))
+    }
+
+    fn active_attributes(&self, program: ProgramId) -> Vec<ActiveInfo> {
+        let program = match self.program(program) {
+            Some(program) => program,
+            None => return Vec::new()
+        };
+        let count = self.gl.get_program_parameter(&program, GL::ACTIVE_ATTRIBUTES).as_f64().unwrap_or(0.0) as u32;
+        (0..count)
+            .filter_map(|i| self.gl.get_active_attrib(&program, i))
+            .map(|info| active_info(&info))
+            .collect()
+    }
+
+    fn active_uniforms(&self, program: ProgramId) -> Vec<ActiveInfo> {
+        let program = match self.program(program) {
+            Some(program) => program,
+            None => return Vec::new()
+        };
+        let count = self.gl.get_program_parameter(&program, GL::ACTIVE_UNIFORMS).as_f64().unwrap_or(0.0) as u32;
+        let indices: js_sys::Array = (0..count).map(JsValue::from).collect();
+        let blocks: js_sys::Array = self.gl.get_active_uniforms(&program, &indices, GL::UNIFORM_BLOCK_INDEX).into();
+        (0..count)
+            .filter(|i| blocks.get(*i).as_f64() == Some(-1.0))
+            .filter_map(|i| self.gl.get_active_uniform(&program, i))
+            .map(|info| active_info(&info))
+            .collect()
+    }
+
+    fn active_uniform_blocks(&self, program: ProgramId) -> Vec<String> {
+        let program = match self.program(program) {
+            Some(program) => program,
+            None => return Vec::new()
+        };
+        let count = self.gl.get_program_parameter(&program, GL::ACTIVE_UNIFORM_BLOCKS).as_f64().unwrap_or(0.0) as u32;
+        (0..count)
+            .filter_map(|i| self.gl.get_active_uniform_block_name(&program, i))
+            .collect()
+    }
+
+    fn uniform_block_binding(&self, program: ProgramId, block_index: u32, binding: u32) {
+        if let Some(program) = self.program(program) {
+            self.gl.uniform_block_binding(&program, block_index, binding);
+        }
+    }
+
+    fn uniform1i(&self, location: Option<UniformLocationId>, value: i32) {
+        self.gl.uniform1i(self.uniform_location(location).as_ref(), value);
+    }
+
+    fn uniform2i(&self, location: Option<UniformLocationId>, x: i32, y: i32) {
+        self.gl.uniform2i(self.uniform_location(location).as_ref(), x, y);
+    }
+
+    fn uniform3i(&self, location: Option<UniformLocationId>, x: i32, y: i32, z: i32) {
+        self.gl.uniform3i(self.uniform_location(location).as_ref(), x, y, z);
+    }
+
+    fn uniform4i(&self, location: Option<UniformLocationId>, x: i32, y: i32, z: i32, w: i32) {
+        self.gl.uniform4i(self.uniform_location(location).as_ref(), x, y, z, w);
+    }
+
+    fn uniform1f(&self, location: Option<UniformLocationId>, value: f32) {
+        self.gl.uniform1f(self.uniform_location(location).as_ref(), value);
+    }
+
+    fn uniform2f(&self, location: Option<UniformLocationId>, x: f32, y: f32) {
+        self.gl.uniform2f(self.uniform_location(location).as_ref(), x, y);
+    }
+
+    fn uniform3f(&self, location: Option<UniformLocationId>, x: f32, y: f32, z: f32) {
+        self.gl.uniform3f(self.uniform_location(location).as_ref(), x, y, z);
+    }
+
+    fn uniform4f(&self, location: Option<UniformLocationId>, x: f32, y: f32, z: f32, w: f32) {
+        self.gl.uniform4f(self.uniform_location(location).as_ref(), x, y, z, w);
+    }
+
+    fn uniform_matrix3fv(&self, location: Option<UniformLocationId>, transpose: bool, data: &[f32]) {
+        self.gl.uniform_matrix3fv_with_f32_array(self.uniform_location(location).as_ref(), transpose, data);
+    }
+
+    fn uniform_matrix4fv(&self, location: Option<UniformLocationId>, transpose: bool, data: &[f32]) {
+        self.gl.uniform_matrix4fv_with_f32_array(self.uniform_location(location).as_ref(), transpose, data);
+    }
+
+    fn create_texture(&self) -> Option<TextureId> {
+        self.gl.create_texture().map(|t| TextureId(self.textures.insert(t)))
+    }
+
+    fn delete_texture(&self, texture: TextureId) {
+        self.images.borrow_mut().remove(&texture);
+        self.gl.delete_texture(self.textures.remove(texture.0).as_ref());
+    }
+
+    fn active_texture(&self, unit: u32) {
+        self.gl.active_texture(unit);
+    }
+
+    fn bind_texture(&self, target: u32, texture: Option<TextureId>) {
+        self.gl.bind_texture(target, texture.and_then(|t| self.textures.get(t.0)).as_ref());
+    }
+
+    fn tex_parameteri(&self, target: u32, pname: u32, param: i32) {
+        self.gl.tex_parameteri(target, pname, param);
+    }
+
+    fn tex_parameterf(&self, target: u32, pname: u32, param: f32) {
+        self.gl.tex_parameterf(target, pname, param);
+    }
+
+    fn max_anisotropy(&self) -> f32 {
+        if let Some(max) = self.max_anisotropy.get() {
+            return max;
+        }
+        // Getting the extension is what enables it.
+        let max = match self.gl.get_extension("EXT_texture_filter_anisotropic") {
+            Ok(Some(_)) => self.gl.get_parameter(MAX_TEXTURE_MAX_ANISOTROPY_EXT)
+                .ok()
+                .and_then(|max| max.as_f64())
+                .map_or(1.0, |max| max as f32),
+            _ => 1.0
+        };
+        self.max_anisotropy.set(Some(max));
+        max
+    }
+
+    fn tex_image_2d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: i32,
+        width: i32,
+        height: i32,
+        border: i32,
+        format: u32,
+        data_type: u32,
+        pixels: Option<ArrayData>
+    ) {
+        let pixels = pixels.map(create_view);
+        let result = self.gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view(
+            target,
+            level,
+            internal_format,
+            width,
+            height,
+            border,
+            format,
+            data_type,
+            pixels.as_ref()
+        );
+        if let Err(e) = result {
+            log!("Failed to upload texture data: {:?}", e);
+        }
+    }
+
+    fn tex_image_3d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: i32,
+        width: i32,
+        height: i32,
+        depth: i32,
+        border: i32,
+        format: u32,
+        data_type: u32,
+        pixels: Option<ArrayData>
+    ) {
+        let pixels = pixels.map(create_view);
+        let result = self.gl.tex_image_3d_with_opt_array_buffer_view(
+            target,
+            level,
+            internal_format,
+            width,
+            height,
+            depth,
+            border,
+            format,
+            data_type,
+            pixels.as_ref()
+        );
+        if let Err(e) = result {
+            log!("Failed to upload texture data: {:?}", e);
+        }
+    }
+
+    fn tex_sub_image_2d(
+        &self,
+        target: u32,
+        level: i32,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        format: u32,
+        data_type: u32,
+        pixels: ArrayData
+    ) {
+        let result = self.gl.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_array_buffer_view(
+            target,
+            level,
+            x,
+            y,
+            width,
+            height,
+            format,
+            data_type,
+            Some(&create_view(pixels))
+        );
+        if let Err(e) = result {
+            log!("Failed to update texture data: {:?}", e);
+        }
+    }
+
+    fn pixel_storei(&self, pname: u32, param: i32) {
+        self.gl.pixel_storei(pname, param);
+    }
+
+    fn load_image(&self, texture: TextureId, src: &str, upload: ImageUpload) -> Result<ImageLoad> {
+        let load = ImageLoad::new();
+        let request = match window() {
+            Some(window) if Reflect::has(&window, &"createImageBitmap".into()).unwrap_or(false) => {
+                self.load_image_bitmap(window, texture, src, upload, load.clone())
+            }
+            _ => self.load_image_element(texture, src, upload, load.clone())?
+        };
+        self.images.borrow_mut().insert(texture, request);
+        Ok(load)
+    }
+
+    fn max_combined_texture_image_units(&self) -> u32 {
+        self.gl.get_parameter(GL::MAX_COMBINED_TEXTURE_IMAGE_UNITS)
+            .ok()
+            .and_then(|units| units.as_f64())
+            // The minimum WebGL2 guarantees.
+            .map_or(32, |units| units as u32)
+    }
+
+    fn create_sampler(&self) -> Option<SamplerId> {
+        self.gl.create_sampler().map(|s| SamplerId(self.samplers.insert(s)))
+    }
+
+    fn delete_sampler(&self, sampler: SamplerId) {
+        self.gl.delete_sampler(self.samplers.remove(sampler.0).as_ref());
+    }
+
+    fn bind_sampler(&self, unit: u32, sampler: Option<SamplerId>) {
+        let sampler = sampler.and_then(|s| self.samplers.get(s.0));
+        self.gl.bind_sampler(unit, sampler.as_ref());
+    }
+
+    fn sampler_parameteri(&self, sampler: SamplerId, pname: u32, param: i32) {
+        if let Some(sampler) = self.samplers.get(sampler.0) {
+            self.gl.sampler_parameteri(&sampler, pname, param);
+        }
+    }
+
+    fn sampler_parameterf(&self, sampler: SamplerId, pname: u32, param: f32) {
+        if let Some(sampler) = self.samplers.get(sampler.0) {
+            self.gl.sampler_parameterf(&sampler, pname, param);
+        }
+    }
+
+    fn resources(&self) -> &Rc<Resources> {
+        &self.resources
+    }
+
+    fn reset(&self) {
+        self.buffers.clear();
+        self.textures.clear();
+        self.images.borrow_mut().clear();
+        self.samplers.clear();
+        // Extensions have to be enabled again on the restored context.
+        self.max_anisotropy.set(None);
+        self.shaders.clear();
+        self.programs.clear();
+        self.vertex_arrays.clear();
+        self.uniform_locations.clear();
+        self.resources.clear();
+    }
+}
@@ -1,17 +1,23 @@
+use std::cell::Cell;
 use std::rc::Rc;
+use js_sys::Array;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::*;
-use web_sys::WebGl2RenderingContext as GL;
 
+use crate::error::{Error, Result};
 
-pub fn create_canvas(parent: HtmlElement, width: u32, height: u32) -> Result<HtmlCanvasElement, JsValue> {
-    let window = window().unwrap();
-    let document = window.document().unwrap();
 
-    let canvas: HtmlCanvasElement = document.create_element("canvas")?.dyn_into()?;
-    canvas.set_width(width);
-    canvas.set_height(height);
+/// Appends a canvas of `width` x `height` CSS pixels to `parent`, with a
+/// backing store scaled by `pixel_ratio`.
+pub fn create_canvas(parent: &HtmlElement, width: u32, height: u32, pixel_ratio: f64) -> Result<HtmlCanvasElement> {
+    let window = window().ok_or_else(|| Error::Dom("no global window".to_string()))?;
+    let document = window.document().ok_or_else(|| Error::Dom("no document".to_string()))?;
+
+    let canvas: HtmlCanvasElement = document.create_element("canvas")?
+        .dyn_into()
+        .map_err(|_| Error::Dom("created element is not a canvas".to_string()))?;
+    resize_canvas(&canvas, width, height, pixel_ratio)?;
 
     parent.append_child(&canvas)?;
 
@@ -19,11 +25,131 @@ pub fn create_canvas(parent: HtmlElement, width: u32, height: u32) -> Result<Htm
 }
 
 
-pub fn create_webgl_context(canvas: &HtmlCanvasElement) -> Result<WebGl2RenderingContext, JsValue> {
-    let gl: WebGl2RenderingContext = canvas.get_context("webgl2")?.unwrap().dyn_into()?;
-    gl.clear_color(0.0, 0.0, 0.0, 1.0);
-    gl.enable(GL::DEPTH_TEST);
+/// Sets the CSS size of `canvas` and scales its backing store by
+/// `pixel_ratio`, so that one drawing buffer pixel maps to one device pixel.
+pub fn resize_canvas(canvas: &HtmlCanvasElement, width: u32, height: u32, pixel_ratio: f64) -> Result<()> {
+    canvas.set_width(((width as f64 * pixel_ratio).round() as u32).max(1));
+    canvas.set_height(((height as f64 * pixel_ratio).round() as u32).max(1));
 
-    Ok(gl)
+    let style = canvas.style();
+    style.set_property("width", &format!("{}px", width))?;
+    style.set_property("height", &format!("{}px", height))?;
+
+    Ok(())
+}
+
+
+/// Watches the size of an element with a `ResizeObserver`.
+///
+/// The observer callback only records the latest content size; it is picked
+/// up with `take` from the frame loop, where the client can be borrowed.
+/// Observation stops when the watcher is dropped.
+pub struct ResizeWatcher {
+    observer: ResizeObserver,
+    _callback: Closure<dyn FnMut(Array)>,
+    pending: Rc<Cell<Option<(u32, u32)>>>
+}
+
+impl ResizeWatcher {
+    pub fn new(element: &Element) -> Result<ResizeWatcher> {
+        let pending = Rc::new(Cell::new(None));
+
+        let pending_clone = pending.clone();
+        let callback = Closure::wrap(Box::new(move |entries: Array| {
+            if let Some(entry) = entries.iter().last() {
+                let rect = entry.unchecked_into::<ResizeObserverEntry>().content_rect();
+                pending_clone.set(Some((rect.width().round() as u32, rect.height().round() as u32)));
+            }
+        }) as Box<dyn FnMut(Array)>);
+
+        let observer = ResizeObserver::new(callback.as_ref().unchecked_ref())?;
+        observer.observe(element);
+
+        Ok(ResizeWatcher { observer, _callback: callback, pending })
+    }
+
+    /// The size reported since the last call, in CSS pixels.
+    pub fn take(&self) -> Option<(u32, u32)> {
+        self.pending.take()
+    }
+}
+
+impl Drop for ResizeWatcher {
+    fn drop(&mut self) {
+        self.observer.disconnect();
+    }
+}
+
+
+/// Where the WebGL context stands, as last reported by its canvas.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContextState {
+    Ready,
+    /// Lost, e.g. after a GPU reset or when the browser reclaimed it.
+    /// Nothing can be drawn until it is restored.
+    Lost,
+    /// Usable again, but every GPU object has to be created anew.
+    Restored
 }
 
+/// Listens for `webglcontextlost` and `webglcontextrestored` on a canvas.
+///
+/// Like `ResizeWatcher`, the listeners only record the new state, which the
+/// frame loop checks with `state`. The listeners are removed when the
+/// watcher is dropped.
+pub struct ContextWatcher {
+    canvas: HtmlCanvasElement,
+    on_lost: Closure<dyn FnMut(Event)>,
+    on_restored: Closure<dyn FnMut(Event)>,
+    state: Rc<Cell<ContextState>>
+}
+
+impl ContextWatcher {
+    pub fn new(canvas: &HtmlCanvasElement) -> Result<ContextWatcher> {
+        let state = Rc::new(Cell::new(ContextState::Ready));
+
+        let state_clone = state.clone();
+        let on_lost = Closure::wrap(Box::new(move |event: Event| {
+            // Without this the browser never restores the context.
+            event.prevent_default();
+            state_clone.set(ContextState::Lost);
+        }) as Box<dyn FnMut(Event)>);
+
+        let state_clone = state.clone();
+        let on_restored = Closure::wrap(Box::new(move |_: Event| {
+            state_clone.set(ContextState::Restored);
+        }) as Box<dyn FnMut(Event)>);
+
+        canvas.add_event_listener_with_callback("webglcontextlost", on_lost.as_ref().unchecked_ref())?;
+        canvas.add_event_listener_with_callback("webglcontextrestored", on_restored.as_ref().unchecked_ref())?;
+
+        Ok(ContextWatcher { canvas: canvas.clone(), on_lost, on_restored, state })
+    }
+
+    pub fn state(&self) -> ContextState {
+        self.state.get()
+    }
+
+    /// Records that the GPU objects were rebuilt after a restore.
+    pub fn set_ready(&self) {
+        self.state.set(ContextState::Ready);
+    }
+}
+
+impl Drop for ContextWatcher {
+    fn drop(&mut self) {
+        self.canvas.remove_event_listener_with_callback("webglcontextlost", self.on_lost.as_ref().unchecked_ref()).ok();
+        self.canvas.remove_event_listener_with_callback("webglcontextrestored", self.on_restored.as_ref().unchecked_ref()).ok();
+    }
+}
+
+
+pub fn create_webgl_context(canvas: &HtmlCanvasElement) -> Result<WebGl2RenderingContext> {
+    let gl: WebGl2RenderingContext = canvas.get_context("webgl2")
+        .map_err(|e| Error::ContextCreation(format!("{:?}", e)))?
+        .ok_or_else(|| Error::ContextCreation("WebGL2 is not supported".to_string()))?
+        .dyn_into()
+        .map_err(|_| Error::ContextCreation("unexpected context type".to_string()))?;
+
+    Ok(gl)
+}
@@ -0,0 +1,115 @@
+use std::fmt;
+use wasm_bindgen::JsValue;
+
+use crate::geometry::MeshError;
+use crate::image::ImageError;
+use crate::shader::ShaderStage;
+
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// A DOM call made while setting up the page failed.
+    Dom(String),
+    /// The options object passed to `WebClient::new` could not be parsed.
+    InvalidOptions(String),
+    /// The browser did not hand out a WebGL2 context.
+    ContextCreation(String),
+    /// The context refused to create an object, which usually means it was lost.
+    ObjectCreation(&'static str),
+    /// A shader file is missing or has a malformed `#include`.
+    ShaderSource(String),
+    ShaderCompile { stage: ShaderStage, log: String },
+    ProgramLink(String),
+    MissingAttribute(String),
+    MissingUniform(String),
+    /// A uniform value does not fit the uniform's type.
+    InvalidUniform(String),
+    /// Data does not match the type the shader declares for an attribute
+    /// or uniform.
+    TypeMismatch { name: String, expected: String, found: String },
+    TextureLoad { src: String, reason: String },
+    /// A material reads from more textures than the GPU has units.
+    TextureUnits { needed: usize, available: u32 },
+    /// A sampler was given for a sampler uniform without a texture.
+    MissingTexture(String),
+    /// A range update would leave a gap after the end of a buffer.
+    BufferUpdate(String),
+    /// A texture update does not fit the texture.
+    TextureUpdate(String),
+    /// The size of a data texture does not fit its target, e.g. cube map
+    /// faces that are not square.
+    InvalidTexture(String),
+    UnknownTextureFormat(String),
+    OutOfMemory,
+    Mesh(MeshError),
+    Image(ImageError),
+    UnknownLayer(String),
+    /// A marker has no valid position, or would leave a gap.
+    InvalidMarker(String),
+    UnknownNode(String),
+    /// A scene graph change would detach the root or create a cycle.
+    InvalidParent(String)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Dom(message) => write!(f, "DOM error: {}", message),
+            Error::InvalidOptions(message) => write!(f, "invalid options: {}", message),
+            Error::ContextCreation(message) => write!(f, "could not create a WebGL2 context: {}", message),
+            Error::ObjectCreation(object) => write!(f, "could not create {} object", object),
+            Error::ShaderSource(message) => write!(f, "invalid shader source: {}", message),
+            Error::ShaderCompile { stage, log } => write!(f, "{} shader failed to compile: {}", stage, log),
+            Error::ProgramLink(log) => write!(f, "shader program failed to link: {}", log),
+            Error::MissingAttribute(name) => write!(f, "cannot find attribute '{}'", name),
+            Error::MissingUniform(name) => write!(f, "cannot find uniform '{}'", name),
+            Error::InvalidUniform(message) => write!(f, "invalid uniform value: {}", message),
+            Error::TypeMismatch { name, expected, found } => write!(f, "'{}' expects {}, got {}", name, expected, found),
+            Error::TextureLoad { src, reason } => write!(f, "could not load texture '{}': {}", src, reason),
+            Error::TextureUnits { needed, available } => {
+                write!(f, "{} textures need more than the {} texture units available", needed, available)
+            },
+            Error::MissingTexture(name) => write!(f, "no texture is set for '{}'", name),
+            Error::BufferUpdate(message) => write!(f, "cannot update buffer: {}", message),
+            Error::TextureUpdate(message) => write!(f, "cannot update texture: {}", message),
+            Error::InvalidTexture(message) => write!(f, "invalid texture: {}", message),
+            Error::UnknownTextureFormat(name) => write!(f, "unknown texture format '{}'", name),
+            Error::OutOfMemory => write!(f, "the GPU ran out of memory"),
+            Error::Mesh(error) => error.fmt(f),
+            Error::Image(error) => error.fmt(f),
+            Error::UnknownLayer(name) => write!(f, "unknown globe layer '{}'", name),
+            Error::InvalidMarker(message) => write!(f, "invalid marker: {}", message),
+            Error::UnknownNode(id) => write!(f, "unknown scene node '{}'", id),
+            Error::InvalidParent(message) => write!(f, "invalid scene graph change: {}", message)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<MeshError> for Error {
+    fn from(error: MeshError) -> Self {
+        Error::Mesh(error)
+    }
+}
+
+impl From<ImageError> for Error {
+    fn from(error: ImageError) -> Self {
+        Error::Image(error)
+    }
+}
+
+impl From<JsValue> for Error {
+    fn from(value: JsValue) -> Self {
+        Error::Dom(format!("{:?}", value))
+    }
+}
+
+impl From<Error> for JsValue {
+    fn from(error: Error) -> Self {
+        js_sys::Error::new(&error.to_string()).into()
+    }
+}
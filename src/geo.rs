@@ -0,0 +1,181 @@
+//! Conversions between geographic coordinates and the globe's local frame.
+//!
+//! The frame matches the texture mapping of `Mesh::uv_sphere`, which wraps an
+//! equirectangular map around the globe: +y points to the north pole, +x to
+//! latitude 0 / longitude 0 and -z to longitude 90° east. Angles are in
+//! degrees; altitude is measured from the surface, in the same units as the
+//! globe radius.
+
+use std::f32::consts::PI;
+use nalgebra::{Point3, Unit, Vector3};
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GeoPosition {
+    pub latitude: f32,
+    pub longitude: f32,
+    pub altitude: f32
+}
+
+impl GeoPosition {
+    pub fn new(latitude: f32, longitude: f32, altitude: f32) -> Self {
+        GeoPosition { latitude, longitude, altitude }
+    }
+
+    /// Point at this position above a globe of `radius` centred on the origin.
+    pub fn to_cartesian(&self, radius: f32) -> Point3<f32> {
+        Point3::from(self.direction().into_inner() * (radius + self.altitude))
+    }
+
+    /// Inverse of `to_cartesian`. Longitude is in `-180..=180`, and 0 at the
+    /// poles.
+    pub fn from_cartesian(point: &Point3<f32>, radius: f32) -> Self {
+        let distance = point.coords.norm();
+        if distance == 0.0 {
+            return GeoPosition::new(0.0, 0.0, -radius);
+        }
+
+        let latitude = (point.y / distance).clamp(-1.0, 1.0).asin();
+        let longitude = if point.x == 0.0 && point.z == 0.0 {
+            0.0
+        } else {
+            (-point.z).atan2(point.x)
+        };
+
+        GeoPosition::new(latitude.to_degrees(), longitude.to_degrees(), distance - radius)
+    }
+
+    /// Unit vector from the centre of the globe towards this position.
+    pub fn direction(&self) -> Unit<Vector3<f32>> {
+        let (sin_lat, cos_lat) = self.latitude.to_radians().sin_cos();
+        let (sin_lon, cos_lon) = self.longitude.to_radians().sin_cos();
+        Unit::new_unchecked(Vector3::new(cos_lat * cos_lon, sin_lat, -cos_lat * sin_lon))
+    }
+
+    /// Texture coordinates of this position on the globe's color maps.
+    pub fn uv(&self) -> (f32, f32) {
+        ((self.longitude + 180.0) / 360.0, (90.0 - self.latitude) / 180.0)
+    }
+}
+
+
+/// Spherical interpolation between two directions, following the shorter
+/// great circle. Antipodal directions have no unique great circle; the
+/// path then passes over the north pole, or through longitude 0 when it
+/// starts from a pole.
+pub fn great_circle(
+    from: &Unit<Vector3<f32>>,
+    to: &Unit<Vector3<f32>>,
+    t: f32
+) -> Unit<Vector3<f32>> {
+    let cos_angle = from.dot(to).clamp(-1.0, 1.0);
+    let angle = cos_angle.acos();
+    if angle < 1e-6 {
+        return *to;
+    }
+
+    // Direction perpendicular to `from` in the plane of the path.
+    let towards = if PI - angle < 1e-4 {
+        let axis = if from.y.abs() < 0.9 { Vector3::y() } else { Vector3::x() };
+        from.cross(&axis).cross(from).normalize()
+    } else {
+        (to.into_inner() - from.into_inner() * cos_angle).normalize()
+    };
+
+    let (sin, cos) = (angle * t).sin_cos();
+    Unit::new_normalize(from.into_inner() * cos + towards * sin)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Mesh;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn reference_points() {
+        let radius = 200.0;
+        let points = [
+            (GeoPosition::new(0.0, 0.0, 0.0), [200.0, 0.0, 0.0]),
+            (GeoPosition::new(0.0, 90.0, 0.0), [0.0, 0.0, -200.0]),
+            (GeoPosition::new(0.0, -90.0, 0.0), [0.0, 0.0, 200.0]),
+            (GeoPosition::new(90.0, 0.0, 0.0), [0.0, 200.0, 0.0]),
+            (GeoPosition::new(-90.0, 0.0, 50.0), [0.0, -250.0, 0.0])
+        ];
+
+        for (position, expected) in points.iter() {
+            let point = position.to_cartesian(radius);
+            for i in 0..3 {
+                assert_close(point[i], expected[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn cartesian_round_trip() {
+        for latitude in (-80..=80).step_by(20) {
+            for longitude in (-175..=175).step_by(35) {
+                let position = GeoPosition::new(latitude as f32, longitude as f32, 12.5);
+                let back = GeoPosition::from_cartesian(&position.to_cartesian(200.0), 200.0);
+
+                assert_close(back.latitude, position.latitude);
+                assert_close(back.longitude, position.longitude);
+                assert_close(back.altitude, position.altitude);
+            }
+        }
+    }
+
+    #[test]
+    fn matches_the_globe_texture_mapping() {
+        let mesh = Mesh::uv_sphere(200.0, 36, 18).unwrap();
+
+        for i in 0..mesh.vertex_count() {
+            let uv = mesh.uv(i);
+            let (u, v) = (uv.x, uv.y);
+            // Skip the poles and the seam, where the mapping is not unique.
+            let inside = 1e-3..=1.0 - 1e-3;
+            if !inside.contains(&u) || !inside.contains(&v) {
+                continue;
+            }
+
+            let p = mesh.position(i);
+            let position = GeoPosition::from_cartesian(&Point3::from(p), 200.0);
+            let (geo_u, geo_v) = position.uv();
+            assert_close(geo_u, u);
+            assert_close(geo_v, v);
+            assert_close(position.altitude, 0.0);
+        }
+    }
+
+    #[test]
+    fn great_circle_stays_on_the_sphere() {
+        let london = GeoPosition::new(51.5, -0.1, 0.0).direction();
+        let tokyo = GeoPosition::new(35.7, 139.7, 0.0).direction();
+        let angle = london.angle(&tokyo);
+
+        assert_eq!(great_circle(&london, &tokyo, 0.0).into_inner(), london.into_inner());
+        assert!((great_circle(&london, &tokyo, 1.0).into_inner() - tokyo.into_inner()).norm() < 1e-5);
+
+        let halfway = great_circle(&london, &tokyo, 0.5);
+        assert_close(halfway.into_inner().norm(), 1.0);
+        assert_close(london.angle(&halfway), angle / 2.0);
+        assert_close(tokyo.angle(&halfway), angle / 2.0);
+
+        // The great circle from London to Tokyo passes over the Arctic.
+        assert!(GeoPosition::from_cartesian(&Point3::from(halfway.into_inner()), 1.0).latitude > 60.0);
+    }
+
+    #[test]
+    fn great_circle_between_antipodes() {
+        let from = GeoPosition::new(0.0, 0.0, 0.0).direction();
+        let to = GeoPosition::new(0.0, 180.0, 0.0).direction();
+
+        let halfway = great_circle(&from, &to, 0.5);
+        assert_close(halfway.y.abs(), 1.0);
+        assert!((great_circle(&from, &to, 1.0).into_inner() - to.into_inner()).norm() < 1e-5);
+    }
+}
@@ -0,0 +1,370 @@
+use std::error::Error;
+use std::fmt;
+use nalgebra::{Vector2, Vector3, Vector4};
+
+
+/// Largest vertex count addressable with `u16` indices. WebGL2 always
+/// enables primitive restart, so the maximum index value is reserved.
+const MAX_U16_VERTICES: u128 = u16::MAX as u128;
+
+/// Largest vertex count addressable with `u32` indices.
+const MAX_U32_VERTICES: u128 = u32::MAX as u128;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MeshError {
+    /// The requested mesh needs more vertices than 32-bit indices can address.
+    TooManyVertices(u128),
+    /// A size given to a generator is negative, zero where the mesh would
+    /// collapse, or not finite.
+    InvalidDimension { name: &'static str, value: f32 }
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshError::TooManyVertices(count) => write!(
+                f, "mesh needs {} vertices, more than 32-bit indices can address", count
+            ),
+            MeshError::InvalidDimension { name, value } => write!(f, "invalid mesh {} {}", name, value)
+        }
+    }
+}
+
+impl Error for MeshError {}
+
+
+/// Index buffer of a `Mesh`, stored as `u16` while every vertex fits and as
+/// `u32` otherwise.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>)
+}
+
+impl Indices {
+    pub fn len(&self) -> usize {
+        match self {
+            Indices::U16(indices) => indices.len(),
+            Indices::U32(indices) => indices.len()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, i: usize) -> u32 {
+        match self {
+            Indices::U16(indices) => indices[i] as u32,
+            Indices::U32(indices) => indices[i]
+        }
+    }
+
+    fn set(&mut self, i: usize, value: u32) {
+        match self {
+            Indices::U16(indices) => indices[i] = value as u16,
+            Indices::U32(indices) => indices[i] = value
+        }
+    }
+
+    fn push(&mut self, value: u32) {
+        match self {
+            Indices::U16(indices) => indices.push(value as u16),
+            Indices::U32(indices) => indices.push(value)
+        }
+    }
+
+    fn promote(&mut self) {
+        if let Indices::U16(indices) = self {
+            *self = Indices::U32(indices.iter().map(|&i| i as u32).collect());
+        }
+    }
+}
+
+impl Default for Indices {
+    fn default() -> Self {
+        Indices::U16(Vec::new())
+    }
+}
+
+
+/// Indexed triangle mesh built on the CPU, independent of any GL context.
+///
+/// Attributes are stored flat in the layout they are uploaded with: three
+/// floats per position and normal, two per uv and four per tangent, where
+/// `w` holds the handedness of the tangent frame. Triangles are wound
+/// counter-clockwise when seen from the side their normals point to.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub positions: Vec<f32>,
+    pub normals: Vec<f32>,
+    pub uvs: Vec<f32>,
+    pub tangents: Vec<f32>,
+    pub indices: Indices
+}
+
+impl Mesh {
+    pub fn new() -> Self {
+        Mesh::default()
+    }
+
+    /// Empty mesh whose index type fits `vertex_count` vertices.
+    pub(super) fn sized_for(vertex_count: u128) -> Result<Mesh, MeshError> {
+        if vertex_count > MAX_U32_VERTICES {
+            return Err(MeshError::TooManyVertices(vertex_count));
+        }
+        let mut mesh = Mesh::new();
+        if vertex_count > MAX_U16_VERTICES {
+            mesh.indices.promote();
+        }
+        Ok(mesh)
+    }
+
+    /// Fails if `extra` more vertices would not fit 32-bit indices.
+    pub(super) fn check_room(&self, extra: u128) -> Result<(), MeshError> {
+        let vertex_count = self.vertex_count() as u128 + extra;
+        if vertex_count > MAX_U32_VERTICES {
+            return Err(MeshError::TooManyVertices(vertex_count));
+        }
+        Ok(())
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len() / 3
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Distance from the origin to the farthest vertex, e.g. the radius a
+    /// sphere was built with.
+    pub fn bounding_radius(&self) -> f32 {
+        (0..self.vertex_count())
+            .map(|i| self.position(i).norm())
+            .fold(0.0, f32::max)
+    }
+
+    pub fn position(&self, index: usize) -> Vector3<f32> {
+        Vector3::from_column_slice(&self.positions[index * 3..index * 3 + 3])
+    }
+
+    pub fn normal(&self, index: usize) -> Vector3<f32> {
+        Vector3::from_column_slice(&self.normals[index * 3..index * 3 + 3])
+    }
+
+    pub fn uv(&self, index: usize) -> Vector2<f32> {
+        Vector2::from_column_slice(&self.uvs[index * 2..index * 2 + 2])
+    }
+
+    pub fn tangent(&self, index: usize) -> Vector4<f32> {
+        Vector4::from_column_slice(&self.tangents[index * 4..index * 4 + 4])
+    }
+
+    pub fn triangle(&self, index: usize) -> [u32; 3] {
+        [self.indices.get(index * 3), self.indices.get(index * 3 + 1), self.indices.get(index * 3 + 2)]
+    }
+
+    pub(super) fn set_index(&mut self, i: usize, value: u32) {
+        self.indices.set(i, value);
+    }
+
+    pub(super) fn push_vertex(&mut self, position: Vector3<f32>, normal: Vector3<f32>, uv: Vector2<f32>) -> u32 {
+        let index = self.vertex_count() as u128;
+        assert!(index < MAX_U32_VERTICES, "mesh exceeds the 32-bit index range");
+        if index >= MAX_U16_VERTICES {
+            self.indices.promote();
+        }
+        self.positions.extend_from_slice(position.as_slice());
+        self.normals.extend_from_slice(normal.as_slice());
+        self.uvs.extend_from_slice(uv.as_slice());
+        index as u32
+    }
+
+    pub(super) fn push_triangle(&mut self, a: u32, b: u32, c: u32) {
+        self.indices.push(a);
+        self.indices.push(b);
+        self.indices.push(c);
+    }
+
+    /// Appends a flat grid spanning `u_axis` and `v_axis` from `origin`.
+    ///
+    /// The face normal is `u_axis × v_axis` and uvs run from 0 to 1 along
+    /// each axis.
+    pub(super) fn push_grid(
+        &mut self,
+        origin: Vector3<f32>,
+        u_axis: Vector3<f32>,
+        v_axis: Vector3<f32>,
+        u_segments: u32,
+        v_segments: u32
+    ) {
+        let normal = u_axis.cross(&v_axis).normalize();
+        let first = self.vertex_count() as u32;
+        let row = u_segments + 1;
+
+        for iv in 0..=v_segments {
+            let v = iv as f32 / v_segments as f32;
+            for iu in 0..=u_segments {
+                let u = iu as f32 / u_segments as f32;
+                self.push_vertex(origin + u_axis * u + v_axis * v, normal, Vector2::new(u, v));
+            }
+        }
+
+        for iv in 0..v_segments {
+            for iu in 0..u_segments {
+                let a = first + iv * row + iu;
+                let b = a + 1;
+                let c = a + row + 1;
+                let d = a + row;
+                self.push_triangle(a, b, c);
+                self.push_triangle(a, c, d);
+            }
+        }
+    }
+
+    /// Recomputes `tangents` from positions, normals and uvs.
+    ///
+    /// Tangents follow the direction of increasing `u`; vertices whose uvs
+    /// do not define a direction (such as the poles of a sphere) get an
+    /// arbitrary tangent perpendicular to their normal.
+    pub fn compute_tangents(&mut self) {
+        let count = self.vertex_count();
+        let mut tan_u = vec![Vector3::zeros(); count];
+        let mut tan_v = vec![Vector3::zeros(); count];
+
+        for t in 0..self.triangle_count() {
+            let [i0, i1, i2] = self.triangle(t);
+            let (i0, i1, i2) = (i0 as usize, i1 as usize, i2 as usize);
+            let e1 = self.position(i1) - self.position(i0);
+            let e2 = self.position(i2) - self.position(i0);
+            let d1 = self.uv(i1) - self.uv(i0);
+            let d2 = self.uv(i2) - self.uv(i0);
+
+            let r = d1.x * d2.y - d2.x * d1.y;
+            if r.abs() < f32::EPSILON {
+                continue;
+            }
+            let sdir = (e1 * d2.y - e2 * d1.y) / r;
+            let tdir = (e2 * d1.x - e1 * d2.x) / r;
+            for &i in &[i0, i1, i2] {
+                tan_u[i] += sdir;
+                tan_v[i] += tdir;
+            }
+        }
+
+        self.tangents.clear();
+        for i in 0..count {
+            let n = self.normal(i);
+            let mut t = tan_u[i] - n * n.dot(&tan_u[i]);
+            if t.norm() < 1e-6 {
+                t = perpendicular(&n);
+            }
+            let t = t.normalize();
+            let w = if n.cross(&t).dot(&tan_v[i]) < 0.0 { -1.0 } else { 1.0 };
+            self.tangents.extend_from_slice(&[t.x, t.y, t.z, w]);
+        }
+    }
+}
+
+
+fn perpendicular(n: &Vector3<f32>) -> Vector3<f32> {
+    if n.x.abs() < 0.9 {
+        n.cross(&Vector3::x())
+    } else {
+        n.cross(&Vector3::y())
+    }
+}
+
+
+#[cfg(test)]
+impl Mesh {
+    /// Checks the invariants every generator must uphold.
+    pub(super) fn assert_valid(&self) {
+        let count = self.vertex_count();
+        assert_eq!(self.positions.len(), count * 3);
+        assert_eq!(self.normals.len(), count * 3);
+        assert_eq!(self.uvs.len(), count * 2);
+        assert_eq!(self.tangents.len(), count * 4);
+        assert_eq!(self.indices.len() % 3, 0);
+        assert!((0..self.indices.len()).all(|i| (self.indices.get(i) as usize) < count));
+
+        for i in 0..count {
+            let n = self.normal(i);
+            let t = self.tangent(i);
+            assert!((n.norm() - 1.0).abs() < 1e-4, "normal {} has length {}", i, n.norm());
+            assert!((t.xyz().norm() - 1.0).abs() < 1e-4, "tangent {} has length {}", i, t.xyz().norm());
+            assert!(n.dot(&t.xyz()).abs() < 1e-4, "tangent {} is not perpendicular to its normal", i);
+            assert!(t.w == 1.0 || t.w == -1.0);
+        }
+
+        // Counter-clockwise winding: the face normal agrees with the vertex normals
+        for t in 0..self.triangle_count() {
+            let triangle = self.triangle(t);
+            let (i0, i1, i2) = (triangle[0] as usize, triangle[1] as usize, triangle[2] as usize);
+            let face = (self.position(i1) - self.position(i0)).cross(&(self.position(i2) - self.position(i0)));
+            if face.norm() < 1e-6 {
+                continue;
+            }
+            let normal = self.normal(i0) + self.normal(i1) + self.normal(i2);
+            assert!(face.dot(&normal) > 0.0, "triangle {:?} is wound clockwise", triangle);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_is_wound_towards_its_normal() {
+        let mut mesh = Mesh::new();
+        mesh.push_grid(Vector3::zeros(), Vector3::x(), Vector3::y(), 3, 2);
+        mesh.compute_tangents();
+        mesh.assert_valid();
+
+        assert_eq!(mesh.vertex_count(), 4 * 3);
+        assert_eq!(mesh.triangle_count(), 3 * 2 * 2);
+        assert_eq!(mesh.normal(0), Vector3::z());
+        assert_eq!(mesh.tangent(0), Vector4::new(1.0, 0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn tangents_flip_handedness_with_mirrored_uvs() {
+        let mut mesh = Mesh::new();
+        mesh.push_grid(Vector3::zeros(), Vector3::x(), Vector3::y(), 1, 1);
+        for v in mesh.uvs.iter_mut().skip(1).step_by(2) {
+            *v = 1.0 - *v;
+        }
+        mesh.compute_tangents();
+
+        assert_eq!(mesh.tangent(0).w, -1.0);
+    }
+
+    #[test]
+    fn indices_widen_once_u16_runs_out() {
+        let mut mesh = Mesh::new();
+        mesh.push_grid(Vector3::zeros(), Vector3::x(), Vector3::y(), 255, 254);
+        assert_eq!(mesh.vertex_count(), 256 * 255);
+        assert!(matches!(mesh.indices, Indices::U16(_)));
+
+        mesh.push_grid(Vector3::z(), Vector3::x(), Vector3::y(), 15, 15);
+        assert_eq!(mesh.vertex_count(), 65536);
+        assert!(matches!(mesh.indices, Indices::U32(_)));
+        assert_eq!(mesh.triangle(mesh.triangle_count() - 1), [65518, 65535, 65534]);
+        mesh.compute_tangents();
+        mesh.assert_valid();
+    }
+
+    #[test]
+    fn sized_for_picks_index_type() {
+        assert!(matches!(Mesh::sized_for(65535).unwrap().indices, Indices::U16(_)));
+        assert!(matches!(Mesh::sized_for(65536).unwrap().indices, Indices::U32(_)));
+        assert_eq!(
+            Mesh::sized_for(1 << 32).unwrap_err(),
+            MeshError::TooManyVertices(1 << 32)
+        );
+    }
+}
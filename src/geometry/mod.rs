@@ -0,0 +1,5 @@
+mod mesh;
+mod primitives;
+mod sphere;
+
+pub use self::mesh::*;
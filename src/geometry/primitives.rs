@@ -0,0 +1,363 @@
+use std::f32::consts::PI;
+use nalgebra::{Vector2, Vector3};
+
+use super::{Mesh, MeshError};
+
+
+/// Outward normal, right and up axes of each cube face, chosen so that
+/// `right × up = normal`.
+pub(super) const CUBE_FACES: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0])
+];
+
+
+impl Mesh {
+    /// Rectangle in the XY plane, centered on the origin and facing +Z.
+    pub fn plane(width: f32, height: f32, width_segments: u32, height_segments: u32) -> Result<Mesh, MeshError> {
+        let width_segments = width_segments.max(1);
+        let height_segments = height_segments.max(1);
+
+        let mut mesh = Mesh::sized_for(grid_vertices(width_segments, height_segments))?;
+        mesh.push_grid(
+            Vector3::new(-width / 2.0, -height / 2.0, 0.0),
+            Vector3::new(width, 0.0, 0.0),
+            Vector3::new(0.0, height, 0.0),
+            width_segments,
+            height_segments
+        );
+        mesh.compute_tangents();
+        Ok(mesh)
+    }
+
+    /// Axis aligned box centered on the origin, with every face split into
+    /// `segments` × `segments` quads.
+    pub fn cuboid(width: f32, height: f32, depth: f32, segments: u32) -> Result<Mesh, MeshError> {
+        let half = Vector3::new(width / 2.0, height / 2.0, depth / 2.0);
+        let segments = segments.max(1);
+
+        let mut mesh = Mesh::sized_for(6 * grid_vertices(segments, segments))?;
+        for (normal, right, up) in CUBE_FACES.iter() {
+            let (normal, right, up) = (
+                Vector3::from(*normal).component_mul(&half),
+                Vector3::from(*right).component_mul(&half),
+                Vector3::from(*up).component_mul(&half)
+            );
+            mesh.push_grid(normal - right - up, right * 2.0, up * 2.0, segments, segments);
+        }
+        mesh.compute_tangents();
+        Ok(mesh)
+    }
+
+    /// Cylinder (or truncated cone) along the Y axis, centered on the origin.
+    pub fn cylinder(
+        radius_top: f32,
+        radius_bottom: f32,
+        height: f32,
+        radial_segments: u32,
+        height_segments: u32,
+        open_ended: bool
+    ) -> Result<Mesh, MeshError> {
+        check_dimension("height", height, false)?;
+        check_dimension("radius_top", radius_top, true)?;
+        check_dimension("radius_bottom", radius_bottom, radius_top > 0.0)?;
+        let radial_segments = radial_segments.max(3);
+        let height_segments = height_segments.max(1);
+        let slope = (radius_bottom - radius_top) / height;
+
+        let caps = if open_ended { 0 } else { 2 * (radial_segments as u128 + 2) };
+        let mut mesh = Mesh::sized_for(grid_vertices(radial_segments, height_segments) + caps)?;
+        let mut grid: Vec<Vec<u32>> = Vec::new();
+
+        for iy in 0..=height_segments {
+            let v = iy as f32 / height_segments as f32;
+            let radius = v * (radius_bottom - radius_top) + radius_top;
+
+            let row = (0..=radial_segments).map(|ix| {
+                let u = ix as f32 / radial_segments as f32;
+                let theta = u * 2.0 * PI;
+                let (sin, cos) = theta.sin_cos();
+                mesh.push_vertex(
+                    Vector3::new(radius * sin, height / 2.0 - v * height, radius * cos),
+                    Vector3::new(sin, slope, cos).normalize(),
+                    Vector2::new(u, 1.0 - v)
+                )
+            }).collect();
+            grid.push(row);
+        }
+
+        for iy in 0..height_segments as usize {
+            for ix in 0..radial_segments as usize {
+                let a = grid[iy][ix];
+                let b = grid[iy + 1][ix];
+                let c = grid[iy + 1][ix + 1];
+                let d = grid[iy][ix + 1];
+
+                // Skip the triangles that collapse into the apex of a cone
+                if !(iy == 0 && radius_top == 0.0) {
+                    mesh.push_triangle(a, b, d);
+                }
+                if !(iy == height_segments as usize - 1 && radius_bottom == 0.0) {
+                    mesh.push_triangle(b, c, d);
+                }
+            }
+        }
+
+        if !open_ended {
+            if radius_top > 0.0 {
+                push_cap(&mut mesh, radius_top, height / 2.0, radial_segments, true);
+            }
+            if radius_bottom > 0.0 {
+                push_cap(&mut mesh, radius_bottom, -height / 2.0, radial_segments, false);
+            }
+        }
+
+        mesh.compute_tangents();
+        Ok(mesh)
+    }
+
+    /// Closed cone along the Y axis with its apex at the top.
+    pub fn cone(radius: f32, height: f32, radial_segments: u32, height_segments: u32) -> Result<Mesh, MeshError> {
+        check_dimension("radius", radius, false)?;
+        Mesh::cylinder(0.0, radius, height, radial_segments, height_segments, false)
+    }
+
+    /// Torus around the Z axis. `radius` is measured to the center of the
+    /// tube.
+    pub fn torus(radius: f32, tube: f32, radial_segments: u32, tubular_segments: u32) -> Result<Mesh, MeshError> {
+        let radial_segments = radial_segments.max(3);
+        let tubular_segments = tubular_segments.max(3);
+
+        let mut mesh = Mesh::sized_for(grid_vertices(tubular_segments, radial_segments))?;
+        let row = tubular_segments + 1;
+        for j in 0..=radial_segments {
+            let v = j as f32 / radial_segments as f32 * 2.0 * PI;
+            for i in 0..=tubular_segments {
+                let u = i as f32 / tubular_segments as f32 * 2.0 * PI;
+                let center = Vector3::new(radius * u.cos(), radius * u.sin(), 0.0);
+                let position = Vector3::new(
+                    (radius + tube * v.cos()) * u.cos(),
+                    (radius + tube * v.cos()) * u.sin(),
+                    tube * v.sin()
+                );
+                mesh.push_vertex(
+                    position,
+                    (position - center).normalize(),
+                    Vector2::new(i as f32 / tubular_segments as f32, j as f32 / radial_segments as f32)
+                );
+            }
+        }
+
+        for j in 1..=radial_segments {
+            for i in 1..=tubular_segments {
+                let a = row * j + i - 1;
+                let b = row * (j - 1) + i - 1;
+                let c = row * (j - 1) + i;
+                let d = row * j + i;
+                mesh.push_triangle(a, b, d);
+                mesh.push_triangle(b, c, d);
+            }
+        }
+
+        mesh.compute_tangents();
+        Ok(mesh)
+    }
+
+    /// Flat annulus in the XY plane facing +Z.
+    pub fn ring(inner_radius: f32, outer_radius: f32, theta_segments: u32, phi_segments: u32) -> Result<Mesh, MeshError> {
+        let theta_segments = theta_segments.max(3);
+        let phi_segments = phi_segments.max(1);
+
+        let mut mesh = Mesh::sized_for(grid_vertices(theta_segments, phi_segments))?;
+        let row = theta_segments + 1;
+        for j in 0..=phi_segments {
+            let radius = inner_radius + (outer_radius - inner_radius) * j as f32 / phi_segments as f32;
+            for i in 0..=theta_segments {
+                let theta = i as f32 / theta_segments as f32 * 2.0 * PI;
+                let position = Vector3::new(radius * theta.cos(), radius * theta.sin(), 0.0);
+                mesh.push_vertex(
+                    position,
+                    Vector3::z(),
+                    Vector2::new(
+                        (position.x / outer_radius + 1.0) / 2.0,
+                        (position.y / outer_radius + 1.0) / 2.0
+                    )
+                );
+            }
+        }
+
+        for j in 0..phi_segments {
+            for i in 0..theta_segments {
+                let a = j * row + i;
+                let b = a + row;
+                let c = a + row + 1;
+                let d = a + 1;
+                mesh.push_triangle(a, b, d);
+                mesh.push_triangle(b, c, d);
+            }
+        }
+
+        mesh.compute_tangents();
+        Ok(mesh)
+    }
+}
+
+
+/// Vertex count of a grid of `u_segments` × `v_segments` quads.
+pub(super) fn grid_vertices(u_segments: u32, v_segments: u32) -> u128 {
+    (u_segments as u128 + 1) * (v_segments as u128 + 1)
+}
+
+
+/// Checks that `value` is finite and positive, or zero if `zero` is allowed.
+fn check_dimension(name: &'static str, value: f32, zero: bool) -> Result<(), MeshError> {
+    if value.is_finite() && (value > 0.0 || (zero && value == 0.0)) {
+        Ok(())
+    } else {
+        Err(MeshError::InvalidDimension { name, value })
+    }
+}
+
+
+/// Disc closing one end of a cylinder.
+fn push_cap(mesh: &mut Mesh, radius: f32, y: f32, radial_segments: u32, top: bool) {
+    let sign = if top { 1.0 } else { -1.0 };
+    let normal = Vector3::new(0.0, sign, 0.0);
+    let center = mesh.push_vertex(Vector3::new(0.0, y, 0.0), normal, Vector2::new(0.5, 0.5));
+
+    let ring: Vec<u32> = (0..=radial_segments).map(|ix| {
+        let theta = ix as f32 / radial_segments as f32 * 2.0 * PI;
+        let (sin, cos) = theta.sin_cos();
+        mesh.push_vertex(
+            Vector3::new(radius * sin, y, radius * cos),
+            normal,
+            Vector2::new(sin * 0.5 + 0.5, cos * 0.5 * sign + 0.5)
+        )
+    }).collect();
+
+    for ix in 0..radial_segments as usize {
+        if top {
+            mesh.push_triangle(center, ring[ix], ring[ix + 1]);
+        } else {
+            mesh.push_triangle(center, ring[ix + 1], ring[ix]);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Indices;
+
+    #[test]
+    fn plane_counts() {
+        let mesh = Mesh::plane(4.0, 2.0, 4, 2).unwrap();
+        mesh.assert_valid();
+        assert_eq!(mesh.vertex_count(), 5 * 3);
+        assert_eq!(mesh.triangle_count(), 4 * 2 * 2);
+        assert_eq!(mesh.position(0), Vector3::new(-2.0, -1.0, 0.0));
+        assert_eq!(mesh.position(14), Vector3::new(2.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn cuboid_faces_point_outwards() {
+        let mesh = Mesh::cuboid(2.0, 4.0, 6.0, 2).unwrap();
+        mesh.assert_valid();
+        assert_eq!(mesh.vertex_count(), 6 * 3 * 3);
+        assert_eq!(mesh.triangle_count(), 6 * 2 * 2 * 2);
+        for i in 0..mesh.vertex_count() {
+            let p = mesh.position(i);
+            assert!(p.x.abs() <= 1.0 && p.y.abs() <= 2.0 && p.z.abs() <= 3.0);
+            // Each vertex lies on the face its normal points out of
+            let n = mesh.normal(i);
+            assert!((p.dot(&n) - Vector3::new(1.0, 2.0, 3.0).dot(&n.abs())).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn cylinder_counts() {
+        let mesh = Mesh::cylinder(1.0, 2.0, 3.0, 8, 2, false).unwrap();
+        mesh.assert_valid();
+        assert_eq!(mesh.vertex_count(), 9 * 3 + 2 * (1 + 9));
+        assert_eq!(mesh.triangle_count(), 8 * 2 * 2 + 2 * 8);
+
+        let open = Mesh::cylinder(1.0, 1.0, 3.0, 8, 2, true).unwrap();
+        open.assert_valid();
+        assert_eq!(open.triangle_count(), 8 * 2 * 2);
+    }
+
+    #[test]
+    fn cone_has_no_degenerate_triangles() {
+        let mesh = Mesh::cone(1.0, 2.0, 16, 1).unwrap();
+        mesh.assert_valid();
+        assert_eq!(mesh.triangle_count(), 16 + 16);
+        for t in 0..mesh.triangle_count() {
+            let triangle = mesh.triangle(t);
+            let p: Vec<_> = triangle.iter().map(|&i| mesh.position(i as usize)).collect();
+            assert!((p[1] - p[0]).cross(&(p[2] - p[0])).norm() > 1e-6);
+        }
+    }
+
+    #[test]
+    fn cylinders_need_a_height_and_a_radius() {
+        assert_eq!(
+            Mesh::cylinder(1.0, 1.0, 0.0, 8, 1, false).unwrap_err(),
+            MeshError::InvalidDimension { name: "height", value: 0.0 }
+        );
+        assert_eq!(
+            Mesh::cylinder(-1.0, 1.0, 1.0, 8, 1, false).unwrap_err(),
+            MeshError::InvalidDimension { name: "radius_top", value: -1.0 }
+        );
+        assert_eq!(
+            Mesh::cylinder(0.0, 0.0, 1.0, 8, 1, false).unwrap_err(),
+            MeshError::InvalidDimension { name: "radius_bottom", value: 0.0 }
+        );
+        assert!(matches!(
+            Mesh::cone(1.0, f32::INFINITY, 8, 1),
+            Err(MeshError::InvalidDimension { name: "height", .. })
+        ));
+        assert!(matches!(Mesh::cone(f32::NAN, 1.0, 8, 1), Err(MeshError::InvalidDimension { name: "radius", .. })));
+        // An upside down cone is fine.
+        Mesh::cylinder(1.0, 0.0, 1.0, 8, 1, false).unwrap().assert_valid();
+    }
+
+    #[test]
+    fn torus_counts() {
+        let mesh = Mesh::torus(3.0, 1.0, 8, 12).unwrap();
+        mesh.assert_valid();
+        assert_eq!(mesh.vertex_count(), 9 * 13);
+        assert_eq!(mesh.triangle_count(), 8 * 12 * 2);
+        for i in 0..mesh.vertex_count() {
+            let p = mesh.position(i);
+            let center = Vector3::new(p.x, p.y, 0.0).normalize() * 3.0;
+            assert!(((p - center).norm() - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn ring_counts() {
+        let mesh = Mesh::ring(1.0, 2.0, 16, 2).unwrap();
+        mesh.assert_valid();
+        assert_eq!(mesh.vertex_count(), 17 * 3);
+        assert_eq!(mesh.triangle_count(), 16 * 2 * 2);
+        for i in 0..mesh.vertex_count() {
+            let r = mesh.position(i).norm();
+            assert!(r > 1.0 - 1e-5 && r < 2.0 + 1e-5);
+        }
+    }
+
+    #[test]
+    fn oversized_primitives_are_rejected() {
+        assert!(Mesh::plane(1.0, 1.0, u32::MAX, 2).is_err());
+        assert!(Mesh::torus(1.0, 0.5, 70_000, 70_000).is_err());
+        // Segment counts that overflow `u32` once a row is closed.
+        assert!(Mesh::torus(1.0, 0.5, 3, u32::MAX).is_err());
+        assert!(Mesh::ring(1.0, 2.0, u32::MAX, 1).is_err());
+        assert!(matches!(Mesh::plane(1.0, 1.0, 300, 300).unwrap().indices, Indices::U32(_)));
+    }
+}
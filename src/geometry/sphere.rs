@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use nalgebra::{Vector2, Vector3};
+
+use super::{Mesh, MeshError};
+use super::primitives::{grid_vertices, CUBE_FACES};
+
+
+impl Mesh {
+    /// Latitude/longitude sphere, parameterized the way the globe textures
+    /// expect:
+    ///
+    /// x = -r·cos(2πu)·sin(πv), y = r·cos(πv), z = r·sin(2πu)·sin(πv)
+    pub fn uv_sphere(radius: f32, width_segments: u32, height_segments: u32) -> Result<Mesh, MeshError> {
+        let width_segments = width_segments.max(3);
+        let height_segments = height_segments.max(2);
+
+        let mut mesh = Mesh::sized_for(grid_vertices(width_segments, height_segments))?;
+        let mut grid: Vec<Vec<u32>> = Vec::new();
+
+        for iy in 0..=height_segments {
+            let mut vertices_row: Vec<u32> = Vec::new();
+
+            let v = iy as f32 / height_segments as f32;
+
+            let u_offset = if iy == 0 {
+                0.5 / width_segments as f32
+            } else if iy == height_segments {
+                - 0.5 / width_segments as f32
+            } else {
+                0.0
+            };
+
+            for ix in 0..=width_segments {
+                let u = ix as f32 / width_segments as f32;
+
+                let n = Vector3::new(
+                    -(u * 2.0 * PI).cos() * (v * PI).sin(),
+                    (v * PI).cos(),
+                    (u * 2.0 * PI).sin() * (v * PI).sin()
+                );
+
+                vertices_row.push(mesh.push_vertex(n * radius, n, Vector2::new(u + u_offset, v)));
+            }
+
+            grid.push(vertices_row);
+        }
+
+        for iy in 0..(height_segments as usize) {
+            for ix in 0..(width_segments as usize) {
+                let a = grid[iy][ix + 1];
+                let b = grid[iy][ix];
+                let c = grid[iy + 1][ix];
+                let d = grid[iy + 1][ix + 1];
+
+                if iy != 0 {
+                    mesh.push_triangle(a, b, d);
+                }
+
+                if iy != (height_segments as usize - 1) {
+                    mesh.push_triangle(b, c, d);
+                }
+            }
+        }
+
+        mesh.compute_tangents();
+        Ok(mesh)
+    }
+
+    /// Sphere made by repeatedly subdividing an icosahedron, giving evenly
+    /// sized triangles with no pinching at the poles.
+    pub fn icosphere(radius: f32, subdivisions: u32) -> Result<Mesh, MeshError> {
+        let mut mesh = Mesh::sized_for(icosphere_vertices(subdivisions))?;
+
+        let t = (1.0 + 5f32.sqrt()) / 2.0;
+        let mut vertices: Vec<Vector3<f32>> = [
+            (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+            (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+            (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0)
+        ].iter().map(|&(x, y, z)| Vector3::new(x, y, z).normalize()).collect();
+
+        let mut faces: Vec<[usize; 3]> = vec![
+            [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+            [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+            [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+            [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1]
+        ];
+
+        for _ in 0..subdivisions {
+            let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+            let mut midpoint = |a: usize, b: usize, vertices: &mut Vec<Vector3<f32>>| {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    vertices.push(((vertices[a] + vertices[b]) / 2.0).normalize());
+                    vertices.len() - 1
+                })
+            };
+
+            faces = faces.iter().flat_map(|&[a, b, c]| {
+                let ab = midpoint(a, b, &mut vertices);
+                let bc = midpoint(b, c, &mut vertices);
+                let ca = midpoint(c, a, &mut vertices);
+                vec![[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            }).collect();
+        }
+
+        for n in &vertices {
+            mesh.push_vertex(n * radius, *n, spherical_uv(n));
+        }
+        for &[a, b, c] in &faces {
+            mesh.push_triangle(a as u32, b as u32, c as u32);
+        }
+
+        wrap_seam(&mut mesh)?;
+        mesh.compute_tangents();
+        Ok(mesh)
+    }
+
+    /// Sphere made by projecting a subdivided cube onto its circumscribed
+    /// sphere. Each cube face is split into `segments` × `segments` quads.
+    pub fn cube_sphere(radius: f32, segments: u32) -> Result<Mesh, MeshError> {
+        let segments = segments.max(1);
+
+        let mut mesh = Mesh::sized_for(cube_sphere_vertices(segments))?;
+        for (normal, right, up) in CUBE_FACES.iter() {
+            let (normal, right, up) = (Vector3::from(*normal), Vector3::from(*right), Vector3::from(*up));
+            mesh.push_grid(normal - right - up, right * 2.0, up * 2.0, segments, segments);
+        }
+
+        for i in 0..mesh.vertex_count() {
+            let n = mesh.position(i).normalize();
+            let uv = spherical_uv(&n);
+            mesh.positions[i * 3..i * 3 + 3].copy_from_slice((n * radius).as_slice());
+            mesh.normals[i * 3..i * 3 + 3].copy_from_slice(n.as_slice());
+            mesh.uvs[i * 2..i * 2 + 2].copy_from_slice(uv.as_slice());
+        }
+
+        wrap_seam(&mut mesh)?;
+        mesh.compute_tangents();
+        Ok(mesh)
+    }
+}
+
+
+/// Vertex count of an icosphere, including the copies `wrap_seam` makes
+/// along the meridian of about `3·2^subdivisions` edges it cuts.
+fn icosphere_vertices(subdivisions: u32) -> u128 {
+    4u128.checked_pow(subdivisions)
+        .map_or(u128::MAX, |faces| faces * 10 + 2 + 3 * (1 << subdivisions) + 2)
+}
+
+/// Vertex count of a cube sphere, including the copies `wrap_seam` makes
+/// along the `2·segments` quads the seam crosses.
+fn cube_sphere_vertices(segments: u32) -> u128 {
+    6 * grid_vertices(segments, segments) + 2 * segments as u128 + 5
+}
+
+
+/// Inverse of the `uv_sphere` parameterization for a unit direction.
+fn spherical_uv(n: &Vector3<f32>) -> Vector2<f32> {
+    let u = n.z.atan2(-n.x) / (2.0 * PI);
+    let v = n.y.clamp(-1.0, 1.0).acos() / PI;
+    Vector2::new(if u < 0.0 { u + 1.0 } else { u }, v)
+}
+
+
+/// Duplicates the vertices of triangles that straddle the `u = 0` seam so
+/// that their uvs do not interpolate across the whole texture.
+fn wrap_seam(mesh: &mut Mesh) -> Result<(), MeshError> {
+    let mut wrapped: HashMap<u32, u32> = HashMap::new();
+
+    for t in 0..mesh.triangle_count() {
+        let corners = mesh.triangle(t);
+        let us: Vec<f32> = corners.iter().map(|&i| mesh.uv(i as usize).x).collect();
+        let max_u = us.iter().cloned().fold(f32::MIN, f32::max);
+        let min_u = us.iter().cloned().fold(f32::MAX, f32::min);
+        if max_u - min_u <= 0.5 {
+            continue;
+        }
+
+        for (k, &i) in corners.iter().enumerate() {
+            if us[k] >= 0.5 {
+                continue;
+            }
+            let copy = match wrapped.get(&i) {
+                Some(&copy) => copy,
+                None => {
+                    mesh.check_room(1)?;
+                    let (position, normal, uv) = (mesh.position(i as usize), mesh.normal(i as usize), mesh.uv(i as usize));
+                    let copy = mesh.push_vertex(position, normal, Vector2::new(uv.x + 1.0, uv.y));
+                    wrapped.insert(i, copy);
+                    copy
+                }
+            };
+            mesh.set_index(t * 3 + k, copy);
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Indices;
+
+    fn assert_on_sphere(mesh: &Mesh, radius: f32) {
+        for i in 0..mesh.vertex_count() {
+            assert!((mesh.position(i).norm() - radius).abs() < 1e-3);
+            assert!((mesh.position(i).normalize() - mesh.normal(i)).norm() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn uv_sphere_matches_globe_layout() {
+        let mesh = Mesh::uv_sphere(200.0, 40, 30).unwrap();
+        mesh.assert_valid();
+        assert_on_sphere(&mesh, 200.0);
+
+        assert_eq!(mesh.vertex_count(), 41 * 31);
+        // The first and last rows collapse into the poles, so they only
+        // contribute one triangle per segment
+        assert_eq!(mesh.triangle_count(), 40 * 30 * 2 - 2 * 40);
+
+        // u = 0.25, v = 0.5 lies on the +z axis
+        let i = 15 * 41 + 10;
+        assert!((mesh.position(i) - Vector3::new(0.0, 0.0, 200.0)).norm() < 1e-3);
+        assert_eq!(mesh.uv(i), Vector2::new(0.25, 0.5));
+    }
+
+    #[test]
+    fn uv_sphere_clamps_segments() {
+        let mesh = Mesh::uv_sphere(1.0, 0, 0).unwrap();
+        assert_eq!(mesh.vertex_count(), 4 * 3);
+        mesh.assert_valid();
+    }
+
+    #[test]
+    fn icosphere_subdivides_faces() {
+        for subdivisions in 0..4 {
+            let mesh = Mesh::icosphere(2.0, subdivisions).unwrap();
+            mesh.assert_valid();
+            assert_on_sphere(&mesh, 2.0);
+            assert_eq!(mesh.triangle_count(), 20 * 4usize.pow(subdivisions));
+            assert!(mesh.vertex_count() >= 10 * 4usize.pow(subdivisions) + 2);
+        }
+    }
+
+    #[test]
+    fn cube_sphere_has_six_faces() {
+        let mesh = Mesh::cube_sphere(1.0, 8).unwrap();
+        mesh.assert_valid();
+        assert_on_sphere(&mesh, 1.0);
+        assert_eq!(mesh.triangle_count(), 6 * 8 * 8 * 2);
+        assert!(mesh.vertex_count() >= 6 * 9 * 9);
+    }
+
+    #[test]
+    fn spherical_uv_inverts_uv_sphere() {
+        for &(u, v) in &[(0.1f32, 0.3f32), (0.25, 0.5), (0.6, 0.8), (0.9, 0.1)] {
+            let n = Vector3::new(
+                -(u * 2.0 * PI).cos() * (v * PI).sin(),
+                (v * PI).cos(),
+                (u * 2.0 * PI).sin() * (v * PI).sin()
+            );
+            assert!((spherical_uv(&n) - Vector2::new(u, v)).norm() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn seam_triangles_do_not_span_the_texture() {
+        let mesh = Mesh::icosphere(1.0, 2).unwrap();
+        for t in 0..mesh.triangle_count() {
+            let triangle = mesh.triangle(t);
+            let us: Vec<f32> = triangle.iter().map(|&i| mesh.uv(i as usize).x).collect();
+            let span = us.iter().cloned().fold(f32::MIN, f32::max) - us.iter().cloned().fold(f32::MAX, f32::min);
+            assert!(span <= 0.5);
+        }
+    }
+
+    #[test]
+    fn large_uv_sphere_uses_u32_indices() {
+        let mesh = Mesh::uv_sphere(1.0, 300, 300).unwrap();
+        assert_eq!(mesh.vertex_count(), 301 * 301);
+        assert!(matches!(mesh.indices, Indices::U32(_)));
+        assert_eq!(mesh.triangle(mesh.triangle_count() - 1), [300 * 301 - 1, 300 * 301 - 2, 301 * 301 - 1]);
+
+        assert!(matches!(Mesh::uv_sphere(1.0, 40, 30).unwrap().indices, Indices::U16(_)));
+    }
+
+    #[test]
+    fn unindexable_spheres_are_rejected() {
+        assert_eq!(
+            Mesh::uv_sphere(1.0, 70_000, 70_000).unwrap_err(),
+            MeshError::TooManyVertices(70_001 * 70_001)
+        );
+        assert!(Mesh::icosphere(1.0, 20).is_err());
+        assert!(Mesh::cube_sphere(1.0, u32::MAX).is_err());
+    }
+
+    #[test]
+    fn size_estimates_cover_the_seam_copies() {
+        for subdivisions in 0..6 {
+            let mesh = Mesh::icosphere(1.0, subdivisions).unwrap();
+            assert!(mesh.vertex_count() as u128 <= icosphere_vertices(subdivisions));
+        }
+        for segments in [1, 2, 3, 8, 33, 100] {
+            let mesh = Mesh::cube_sphere(1.0, segments).unwrap();
+            assert!(mesh.vertex_count() as u128 <= cube_sphere_vertices(segments));
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! JPEG and PNG decoding in Rust, so that the pixels of the globe maps can
+//! be read outside of a browser, e.g. the land mask of the specular map.
+//!
+//! Decoded images are RGBA8, row by row from the top. In the browser,
+//! loading a texture from its URL remains the faster path, since the browser
+//! decodes images off the main thread with `createImageBitmap`.
+
+use std::error::Error;
+use std::fmt;
+
+
+const PNG_SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+const JPEG_SIGNATURE: &[u8] = &[0xff, 0xd8, 0xff];
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImageError {
+    /// The bytes start like neither a JPEG nor a PNG file.
+    UnknownFormat,
+    /// The file is damaged or uses a feature the decoder lacks.
+    Decode(String)
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::UnknownFormat => write!(f, "image is neither a JPEG nor a PNG file"),
+            ImageError::Decode(message) => write!(f, "could not decode image: {}", message)
+        }
+    }
+}
+
+impl Error for ImageError {}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>
+}
+
+impl Image {
+    /// Decodes a JPEG or PNG file, told apart by their signatures.
+    pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+        if bytes.starts_with(PNG_SIGNATURE) {
+            decode_png(bytes)
+        } else if bytes.starts_with(JPEG_SIGNATURE) {
+            decode_jpeg(bytes)
+        } else {
+            Err(ImageError::UnknownFormat)
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// RGBA, four bytes per pixel.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// The pixel at column `x` of row `y`, counted from the top left.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        assert!(x < self.width && y < self.height, "pixel ({}, {}) is outside of the image", x, y);
+        let start = (y as usize * self.width as usize + x as usize) * 4;
+        let mut pixel = [0; 4];
+        pixel.copy_from_slice(&self.pixels[start..start + 4]);
+        pixel
+    }
+
+    /// Turns the image upside down, so that the first row is the bottom one
+    /// as texture coordinates expect.
+    pub fn flip_rows(&mut self) {
+        let row = self.width as usize * 4;
+        self.pixels = self.pixels.chunks(row).rev().flatten().copied().collect();
+    }
+
+    /// Multiplies the color of each pixel by its alpha.
+    pub fn premultiply_alpha(&mut self) {
+        for pixel in self.pixels.chunks_mut(4) {
+            let alpha = pixel[3] as u32;
+            for component in &mut pixel[..3] {
+                *component = ((*component as u32 * alpha + 127) / 255) as u8;
+            }
+        }
+    }
+}
+
+
+fn decode_png(bytes: &[u8]) -> Result<Image, ImageError> {
+    let mut decoder = png::Decoder::new(bytes);
+    // Palettes, low bit depths and 16-bit channels all come out as 8 bits.
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()
+        .map_err(|e| ImageError::Decode(e.to_string()))?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut data)
+        .map_err(|e| ImageError::Decode(e.to_string()))?;
+    data.truncate(frame.buffer_size());
+
+    let pixels = match frame.color_type {
+        png::ColorType::Grayscale => data.iter().flat_map(|&l| [l, l, l, 255]).collect(),
+        png::ColorType::GrayscaleAlpha => data.chunks(2).flat_map(|la| [la[0], la[0], la[0], la[1]]).collect(),
+        png::ColorType::Rgb => data.chunks(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect(),
+        png::ColorType::Rgba => data,
+        png::ColorType::Indexed => return Err(ImageError::Decode("palette was not expanded".to_string()))
+    };
+    Ok(Image { width: frame.width, height: frame.height, pixels })
+}
+
+fn decode_jpeg(bytes: &[u8]) -> Result<Image, ImageError> {
+    let mut decoder = jpeg_decoder::Decoder::new(bytes);
+    let data = decoder.decode()
+        .map_err(|e| ImageError::Decode(e.to_string()))?;
+    let info = decoder.info()
+        .ok_or_else(|| ImageError::Decode("missing JPEG header".to_string()))?;
+
+    let pixels = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => data.iter().flat_map(|&l| [l, l, l, 255]).collect(),
+        // Big endian, so the first byte is the most significant.
+        jpeg_decoder::PixelFormat::L16 => data.chunks(2).flat_map(|l| [l[0], l[0], l[0], 255]).collect(),
+        jpeg_decoder::PixelFormat::RGB24 => data.chunks(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect(),
+        jpeg_decoder::PixelFormat::CMYK32 => data.chunks(4).flat_map(|cmyk| {
+            let k = 255 - cmyk[3] as u32;
+            let channel = |c: u8| ((255 - c as u32) * k / 255) as u8;
+            [channel(cmyk[0]), channel(cmyk[1]), channel(cmyk[2]), 255]
+        }).collect()
+    };
+    Ok(Image { width: info.width as u32, height: info.height as u32, pixels })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SPEC_MAP: &[u8] = include_bytes!("../data/earthspec1k.jpg");
+
+    fn png(color_type: png::ColorType, width: u32, height: u32, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(color_type);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header().unwrap().write_image_data(data).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn the_spec_map_masks_the_oceans() {
+        let image = Image::decode(SPEC_MAP).unwrap();
+        assert_eq!((image.width(), image.height()), (1000, 500));
+        assert_eq!(image.pixels().len(), 1000 * 500 * 4);
+
+        // The map is equirectangular from 180° west: the middle of the
+        // Pacific (0°, 150° W) shines, the Sahara (23° N, 10° E) does not.
+        let pacific = image.pixel(83, 250);
+        let sahara = image.pixel(528, 186);
+        assert!(pacific[0] > 200, "{:?}", pacific);
+        assert!(sahara[0] < 50, "{:?}", sahara);
+        assert_eq!(pacific[3], 255);
+    }
+
+    #[test]
+    fn png_colors_are_expanded_to_rgba() {
+        let gray = png(png::ColorType::Grayscale, 2, 1, &[10, 200]);
+        let image = Image::decode(&gray).unwrap();
+        assert_eq!(image.pixels(), &[10, 10, 10, 255, 200, 200, 200, 255]);
+
+        let rgba = png(png::ColorType::Rgba, 1, 2, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        let image = Image::decode(&rgba).unwrap();
+        assert_eq!((image.width(), image.height()), (1, 2));
+        assert_eq!(image.pixel(0, 1), [5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn unknown_and_broken_files_are_rejected() {
+        assert_eq!(Image::decode(b"GIF89a"), Err(ImageError::UnknownFormat));
+        assert!(matches!(Image::decode(&SPEC_MAP[..64]), Err(ImageError::Decode(_))));
+    }
+
+    #[test]
+    fn rows_flip_and_alpha_premultiplies() {
+        let rgba = png(png::ColorType::Rgba, 1, 2, &[255, 0, 0, 128, 0, 0, 255, 255]);
+        let mut image = Image::decode(&rgba).unwrap();
+
+        image.flip_rows();
+        image.premultiply_alpha();
+        assert_eq!(image.pixels(), &[0, 0, 255, 255, 128, 0, 0, 128]);
+    }
+}
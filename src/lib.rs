@@ -2,17 +2,32 @@
 mod utils;
 
 mod app;
+pub mod astronomy;
+mod backend;
 mod canvas;
+mod error;
+pub mod geo;
+pub mod geometry;
+pub mod image;
+mod options;
 mod render;
+mod scene;
 mod shader;
 
 
+use nalgebra::{Quaternion, UnitQuaternion, Vector3};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use std::rc::Rc;
 use web_sys::*;
 use crate::app::App;
-use crate::render::Renderer;
+use crate::backend::{GlBackend, WebGlBackend};
+use crate::canvas::{ContextState, ContextWatcher, ResizeWatcher};
+use crate::error::Error;
+use crate::geo::GeoPosition;
+use crate::image::Image;
+use crate::options::{Container, WebClientOptions};
+use crate::render::{Marker, Renderer, Texels};
+use crate::scene::{Node, NodeId};
 
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
@@ -21,49 +36,125 @@ use crate::render::Renderer;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+/// Runs `$upload` with `$data` bound to the slice held by `$texels`, in
+/// its own type.
+macro_rules! with_texels {
+    ($texels:expr, |$data:ident| $upload:expr) => {
+        match $texels {
+            Texels::U8($data) => $upload,
+            Texels::U16($data) => $upload,
+            Texels::U32($data) => $upload,
+            Texels::F32($data) => $upload
+        }
+    };
+}
+
+
 #[wasm_bindgen]
 pub struct WebClient {
     app: App,
+    container: HtmlElement,
     canvas: HtmlCanvasElement,
-    gl: Rc<WebGl2RenderingContext>,
-    renderer: Renderer
+    gl: Box<dyn GlBackend>,
+    renderer: Renderer,
+    options: WebClientOptions,
+    size: (u32, u32),
+    pixel_ratio: f64,
+    resize_watcher: Option<ResizeWatcher>,
+    context_watcher: ContextWatcher,
+    /// `resolve` functions of the promises handed out by `textures_ready`.
+    texture_waiters: Vec<js_sys::Function>
 }
 
 
-static APP_ID: &'static str = "rust-webgl";
-static WIDTH: u32 = 1024;
-static HEIGHT: u32 = 1024;
-
-
 #[wasm_bindgen]
 impl WebClient {
-    pub fn new() -> Self {
+    /// Creates the client from an optional options object, e.g.
+    /// `WebClient.new({ container: "globe", width: 800, height: 600 })`.
+    /// See `WebClientOptions` for the recognised fields.
+    pub fn new(options: JsValue) -> Result<WebClient, JsValue> {
         utils::set_panic_hook();
 
+        let options = WebClientOptions::from_js(&options)?;
+
         // Create the canvas
-        let window = window().unwrap();
-        let document = window.document().unwrap();
-        let div: HtmlElement = match document.get_element_by_id(APP_ID) {
-            Some(container) => container.dyn_into().unwrap(),
-            None => {
-                let div = document.create_element("div").unwrap();
-                div.set_id(APP_ID);
-                div.dyn_into().unwrap()
+        let window = window().ok_or_else(|| Error::Dom("no global window".to_string()))?;
+        let document = window.document().ok_or_else(|| Error::Dom("no document".to_string()))?;
+        let container = match &options.container {
+            Container::Element(element) => element.clone(),
+            Container::Id(id) => {
+                let element = match document.get_element_by_id(id) {
+                    Some(container) => container,
+                    None => {
+                        let div = document.create_element("div")?;
+                        div.set_id(id);
+                        document.body()
+                            .ok_or_else(|| Error::Dom("no document body".to_string()))?
+                            .append_child(&div)?;
+                        div
+                    }
+                };
+                element.dyn_into()
+                    .map_err(|_| Error::Dom(format!("'{}' is not an HTML element", id)))?
             }
         };
-        let canvas = canvas::create_canvas(div, WIDTH, HEIGHT).unwrap();
+        let pixel_ratio = options.effective_pixel_ratio(window.device_pixel_ratio());
+        let canvas = canvas::create_canvas(&container, options.width, options.height, pixel_ratio)?;
 
         // Create the WebGl context
-        let gl = Rc::new(canvas::create_webgl_context(&canvas).unwrap());
+        let context_watcher = ContextWatcher::new(&canvas)?;
+        let gl: Box<dyn GlBackend> = Box::new(
+            WebGlBackend::new(canvas::create_webgl_context(&canvas)?)
+        );
 
         // Create the Application
-        let app = App::new(gl.clone());
+        let mut app = App::new(gl.as_ref(), &options)?;
+        app.set_time(js_sys::Date::now());
 
         // Create the Renderer
-        let renderer = Renderer::new(gl.clone());
+        let renderer = Renderer::new(options.clear_color);
 
         // Create the WebClient
-        WebClient { app, canvas, gl, renderer }
+        let size = (options.width, options.height);
+        let mut client = WebClient {
+            app,
+            container,
+            canvas,
+            gl,
+            renderer,
+            options,
+            size,
+            pixel_ratio,
+            resize_watcher: None,
+            context_watcher,
+            texture_waiters: Vec::new()
+        };
+        client.set_auto_resize(client.options.auto_resize)?;
+
+        Ok(client)
+    }
+
+    /// Resizes the canvas to `width` x `height` CSS pixels, rendering at
+    /// `dpr` device pixels per CSS pixel (capped by `maxPixelRatio`).
+    pub fn resize(&mut self, width: u32, height: u32, dpr: f64) -> Result<(), JsValue> {
+        let pixel_ratio = self.options.clamp_pixel_ratio(dpr);
+        canvas::resize_canvas(&self.canvas, width, height, pixel_ratio)?;
+        self.app.resize(self.gl.as_ref());
+        self.size = (width, height);
+        self.pixel_ratio = pixel_ratio;
+        Ok(())
+    }
+
+    /// Starts or stops following the size of the container element and the
+    /// window's device pixel ratio. Changes are applied in `update`.
+    pub fn set_auto_resize(&mut self, enabled: bool) -> Result<(), JsValue> {
+        self.resize_watcher = if enabled {
+            Some(ResizeWatcher::new(&self.container)?)
+        } else {
+            None
+        };
+        self.options.auto_resize = enabled;
+        Ok(())
     }
 
     pub fn start(&mut self) -> Result<(), JsValue> {
@@ -71,15 +162,280 @@ impl WebClient {
         Ok(())
     }
 
-    pub fn update(&mut self, dt: f64) {
-        self.app.update(dt);
+    pub fn update(&mut self, dt: f64) -> Result<(), JsValue> {
+        if let Some(watcher) = &self.resize_watcher {
+            let (width, height) = watcher.take().unwrap_or(self.size);
+            let device_pixel_ratio = window().map_or(self.pixel_ratio, |w| w.device_pixel_ratio());
+            let pixel_ratio = self.options.effective_pixel_ratio(device_pixel_ratio);
+            if (width, height) != self.size || pixel_ratio != self.pixel_ratio {
+                self.resize(width, height, pixel_ratio)?;
+            }
+        }
+
+        self.app.update(dt)?;
+        self.resolve_texture_waiters()
+    }
+
+    /// Returns a promise resolving once every globe map has either loaded
+    /// or failed to, e.g. to hide a loading indicator. It resolves with
+    /// `{ pending: 0, loaded: 6, failed: [] }`, with `{ src, reason }`
+    /// entries for the maps that failed, which show the
+    /// `texturePlaceholder` color.
+    ///
+    /// Progress is checked in `update`.
+    pub fn textures_ready(&mut self) -> Result<js_sys::Promise, JsValue> {
+        let waiters = &mut self.texture_waiters;
+        let promise = js_sys::Promise::new(&mut |resolve, _reject| waiters.push(resolve));
+        self.resolve_texture_waiters()?;
+        Ok(promise)
+    }
+
+    /// Forwards `pointerdown`. Call `setPointerCapture` on the canvas so the
+    /// matching `pointerup` is received.
+    pub fn pointer_down(&mut self, pointer_id: i32, x: f32, y: f32) {
+        self.app.get_orbit_mut().pointer_down(pointer_id, x, y);
+    }
+
+    /// Forwards `pointermove`. One pointer rotates the globe, two pinch-zoom.
+    pub fn pointer_move(&mut self, pointer_id: i32, x: f32, y: f32) {
+        self.app.get_orbit_mut().pointer_move(pointer_id, x, y);
+    }
+
+    /// Forwards `pointerup` and `pointercancel`.
+    pub fn pointer_up(&mut self, pointer_id: i32) {
+        self.app.get_orbit_mut().pointer_up(pointer_id);
+    }
+
+    /// Forwards the `deltaY` of a `wheel` event.
+    pub fn wheel(&mut self, delta_y: f32) {
+        self.app.get_orbit_mut().wheel(delta_y);
+    }
+
+    /// Flies the camera along a great circle to `altitude` above the given
+    /// latitude and longitude (in degrees), taking `duration` seconds.
+    pub fn fly_to(&mut self, latitude: f32, longitude: f32, altitude: f32, duration: f32) -> Result<(), JsValue> {
+        self.app.fly_to(&GeoPosition::new(latitude, longitude, altitude), duration)?;
+        Ok(())
+    }
+
+    /// Whether a `fly_to` animation is still running.
+    pub fn is_flying(&self) -> bool {
+        self.app.is_flying()
+    }
+
+    /// Shows the globe lit as at `utc_millis`, in milliseconds since the Unix
+    /// epoch (as returned by `Date.now()`). The clock then runs on from there.
+    pub fn set_time(&mut self, utc_millis: f64) {
+        self.app.set_time(utc_millis);
+    }
+
+    /// Sets a uniform of the globe shaders, e.g.
+    /// `setUniform("u_atmosphereColor", [0.6, 0.8, 1.0])`. Takes a number or
+    /// an array of numbers; see `UniformValue::from_slice`.
+    pub fn set_uniform(&mut self, name: &str, value: JsValue) -> Result<(), JsValue> {
+        let data: Vec<f32> = match value.as_f64() {
+            Some(x) => vec![x as f32],
+            None => serde_wasm_bindgen::from_value(value)
+                .map_err(|e| Error::InvalidUniform(e.to_string()))?
+        };
+        self.app.set_uniform(name, &data)?;
+        Ok(())
+    }
+
+    /// Draws a grid of data over the globe, wrapped around it like the color
+    /// map with rows from the north pole down, e.g.
+    /// `setOverlay("r32f", 360, 180, temperatures)`. `format` is one of
+    /// `"r8"`, `"rg8"`, `"rgba8"`, `"srgba8"`, `"r16f"`, `"r32f"` or
+    /// `"rgba16f"`, and `data` a typed array of matching values: a
+    /// `Uint8Array` for the 8-bit formats, a `Float32Array` or a
+    /// `Uint16Array` of half floats for the float ones.
+    ///
+    /// One channel is colored along `u_overlayRange` from `u_overlayLow`
+    /// to `u_overlayHigh`, and a second one is the alpha; see `setUniform`.
+    pub fn set_overlay(&mut self, format: &str, width: u32, height: u32, data: JsValue) -> Result<(), JsValue> {
+        let format = format.parse()?;
+        let gl = self.gl.as_ref();
+        with_texels!(texels(&data)?, |data| self.app.set_overlay(gl, format, width, height, &data))?;
+        Ok(())
+    }
+
+    /// Draws a JPEG or PNG file over the globe, wrapped around it like the
+    /// color map, e.g. `setOverlayImage(new Uint8Array(await response.arrayBuffer()))`.
+    /// Its alpha blends it with the surface, faded by `u_overlayOpacity`.
+    pub fn set_overlay_image(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        let image = Image::decode(bytes).map_err(Error::from)?;
+        self.app.set_overlay_image(self.gl.as_ref(), &image)?;
+        Ok(())
+    }
+
+    /// Replaces the `width` x `height` rectangle at `x`, `y` of the overlay
+    /// with `data`, of the same array type as the one it was set with.
+    pub fn update_overlay(&mut self, x: u32, y: u32, width: u32, height: u32, data: JsValue) -> Result<(), JsValue> {
+        let gl = self.gl.as_ref();
+        with_texels!(texels(&data)?, |data| self.app.update_overlay(gl, x, y, width, height, &data))?;
+        Ok(())
+    }
+
+    /// Shows dots over the globe, replacing the previous ones, e.g.
+    /// `setMarkers([{ latitude: 48.9, longitude: 2.4, color: [1, 0, 0, 1] }], false)`.
+    /// `altitude` defaults to 0 and `color` to orange. Pass `streaming` for
+    /// markers that are going to move about every frame.
+    pub fn set_markers(&mut self, markers: JsValue, streaming: bool) -> Result<(), JsValue> {
+        let markers: Vec<Marker> = serde_wasm_bindgen::from_value(markers)
+            .map_err(|e| Error::InvalidMarker(e.to_string()))?;
+        self.app.set_markers(self.gl.as_ref(), &markers, streaming)?;
+        Ok(())
     }
 
-    pub fn render(&self) -> Result<(), JsValue> {
+    /// Replaces the markers from index `first` on with `markers`, adding
+    /// the ones past the last. Only these markers are uploaded again.
+    pub fn move_markers(&mut self, first: usize, markers: JsValue) -> Result<(), JsValue> {
+        let markers: Vec<Marker> = serde_wasm_bindgen::from_value(markers)
+            .map_err(|e| Error::InvalidMarker(e.to_string()))?;
+        self.app.move_markers(self.gl.as_ref(), first, &markers)?;
+        Ok(())
+    }
+
+    /// Development aid: replaces the GLSL of the program `name`
+    /// (`"globe_earth"`, `"globe_clouds"`, `"globe_atmosphere"` or
+    /// `"markers"`) without
+    /// reloading the page. The sources may `#include` the common files.
+    ///
+    /// When they fail to compile the globe keeps drawing with the previous
+    /// program and the error carries the info log.
+    pub fn reload_shader(&mut self, name: &str, vs_source: &str, fs_source: &str) -> Result<(), JsValue> {
+        self.app.reload_shader(self.gl.as_ref(), name, vs_source, fs_source)?;
+        Ok(())
+    }
+
+    /// Turns a part of the Earth material on or off: one of `"lighting"`,
+    /// `"specular"`, `"bump"`, `"nightLights"`, `"clouds"` or `"atmosphere"`.
+    pub fn set_layer_enabled(&mut self, layer: &str, enabled: bool) -> Result<(), JsValue> {
+        self.app.set_layer_enabled(layer.parse()?, enabled)?;
+        Ok(())
+    }
+
+    /// Id of the root of the scene graph.
+    pub fn root_node(&self) -> String {
+        self.app.get_scene().root().to_string()
+    }
+
+    /// Id of the scene node holding the globe.
+    pub fn globe_node(&self) -> String {
+        self.app.globe_node().to_string()
+    }
+
+    /// Adds an empty node under `parent` and returns its id. Other nodes can
+    /// be moved under it to transform them together.
+    pub fn add_node(&mut self, parent: &str) -> Result<String, JsValue> {
+        let parent = NodeId::parse(parent)?;
+        let id = self.app.get_scene_mut().add(parent, Node::new())?;
+        Ok(id.to_string())
+    }
+
+    /// Removes a node and everything below it.
+    pub fn remove_node(&mut self, id: &str) -> Result<(), JsValue> {
+        self.app.get_scene_mut().remove(NodeId::parse(id)?)?;
+        Ok(())
+    }
+
+    pub fn reparent_node(&mut self, id: &str, parent: &str) -> Result<(), JsValue> {
+        self.app.get_scene_mut().reparent(NodeId::parse(id)?, NodeId::parse(parent)?)?;
+        Ok(())
+    }
+
+    pub fn set_node_translation(&mut self, id: &str, x: f32, y: f32, z: f32) -> Result<(), JsValue> {
+        self.app.get_scene_mut().set_translation(NodeId::parse(id)?, Vector3::new(x, y, z))?;
+        Ok(())
+    }
+
+    /// Sets the rotation of a node from a quaternion, normalizing it.
+    pub fn set_node_rotation(&mut self, id: &str, x: f32, y: f32, z: f32, w: f32) -> Result<(), JsValue> {
+        let rotation = UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z));
+        self.app.get_scene_mut().set_rotation(NodeId::parse(id)?, rotation)?;
+        Ok(())
+    }
+
+    pub fn set_node_scale(&mut self, id: &str, x: f32, y: f32, z: f32) -> Result<(), JsValue> {
+        self.app.get_scene_mut().set_scale(NodeId::parse(id)?, Vector3::new(x, y, z))?;
+        Ok(())
+    }
+
+    pub fn set_node_visible(&mut self, id: &str, visible: bool) -> Result<(), JsValue> {
+        self.app.get_scene_mut().set_visible(NodeId::parse(id)?, visible)?;
+        Ok(())
+    }
+
+    /// How far the globe maps have loaded, in the same form as the value
+    /// `textures_ready` resolves with.
+    pub fn texture_status(&self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.app.texture_report())?)
+    }
+
+    /// Live GPU objects and the bytes they hold, e.g.
+    /// `{ buffers: 8, textures: 6, samplers: 1, programs: 4, vertexArrays: 4,
+    /// uniformLocations: 40, bytes: 5242880 }`.
+    /// Objects are deleted at the start of the frame after their last owner
+    /// went away.
+    pub fn resource_stats(&self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.gl.resources().stats())?)
+    }
+
+    /// Draws a frame. Nothing is drawn while the WebGL context is lost;
+    /// once it is restored, the GPU objects are rebuilt first.
+    pub fn render(&mut self) -> Result<(), JsValue> {
+        match self.context_watcher.state() {
+            ContextState::Ready => {},
+            ContextState::Lost => return Ok(()),
+            ContextState::Restored => {
+                self.gl.reset();
+                self.app.restore(self.gl.as_ref())?;
+                self.renderer.init(self.gl.as_ref())?;
+                self.context_watcher.set_ready();
+            }
+        }
+
         self.renderer.render(
             self.gl.as_ref(),
             self.app.get_camera(),
-            self.app.get_renderables()
+            self.app.get_scene(),
+            self.app.time() as f32,
+            self.app.sun_direction()
         )
     }
 }
+
+
+impl WebClient {
+    /// Resolves the `textures_ready` promises once no map is pending.
+    fn resolve_texture_waiters(&mut self) -> Result<(), JsValue> {
+        if self.texture_waiters.is_empty() {
+            return Ok(());
+        }
+        let report = self.app.texture_report();
+        if !report.is_settled() {
+            return Ok(());
+        }
+        let value = serde_wasm_bindgen::to_value(&report)?;
+        for resolve in self.texture_waiters.drain(..) {
+            resolve.call1(&JsValue::NULL, &value)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// The values of a typed array, for uploads to data textures.
+fn texels(data: &JsValue) -> Result<Texels, Error> {
+    if let Some(array) = data.dyn_ref::<js_sys::Uint8Array>() {
+        Ok(Texels::U8(array.to_vec()))
+    } else if let Some(array) = data.dyn_ref::<js_sys::Uint16Array>() {
+        Ok(Texels::U16(array.to_vec()))
+    } else if let Some(array) = data.dyn_ref::<js_sys::Uint32Array>() {
+        Ok(Texels::U32(array.to_vec()))
+    } else if let Some(array) = data.dyn_ref::<js_sys::Float32Array>() {
+        Ok(Texels::F32(array.to_vec()))
+    } else {
+        Err(Error::TextureUpdate("texture data has to be a typed array".to_string()))
+    }
+}
@@ -0,0 +1,302 @@
+use js_sys::Reflect;
+use serde::Deserialize;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::HtmlElement;
+
+use crate::error::{Error, Result};
+use crate::render::{GlobeMaps, MagFilter, MinFilter, TextureOptions, TextureWrap};
+
+
+/// Where the canvas gets inserted: an element id, or the element itself.
+///
+/// An id that does not match any element creates a new `div` with that id at
+/// the end of the document body.
+#[derive(Clone, Debug)]
+pub enum Container {
+    Id(String),
+    Element(HtmlElement)
+}
+
+impl Default for Container {
+    fn default() -> Self {
+        Container::Id("rust-webgl".to_string())
+    }
+}
+
+
+/// Options accepted by `WebClient::new`, parsed from a plain JS object.
+///
+/// Every field is optional and uses camelCase on the JS side, e.g.
+/// `{ container: "globe", width: 640, assetBaseUrl: "https://cdn/", camera: { fov: 45 } }`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct WebClientOptions {
+    #[serde(skip)]
+    pub container: Container,
+    /// Canvas size in CSS pixels.
+    pub width: u32,
+    pub height: u32,
+    /// Ratio of backing store pixels to CSS pixels. Follows
+    /// `window.devicePixelRatio` when not set.
+    pub pixel_ratio: Option<f64>,
+    /// Upper bound applied to the pixel ratio, to cap the cost of very dense
+    /// screens.
+    pub max_pixel_ratio: f64,
+    /// Follow the size of the container with a `ResizeObserver`, instead of
+    /// waiting for `WebClient::resize` calls.
+    pub auto_resize: bool,
+    /// Prefix for relative asset paths such as the globe texture.
+    pub asset_base_url: String,
+    pub clear_color: [f32; 4],
+    /// RGBA color textures show while their image loads, and keep when it
+    /// fails to load.
+    pub texture_placeholder: [f32; 4],
+    pub camera: CameraOptions,
+    pub globe: GlobeOptions
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CameraOptions {
+    /// Vertical field of view in degrees.
+    pub fov: f32,
+    pub near: f32,
+    pub far: f32,
+    pub position: [f32; 3],
+    pub target: [f32; 3]
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct GlobeOptions {
+    pub radius: f32,
+    pub width_segments: u32,
+    pub height_segments: u32,
+    /// Color map. Like the other maps, relative to `asset_base_url` unless
+    /// absolute.
+    pub texture: String,
+    /// Left out when empty, like the bump and night lights maps.
+    pub specular_map: String,
+    pub bump_map: String,
+    pub night_lights_map: String,
+    pub cloud_map: String,
+    pub cloud_alpha_map: String,
+    pub bump_scale: f32,
+    /// Anisotropic filtering of the maps, which keeps them sharp towards
+    /// the horizon. Capped by what the GPU supports; 1 turns it off.
+    pub anisotropy: f32,
+    /// Wrapping of the maps in longitude and latitude, e.g. `"repeat"`,
+    /// `"clampToEdge"` or `"mirroredRepeat"`.
+    pub wrap_s: TextureWrap,
+    pub wrap_t: TextureWrap,
+    /// Filtering of the maps, e.g. `"linearMipmapLinear"` or `"nearest"`.
+    pub min_filter: MinFilter,
+    pub mag_filter: MagFilter,
+    /// Drift of the cloud cover, in degrees of longitude per second.
+    pub cloud_speed: f32,
+    /// Names of the layers to start with disabled, see `GlobeLayer`.
+    pub disabled_layers: Vec<String>
+}
+
+impl Default for WebClientOptions {
+    fn default() -> Self {
+        WebClientOptions {
+            container: Container::default(),
+            width: 1024,
+            height: 1024,
+            pixel_ratio: None,
+            max_pixel_ratio: 2.0,
+            auto_resize: false,
+            asset_base_url: "/data/".to_string(),
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            texture_placeholder: [0.5, 0.5, 0.5, 1.0],
+            camera: CameraOptions::default(),
+            globe: GlobeOptions::default()
+        }
+    }
+}
+
+impl Default for CameraOptions {
+    fn default() -> Self {
+        CameraOptions {
+            fov: 60.0,
+            near: 1.0,
+            far: 1000.0,
+            position: [0.0, 0.0, 500.0],
+            target: [0.0, 0.0, 0.0]
+        }
+    }
+}
+
+impl Default for GlobeOptions {
+    fn default() -> Self {
+        GlobeOptions {
+            radius: 200.0,
+            width_segments: 40,
+            height_segments: 30,
+            texture: "world.jpg".to_string(),
+            specular_map: "earthspec1k.jpg".to_string(),
+            bump_map: "earthbump1k.jpg".to_string(),
+            night_lights_map: "earthlights1k.jpg".to_string(),
+            cloud_map: "earthcloudmap.jpg".to_string(),
+            cloud_alpha_map: "earthcloudmaptrans.jpg".to_string(),
+            bump_scale: 3.0,
+            anisotropy: 8.0,
+            // The maps wrap around in longitude.
+            wrap_s: TextureWrap::Repeat,
+            wrap_t: TextureWrap::ClampToEdge,
+            min_filter: MinFilter::LinearMipmapLinear,
+            mag_filter: MagFilter::Linear,
+            cloud_speed: 0.2,
+            disabled_layers: Vec::new()
+        }
+    }
+}
+
+impl WebClientOptions {
+    /// Parses the options object handed over from JS. `undefined` and `null`
+    /// give the defaults.
+    pub fn from_js(value: &JsValue) -> Result<Self> {
+        if value.is_undefined() || value.is_null() {
+            return Ok(WebClientOptions::default());
+        }
+
+        let mut options: WebClientOptions = serde_wasm_bindgen::from_value(value.clone())
+            .map_err(|e| Error::InvalidOptions(e.to_string()))?;
+
+        let container = Reflect::get(value, &JsValue::from_str("container"))?;
+        if let Some(id) = container.as_string() {
+            options.container = Container::Id(id);
+        } else if container.is_instance_of::<HtmlElement>() {
+            options.container = Container::Element(container.unchecked_into());
+        } else if !container.is_undefined() && !container.is_null() {
+            return Err(Error::InvalidOptions(
+                "container must be an element id or an HTMLElement".to_string()
+            ));
+        }
+
+        Ok(options)
+    }
+
+    /// Pixel ratio to render at, given the one reported by the window.
+    pub fn effective_pixel_ratio(&self, device_pixel_ratio: f64) -> f64 {
+        self.clamp_pixel_ratio(self.pixel_ratio.unwrap_or(device_pixel_ratio))
+    }
+
+    /// Applies `max_pixel_ratio` to a pixel ratio chosen by the caller.
+    pub fn clamp_pixel_ratio(&self, pixel_ratio: f64) -> f64 {
+        pixel_ratio.min(self.max_pixel_ratio).max(f64::MIN_POSITIVE)
+    }
+
+    /// Resolves `path` against `asset_base_url`. Absolute paths and URLs are
+    /// returned unchanged.
+    pub fn asset_url(&self, path: &str) -> String {
+        if path.starts_with('/') || path.contains("://") || self.asset_base_url.is_empty() {
+            path.to_string()
+        } else {
+            format!("{}/{}", self.asset_base_url.trim_end_matches('/'), path)
+        }
+    }
+
+    fn optional_asset_url(&self, path: &str) -> Option<String> {
+        if path.is_empty() {
+            None
+        } else {
+            Some(self.asset_url(path))
+        }
+    }
+
+    /// URLs of the globe maps.
+    pub fn globe_maps(&self) -> GlobeMaps {
+        let globe = &self.globe;
+        GlobeMaps {
+            color: self.asset_url(&globe.texture),
+            specular: self.optional_asset_url(&globe.specular_map),
+            bump: self.optional_asset_url(&globe.bump_map),
+            night_lights: self.optional_asset_url(&globe.night_lights_map),
+            clouds: self.asset_url(&globe.cloud_map),
+            cloud_alpha: self.asset_url(&globe.cloud_alpha_map),
+            texture_options: TextureOptions {
+                wrap_s: globe.wrap_s,
+                wrap_t: globe.wrap_t,
+                min_filter: globe.min_filter,
+                mag_filter: globe.mag_filter,
+                anisotropy: globe.anisotropy,
+                placeholder: self.texture_placeholder.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8),
+                ..TextureOptions::default()
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_take_defaults() {
+        let options: WebClientOptions = serde_json::from_str(r#"{
+            "width": 640,
+            "assetBaseUrl": "https://cdn.example.com/globe",
+            "clearColor": [0.1, 0.2, 0.3, 1.0],
+            "camera": { "fov": 45 },
+            "globe": { "widthSegments": 64, "texture": "earthmap1k.jpg" }
+        }"#).unwrap();
+
+        assert_eq!(options.width, 640);
+        assert_eq!(options.height, 1024);
+        assert_eq!(options.clear_color, [0.1, 0.2, 0.3, 1.0]);
+        assert_eq!(options.camera, CameraOptions { fov: 45.0, ..CameraOptions::default() });
+        assert_eq!(options.globe.width_segments, 64);
+        assert_eq!(options.globe.height_segments, 30);
+        assert_eq!(
+            options.asset_url(&options.globe.texture),
+            "https://cdn.example.com/globe/earthmap1k.jpg"
+        );
+    }
+
+    #[test]
+    fn absolute_asset_paths_are_kept() {
+        let options = WebClientOptions::default();
+        assert_eq!(options.asset_url("world.jpg"), "/data/world.jpg");
+        assert_eq!(options.asset_url("/img/world.jpg"), "/img/world.jpg");
+        assert_eq!(options.asset_url("https://a.b/world.jpg"), "https://a.b/world.jpg");
+    }
+
+    #[test]
+    fn map_sampling_is_configurable() {
+        let options: WebClientOptions = serde_json::from_str(r#"{
+            "globe": { "wrapT": "mirroredRepeat", "minFilter": "nearestMipmapLinear", "magFilter": "nearest" }
+        }"#).unwrap();
+
+        let texture_options = options.globe_maps().texture_options;
+        assert_eq!(texture_options.wrap_s, TextureWrap::Repeat);
+        assert_eq!(texture_options.wrap_t, TextureWrap::MirroredRepeat);
+        assert_eq!(texture_options.min_filter, MinFilter::NearestMipmapLinear);
+        assert_eq!(texture_options.mag_filter, MagFilter::Nearest);
+        assert!(serde_json::from_str::<WebClientOptions>(r#"{ "globe": { "magFilter": "cubic" } }"#).is_err());
+    }
+
+    #[test]
+    fn empty_optional_maps_are_left_out() {
+        let mut options = WebClientOptions::default();
+        options.globe.bump_map = String::new();
+
+        let maps = options.globe_maps();
+        assert_eq!(maps.bump, None);
+        assert_eq!(maps.specular.as_deref(), Some("/data/earthspec1k.jpg"));
+        assert_eq!(maps.texture_options.placeholder, [128, 128, 128, 255]);
+        assert_eq!(maps.texture_options.anisotropy, 8.0);
+    }
+
+    #[test]
+    fn pixel_ratio_is_capped() {
+        let mut options = WebClientOptions::default();
+        assert_eq!(options.effective_pixel_ratio(1.5), 1.5);
+        assert_eq!(options.effective_pixel_ratio(3.0), 2.0);
+
+        options.pixel_ratio = Some(1.0);
+        assert_eq!(options.effective_pixel_ratio(3.0), 1.0);
+    }
+}
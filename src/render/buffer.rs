@@ -0,0 +1,190 @@
+use web_sys::WebGl2RenderingContext as GL;
+
+use crate::backend::{ArrayData, BufferId, GlBackend, Handle, Resource};
+use crate::error::{Error, Result};
+
+
+/// How often the contents of a buffer change, passed to WebGL as a hint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferUsage {
+    /// Set once and drawn many times.
+    Static,
+    /// Changed now and then, and drawn many times.
+    Dynamic,
+    /// Changed about every time it is drawn.
+    Stream
+}
+
+impl BufferUsage {
+    pub fn to_gl(self) -> u32 {
+        match self {
+            BufferUsage::Static => GL::STATIC_DRAW,
+            BufferUsage::Dynamic => GL::DYNAMIC_DRAW,
+            BufferUsage::Stream => GL::STREAM_DRAW
+        }
+    }
+}
+
+
+/// A buffer object that remembers its size, so that its contents can be
+/// replaced or updated in place.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Buffer {
+    handle: Handle<BufferId>,
+    target: u32,
+    usage: BufferUsage,
+    /// Bytes written so far, up to the capacity.
+    len: usize,
+    /// In bytes.
+    capacity: usize
+}
+
+impl Buffer {
+    /// Creates a buffer bound to `target` and fills it with `data`.
+    pub fn new(gl: &dyn GlBackend, target: u32, data: ArrayData, usage: BufferUsage) -> Result<Self> {
+        let id = gl.create_buffer()
+            .ok_or(Error::ObjectCreation("buffer"))?;
+        let mut buffer = Buffer { handle: gl.resources().track(id), target, usage, len: 0, capacity: 0 };
+        buffer.replace(gl, data, usage)?;
+        Ok(buffer)
+    }
+
+    pub fn id(&self) -> BufferId {
+        self.handle.id()
+    }
+
+    pub fn usage(&self) -> BufferUsage {
+        self.usage
+    }
+
+    /// Bytes of data in the buffer, which can be less than its capacity
+    /// once it has grown.
+    pub fn byte_len(&self) -> usize {
+        self.len
+    }
+
+    /// Replaces the whole contents with `data`, which sets the size of the
+    /// buffer. Leaves the buffer bound to its target.
+    pub fn replace(&mut self, gl: &dyn GlBackend, data: ArrayData, usage: BufferUsage) -> Result<()> {
+        gl.bind_buffer(self.target, Some(self.id()));
+        gl.buffer_data(self.target, data, usage.to_gl());
+        check_memory(gl)?;
+        self.usage = usage;
+        self.len = data.byte_len();
+        self.capacity = self.len;
+        gl.resources().set_size(Resource::Buffer(self.id()), self.capacity);
+        Ok(())
+    }
+
+    /// Writes `data` at `offset` bytes. When it does not fit, the contents
+    /// are first copied into a new buffer of at least twice the size, and
+    /// the old one is released. Leaves the buffer bound to its target.
+    ///
+    /// Returns whether the buffer was replaced: vertex attributes have to be
+    /// pointed at the new one.
+    pub fn write(&mut self, gl: &dyn GlBackend, offset: usize, data: ArrayData) -> Result<bool> {
+        let end = offset + data.byte_len();
+        let grown = end > self.capacity;
+        if grown {
+            self.grow(gl, end.max(self.capacity * 2))?;
+        } else {
+            gl.bind_buffer(self.target, Some(self.id()));
+        }
+        gl.buffer_sub_data(self.target, offset as i32, data);
+        self.len = self.len.max(end);
+        Ok(grown)
+    }
+
+    fn grow(&mut self, gl: &dyn GlBackend, capacity: usize) -> Result<()> {
+        let handle = gl.create_buffer()
+            .map(|id| gl.resources().track(id))
+            .ok_or(Error::ObjectCreation("buffer"))?;
+        gl.bind_buffer(self.target, Some(handle.id()));
+        gl.buffer_data_size(self.target, capacity as i32, self.usage.to_gl());
+        check_memory(gl)?;
+        gl.resources().set_size(Resource::Buffer(handle.id()), capacity);
+
+        gl.bind_buffer(GL::COPY_READ_BUFFER, Some(self.id()));
+        gl.copy_buffer_sub_data(GL::COPY_READ_BUFFER, self.target, 0, 0, self.capacity as i32);
+        gl.bind_buffer(GL::COPY_READ_BUFFER, None);
+
+        // The old buffer goes with its handle.
+        self.handle = handle;
+        self.capacity = capacity;
+        Ok(())
+    }
+}
+
+
+fn check_memory(gl: &dyn GlBackend) -> Result<()> {
+    match gl.get_error() {
+        GL::OUT_OF_MEMORY => Err(Error::OutOfMemory),
+        _ => Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{GlCall, MockBackend};
+
+    #[test]
+    fn writes_that_fit_update_in_place() {
+        let gl = MockBackend::new();
+        let mut buffer = Buffer::new(&gl, GL::ARRAY_BUFFER, ArrayData::F32(&[0.0; 8]), BufferUsage::Dynamic).unwrap();
+        gl.clear_calls();
+
+        assert_eq!(buffer.write(&gl, 16, ArrayData::F32(&[1.0; 4])), Ok(false));
+        assert_eq!(gl.calls(), vec![
+            GlCall::BindBuffer(GL::ARRAY_BUFFER, Some(buffer.id())),
+            GlCall::BufferSubData { target: GL::ARRAY_BUFFER, offset: 16, data_type: GL::FLOAT, len: 4 }
+        ]);
+    }
+
+    #[test]
+    fn writes_past_the_end_grow_the_buffer() {
+        let gl = MockBackend::new();
+        let mut buffer = Buffer::new(&gl, GL::ARRAY_BUFFER, ArrayData::F32(&[0.0; 8]), BufferUsage::Stream).unwrap();
+        let old = buffer.id();
+        gl.clear_calls();
+
+        assert_eq!(buffer.write(&gl, 24, ArrayData::F32(&[1.0; 4])), Ok(true));
+        let new = buffer.id();
+        assert_ne!(new, old);
+        assert_eq!(gl.calls(), vec![
+            GlCall::CreateBuffer(new),
+            GlCall::BindBuffer(GL::ARRAY_BUFFER, Some(new)),
+            GlCall::BufferDataSize { target: GL::ARRAY_BUFFER, size: 64, usage: GL::STREAM_DRAW },
+            GlCall::BindBuffer(GL::COPY_READ_BUFFER, Some(old)),
+            GlCall::CopyBufferSubData {
+                read_target: GL::COPY_READ_BUFFER,
+                write_target: GL::ARRAY_BUFFER,
+                read_offset: 0,
+                write_offset: 0,
+                size: 32
+            },
+            GlCall::BindBuffer(GL::COPY_READ_BUFFER, None),
+            GlCall::BufferSubData { target: GL::ARRAY_BUFFER, offset: 24, data_type: GL::FLOAT, len: 4 }
+        ]);
+
+        assert_eq!(gl.resources().stats().bytes, 64);
+        gl.delete_released();
+        assert_eq!(gl.calls().last(), Some(&GlCall::DeleteBuffer(old)));
+    }
+
+    #[test]
+    fn replacing_sets_the_usage() {
+        let gl = MockBackend::new();
+        let mut buffer = Buffer::new(&gl, GL::ELEMENT_ARRAY_BUFFER, ArrayData::U16(&[0, 1, 2]), BufferUsage::Static).unwrap();
+
+        buffer.replace(&gl, ArrayData::U16(&[0, 1, 2, 2, 1, 3]), BufferUsage::Dynamic).unwrap();
+        assert_eq!(buffer.usage(), BufferUsage::Dynamic);
+        assert_eq!(gl.calls().last(), Some(&GlCall::BufferData {
+            target: GL::ELEMENT_ARRAY_BUFFER,
+            data_type: GL::UNSIGNED_SHORT,
+            len: 6,
+            usage: GL::DYNAMIC_DRAW
+        }));
+    }
+}
@@ -1,15 +1,13 @@
-use nalgebra::{Perspective3, Isometry3, Vector3, Point3, Projective3, Matrix4, Transform3};
+use nalgebra::{Perspective3, Isometry3, Vector3, Point3, Transform3};
 
+/// The field of view, aspect ratio and clipping planes live in the
+/// projection.
 #[derive(Clone)]
 pub struct Camera {
     position: Point3<f32>,
     target: Point3<f32>,
     view: Transform3<f32>,
-    projection: Perspective3<f32>,
-    vfov: f32,
-    aspect_ratio: f32,
-    near: f32,
-    far: f32
+    projection: Perspective3<f32>
 }
 
 impl Camera {
@@ -20,7 +18,7 @@ impl Camera {
             Isometry3::look_at_rh(&position, &target, &Vector3::y()).to_homogeneous()
         );
         let projection = Perspective3::new(aspect_ratio, vfov, near, far);
-        Camera { position, target, view, projection, vfov, aspect_ratio, near, far }
+        Camera { position, target, view, projection }
     }
 
     pub fn set_position(&mut self, x: f32, y: f32, z: f32) {
@@ -33,6 +31,19 @@ impl Camera {
         self.update();
     }
 
+    pub fn position(&self) -> &Point3<f32> {
+        &self.position
+    }
+
+    pub fn target(&self) -> &Point3<f32> {
+        &self.target
+    }
+
+    /// Updates the projection for a viewport of the given width / height.
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.projection.set_aspect(aspect_ratio);
+    }
+
     pub fn view(&self) -> &Transform3<f32> {
         &self.view
     }
@@ -51,3 +62,22 @@ impl Camera {
         );
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aspect_ratio_updates_the_projection() {
+        let mut camera = Camera::new(1.0, 1.0, 1.0, 1000.0);
+        let square = *camera.projection().as_matrix();
+
+        camera.set_aspect_ratio(2.0);
+        let wide = camera.projection().as_matrix();
+
+        assert_eq!(camera.projection().aspect(), 2.0);
+        assert!((wide[(0, 0)] - square[(0, 0)] / 2.0).abs() < 1e-6);
+        assert_eq!(wide[(1, 1)], square[(1, 1)]);
+    }
+}
@@ -0,0 +1,120 @@
+use nalgebra::{Matrix4, Point3, Unit, Vector3};
+use web_sys::WebGl2RenderingContext as GL;
+
+use crate::backend::{ArrayData, BufferId, GlBackend, Handle, Resource};
+use crate::error::{Error, Result};
+use crate::shader::FRAME_UNIFORMS_BINDING;
+use super::Camera;
+
+
+/// Values shared by every draw of a frame, uploaded once per frame into the
+/// uniform buffer behind the `FrameUniforms` block:
+///
+/// ```glsl
+/// layout(std140) uniform FrameUniforms {
+///     mat4 u_view;
+///     mat4 u_projection;
+///     mat4 u_viewProjection;
+///     vec3 u_cameraPosition;
+///     float u_time;
+///     vec2 u_viewportSize;
+///     vec3 u_sunDirection;
+/// };
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameUniforms {
+    pub view: Matrix4<f32>,
+    pub projection: Matrix4<f32>,
+    pub camera_position: Point3<f32>,
+    /// Seconds since the client started.
+    pub time: f32,
+    /// Drawing buffer size, in pixels.
+    pub viewport_size: (i32, i32),
+    /// World space direction towards the sun.
+    pub sun_direction: Unit<Vector3<f32>>
+}
+
+impl FrameUniforms {
+    /// Size of the block in floats, following the std140 layout rules.
+    pub const LEN: usize = 60;
+
+    pub fn new(
+        camera: &Camera,
+        viewport_size: (i32, i32),
+        time: f32,
+        sun_direction: Unit<Vector3<f32>>
+    ) -> Self {
+        FrameUniforms {
+            view: camera.view().to_homogeneous(),
+            projection: camera.projection().to_homogeneous(),
+            camera_position: *camera.position(),
+            time,
+            viewport_size,
+            sun_direction
+        }
+    }
+
+    /// The block contents in std140 layout: matrices are column-major, and
+    /// a `vec3` is aligned like a `vec4` but lets a scalar follow it.
+    pub fn to_std140(&self) -> [f32; FrameUniforms::LEN] {
+        let mut data = [0.0; FrameUniforms::LEN];
+        data[0..16].copy_from_slice(self.view.as_slice());
+        data[16..32].copy_from_slice(self.projection.as_slice());
+        data[32..48].copy_from_slice((self.projection * self.view).as_slice());
+        data[48..51].copy_from_slice(self.camera_position.coords.as_slice());
+        data[51] = self.time;
+        data[52] = self.viewport_size.0 as f32;
+        data[53] = self.viewport_size.1 as f32;
+        // 54..56 is padding, the vec3 starts on a 16 byte boundary.
+        data[56..59].copy_from_slice(self.sun_direction.as_slice());
+        data
+    }
+}
+
+
+/// Uniform buffer bound to `FRAME_UNIFORMS_BINDING`.
+#[derive(Clone, Debug)]
+pub struct FrameUniformBuffer {
+    buffer: Handle<BufferId>
+}
+
+impl FrameUniformBuffer {
+    pub fn new(gl: &dyn GlBackend) -> Result<Self> {
+        let buffer = gl.create_buffer()
+            .map(|buffer| gl.resources().track(buffer))
+            .ok_or(Error::ObjectCreation("uniform buffer"))?;
+        gl.resources().set_size(Resource::Buffer(buffer.id()), FrameUniforms::LEN * 4);
+        Ok(FrameUniformBuffer { buffer })
+    }
+
+    /// Replaces the buffer contents and binds it for the following draws.
+    pub fn upload(&self, gl: &dyn GlBackend, uniforms: &FrameUniforms) {
+        gl.bind_buffer(GL::UNIFORM_BUFFER, Some(self.buffer.id()));
+        gl.buffer_data(GL::UNIFORM_BUFFER, ArrayData::F32(&uniforms.to_std140()), GL::DYNAMIC_DRAW);
+        gl.bind_buffer(GL::UNIFORM_BUFFER, None);
+        gl.bind_buffer_base(GL::UNIFORM_BUFFER, FRAME_UNIFORMS_BINDING, Some(self.buffer.id()));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn std140_layout() {
+        let mut camera = Camera::new(1.0, 1.0, 1.0, 1000.0);
+        camera.set_position(1.0, 2.0, 3.0);
+        let sun = Unit::new_normalize(Vector3::new(0.0, 1.0, 0.0));
+        let uniforms = FrameUniforms::new(&camera, (1920, 1080), 2.5, sun);
+        let data = uniforms.to_std140();
+
+        assert_eq!(&data[0..16], camera.view().to_homogeneous().as_slice());
+        assert_eq!(&data[16..32], camera.projection().to_homogeneous().as_slice());
+        let view_projection = camera.projection().to_homogeneous() * camera.view().to_homogeneous();
+        assert_eq!(&data[32..48], view_projection.as_slice());
+        assert_eq!(&data[48..52], &[1.0, 2.0, 3.0, 2.5]);
+        assert_eq!(&data[52..54], &[1920.0, 1080.0]);
+        assert_eq!(&data[56..60], &[0.0, 1.0, 0.0, 0.0]);
+    }
+}
@@ -1,172 +1,733 @@
-use std::rc::Rc;
-use std::vec::Vec;
-use std::f32::consts::PI;
-use web_sys::*;
-use web_sys::WebGl2RenderingContext as GL;
-use js_sys::*;
-use nalgebra::{Isometry3, Matrix4, MatrixMN, Vector3, Perspective3, Matrix3, Rotation3, Transform3, Similarity3};
+use std::fmt;
+use std::str::FromStr;
+use nalgebra::{Vector3, Transform3, Similarity3};
 
-use crate::render::{Render, Camera, Renderable};
-use crate::shader::Shader;
-use crate::utils::get_memory_buffer;
+use crate::backend::GlBackend;
+use crate::error::{Error, Result};
+use crate::geometry::Mesh;
+use crate::image::Image;
+use crate::render::{pack, BufferUsage, CreateArray, Render, Camera, Marker, Markers, Renderable, Sampler};
+use crate::render::{Texture, TextureFormat, TextureOptions, TextureWrap, VertexLayout};
+use crate::shader::{ShaderCache, UniformValue};
 
-static GLOBE_ATMOSPHERE_VS: &'static str = include_str!("../shader/globe_atmosphere_vs.glsl");
-static GLOBE_ATMOSPHERE_FS: &'static str = include_str!("../shader/globe_atmosphere_fs.glsl");
-static GLOBE_EARTH_VS: &'static str = include_str!("../shader/globe_earth_vs.glsl");
-static GLOBE_EARTH_FS: &'static str = include_str!("../shader/globe_earth_fs.glsl");
+
+
+/// Parts of the Earth material that can be switched on and off.
+///
+/// The discriminants are the bits of the `u_layers` uniform read by the
+/// globe shaders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GlobeLayer {
+    /// Shading by the sun. When off the color map is shown unlit.
+    Lighting = 1,
+    /// Sun glint on the oceans.
+    Specular = 2,
+    /// Relief from the bump map.
+    Bump = 4,
+    /// City lights on the night side.
+    NightLights = 8,
+    /// The cloud shell.
+    Clouds = 16,
+    /// The atmosphere shell and rim.
+    Atmosphere = 32
+}
+
+impl GlobeLayer {
+    const ALL: [GlobeLayer; 6] = [
+        GlobeLayer::Lighting,
+        GlobeLayer::Specular,
+        GlobeLayer::Bump,
+        GlobeLayer::NightLights,
+        GlobeLayer::Clouds,
+        GlobeLayer::Atmosphere
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            GlobeLayer::Lighting => "lighting",
+            GlobeLayer::Specular => "specular",
+            GlobeLayer::Bump => "bump",
+            GlobeLayer::NightLights => "nightLights",
+            GlobeLayer::Clouds => "clouds",
+            GlobeLayer::Atmosphere => "atmosphere"
+        }
+    }
+}
+
+impl fmt::Display for GlobeLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for GlobeLayer {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        GlobeLayer::ALL.iter()
+            .copied()
+            .find(|layer| layer.name() == name)
+            .ok_or_else(|| Error::UnknownLayer(name.to_string()))
+    }
+}
+
+
+/// URLs of the maps making up the Earth material. The color, bump, specular
+/// and night lights maps share the same equirectangular layout; the shader
+/// leaves out the parts whose optional map is missing.
+#[derive(Clone, Debug)]
+pub struct GlobeMaps {
+    pub color: String,
+    pub specular: Option<String>,
+    pub bump: Option<String>,
+    pub night_lights: Option<String>,
+    pub clouds: String,
+    /// Cloud cover, black where the sky is overcast.
+    pub cloud_alpha: String,
+    /// Sampling of every map. The maps always wrap around in longitude.
+    pub texture_options: TextureOptions
+}
 
 
 #[derive(Clone)]
 pub struct Globe {
-    positions: Vec<f32>,
-    normals: Vec<f32>,
-    uvs: Vec<f32>,
-    indices: Vec<u16>,
+    mesh: Mesh,
     earth: Renderable,
+    clouds: Renderable,
     atmosphere: Renderable,
-    earth_xform: Transform3<f32>,
-    atmosphere_xform: Transform3<f32>
+    markers: Markers,
+    clouds_xform: Transform3<f32>,
+    atmosphere_xform: Transform3<f32>,
+    layers: u32,
+    cloud_offset: f32,
+    /// Drift of the cloud cover, in degrees of longitude per second.
+    pub cloud_speed: f32
 }
 
 impl Globe {
-    pub fn new(gl: Rc<GL>,
-               radius: f32,
-               width_segments: u32,
-               height_segments: u32
-    ) -> Self {
-
-        let width_segments = width_segments.max(3);
-        let height_segments = height_segments.max(2);
-
-        let mut positions: Vec<f32> = Vec::new();
-        let mut normals: Vec<f32> = Vec::new();
-        let mut uvs: Vec<f32> = Vec::new();
-        let mut indices: Vec<u16> = Vec::new();
-
-        let mut index: u16 = 0;
-        let mut grid: Vec<Vec<u16>> = Vec::new();
-
-        for iy in 0..=height_segments {
-            let mut vertices_row: Vec<u16> = Vec::new();
-
-            let v = iy as f32 / height_segments as f32;
-
-            let u_offset = if iy == 0 {
-                0.5 / width_segments as f32
-            } else if iy == height_segments {
-                - 0.5 / width_segments as f32
-            } else {
-                0.0
-            };
-
-            for ix in 0..=width_segments {
-                let u = ix as f32 / width_segments as f32;
-
-                let vtx = Vector3::new(
-                    -radius * (u * 2.0 * PI).cos() * (v * PI).sin(),
-                    radius * (v * PI).cos(),
-                    radius * (u * 2.0 * PI).sin() * (v * PI).sin()
-                );
-
-                positions.push(vtx.x);
-                positions.push(vtx.y);
-                positions.push(vtx.z);
-
-                let n = vtx.normalize();
-                normals.push(n.x);
-                normals.push(n.y);
-                normals.push(n.z);
-
-                uvs.push(u + u_offset);
-                uvs.push(v);
-
-                vertices_row.push(index);
-                index += 1;
-            }
+    pub fn new(gl: &dyn GlBackend, shaders: &mut ShaderCache, mesh: Mesh, maps: &GlobeMaps) -> Result<Self> {
+        let texture_options = maps.texture_options;
+        let sampler = Sampler::new(gl, &texture_options)?;
+        let map = |gl, renderable: &mut Renderable, src: &str, uniform: &str| -> Result<()> {
+            renderable.texture(gl, src, uniform, &texture_options)?;
+            renderable.set_sampler(uniform, &sampler)
+        };
 
-            grid.push(vertices_row);
+        // Earth
+        let mut earth_defines = vec![("HAS_UV", ""), ("HAS_TANGENTS", "")];
+        let optional_maps = [
+            (&maps.specular, "s_specular", "HAS_SPECULAR_MAP"),
+            (&maps.bump, "s_bump", "HAS_BUMP_MAP"),
+            (&maps.night_lights, "s_nightLights", "HAS_NIGHT_LIGHTS")
+        ];
+        for (map, _, define) in optional_maps.iter() {
+            if map.is_some() {
+                earth_defines.push((define, ""));
+            }
         }
-
-        // indices
-        for iy in 0..(height_segments as usize) {
-            for ix in 0..(width_segments as usize) {
-                let a = grid[iy][ix + 1];
-                let b = grid[iy][ix];
-                let c = grid[iy + 1][ix];
-                let d = grid[iy + 1][ix + 1];
-
-                if iy != 0 {
-                    indices.push(a);
-                    indices.push(b);
-                    indices.push(d);
-                }
-
-                if iy != (height_segments as usize - 1) {
-                    indices.push(b);
-                    indices.push(c);
-                    indices.push(d);
-                }
+        let mut earth = Renderable::new(
+            gl,
+            shaders.get(gl, "globe_earth", &earth_defines)?
+        )?;
+        map(gl, &mut earth, &maps.color, "s_texture")?;
+        for (src, uniform, _) in optional_maps.iter() {
+            if let Some(src) = src {
+                map(gl, &mut earth, src, uniform)?;
             }
         }
 
-        // Earth
-        let mut earth = Renderable::new(
-            gl.as_ref(),
-            Rc::new(Shader::new(
-                gl.as_ref(),
-                GLOBE_EARTH_VS,
-                GLOBE_EARTH_FS
-            ).unwrap())
-        );
-        earth.vertex_attribute(gl.as_ref(), "a_position", positions.as_slice(), 3);
-        earth.vertex_attribute(gl.as_ref(), "a_normal", normals.as_slice(), 3);
-        earth.vertex_attribute(gl.as_ref(), "a_uv", uvs.as_slice(), 2);
-        earth.index_buffer(gl.as_ref(), indices.as_slice());
-        earth.texture(gl.clone(), "/data/world.jpg", "s_texture");
+        // No overlay until one is set: a transparent texel.
+        let overlay = Texture::from_data(gl, TextureFormat::RGBA8, 1, 1, &[0u8; 4], &TextureOptions::default())?;
+        earth.set_texture(gl, "s_overlay", overlay)?;
+        let earth_material = earth.material_mut();
+        earth_material.set("u_overlayComponents", UniformValue::Int(4))?;
+        earth_material.set("u_overlayRange", UniformValue::Vec2([0.0, 1.0]))?;
+        earth_material.set("u_overlayLow", UniformValue::Vec4([0.0, 0.0, 1.0, 1.0]))?;
+        earth_material.set("u_overlayHigh", UniformValue::Vec4([1.0, 0.0, 0.0, 1.0]))?;
+        earth_material.set("u_overlayOpacity", UniformValue::Float(0.7))?;
+
+        // Clouds
+        let mut clouds = Renderable::new(
+            gl,
+            shaders.get(gl, "globe_clouds", &[("HAS_UV", "")])?
+        )?;
+        map(gl, &mut clouds, &maps.clouds, "s_clouds")?;
+        map(gl, &mut clouds, &maps.cloud_alpha, "s_cloudAlpha")?;
 
-        let earth_xform = Transform3::identity();
+        let clouds_xform = scaling(1.01);
 
         // Atmosphere
         let mut atmosphere = Renderable::new(
-            gl.as_ref(),
-            Rc::new(Shader::new(
-                gl.as_ref(),
-                GLOBE_ATMOSPHERE_VS,
-                GLOBE_ATMOSPHERE_FS
-            ).unwrap())
-        );
-        atmosphere.vertex_attribute(gl.as_ref(), "a_position", positions.as_slice(), 3);
-        atmosphere.vertex_attribute(gl.as_ref(), "a_normal", normals.as_slice(), 3);
-        atmosphere.index_buffer(gl.as_ref(), indices.as_slice());
-
-        let atmosphere_xform = Transform3::from_matrix_unchecked(
-            Similarity3::new(
-                Vector3::new(0.0f32, 0.0, 0.0),
-                Vector3::new(0.0f32, 0.0, 0.0),
-                1.04f32
-            ).to_homogeneous()
-        );
+            gl,
+            shaders.get(gl, "globe_atmosphere", &[])?
+        )?;
+
+        let atmosphere_xform = scaling(1.04);
+
+        atmosphere.material_mut().set("u_atmosphereColor", UniformValue::Vec3([1.0, 1.0, 1.0]))?;
+        atmosphere.material_mut().set("u_atmosphereFalloff", UniformValue::Float(12.0))?;
+
+        // Markers sit on the surface of the mesh, however it was built.
+        let markers = Markers::new(gl, shaders, mesh.bounding_radius())?;
 
-        Globe {
-            positions,
-            normals,
-            uvs,
-            indices,
+        let mut globe = Globe {
+            mesh,
             earth,
+            clouds,
             atmosphere,
-            earth_xform,
-            atmosphere_xform
+            markers,
+            clouds_xform,
+            atmosphere_xform,
+            layers: GlobeLayer::ALL.iter().fold(0, |layers, layer| layers | *layer as u32),
+            cloud_offset: 0.0,
+            cloud_speed: 0.2
+        };
+        globe.upload_geometry(gl)?;
+        globe.set_bump_scale(3.0)?;
+        globe.set_layers(globe.layers)?;
+        globe.update(0.0)?;
+
+        Ok(globe)
+    }
+
+    /// Uploads the vertices of the mesh each part needs, and the indices
+    /// they share.
+    fn upload_geometry(&mut self, gl: &dyn GlBackend) -> Result<()> {
+        let mesh = &self.mesh;
+
+        // Normals and tangents are unit vectors, which 16 bits per component
+        // hold with room to spare.
+        let earth_layout = VertexLayout::new()
+            .attribute::<f32>("a_position", 3)
+            .normalized::<i16>("a_normal", 3)
+            .normalized::<i16>("a_tangent", 4)
+            .attribute::<f32>("a_uv", 2);
+        let earth_vertices = pack(&earth_layout, &[&mesh.positions, &mesh.normals, &mesh.tangents, &mesh.uvs])?;
+        self.earth.vertex_buffer(gl, &earth_layout, &earth_vertices)?;
+        self.earth.indices(gl, &mesh.indices)?;
+
+        let clouds_layout = VertexLayout::new()
+            .attribute::<f32>("a_position", 3)
+            .normalized::<i16>("a_normal", 3)
+            .attribute::<f32>("a_uv", 2);
+        let clouds_vertices = pack(&clouds_layout, &[&mesh.positions, &mesh.normals, &mesh.uvs])?;
+        self.clouds.vertex_buffer(gl, &clouds_layout, &clouds_vertices)?;
+        self.clouds.indices(gl, &mesh.indices)?;
+
+        let atmosphere_layout = VertexLayout::new()
+            .attribute::<f32>("a_position", 3)
+            .normalized::<i16>("a_normal", 3);
+        let atmosphere_vertices = pack(&atmosphere_layout, &[&mesh.positions, &mesh.normals])?;
+        self.atmosphere.vertex_buffer(gl, &atmosphere_layout, &atmosphere_vertices)?;
+        self.atmosphere.indices(gl, &mesh.indices)?;
+
+        Ok(())
+    }
+
+    /// Rebuilds the GPU objects of every part from the mesh and the map
+    /// URLs, after the context was lost. The shaders have to be restored
+    /// first, see `ShaderCache::restore`.
+    pub fn restore(&mut self, gl: &dyn GlBackend) -> Result<()> {
+        self.earth.restore(gl)?;
+        self.clouds.restore(gl)?;
+        self.atmosphere.restore(gl)?;
+        self.markers.restore(gl)?;
+        self.upload_geometry(gl)
+    }
+
+    /// The maps of every part.
+    pub fn textures(&self) -> impl std::iter::Iterator<Item = &Texture> {
+        self.earth.textures()
+            .chain(self.clouds.textures())
+            .chain(self.atmosphere.textures())
+    }
+
+    /// Strength of the relief from the bump map.
+    pub fn set_bump_scale(&mut self, scale: f32) -> Result<()> {
+        set_managed(&mut self.earth, "u_bumpScale", UniformValue::Float(scale))
+    }
+
+    fn set_layers(&mut self, layers: u32) -> Result<()> {
+        self.layers = layers;
+        set_managed(&mut self.earth, "u_layers", UniformValue::Int(layers as i32))?;
+        set_managed(&mut self.clouds, "u_layers", UniformValue::Int(layers as i32))
+    }
+
+    /// Sets a uniform of every part of the globe whose shader declares it,
+    /// e.g. `u_atmosphereColor`. See `UniformValue::from_slice` for how the
+    /// numbers are interpreted.
+    pub fn set_uniform(&mut self, name: &str, data: &[f32]) -> Result<()> {
+        let mut found = false;
+        let materials = [
+            self.earth.material_mut(),
+            self.clouds.material_mut(),
+            self.atmosphere.material_mut(),
+            self.markers.material_mut()
+        ];
+        for material in materials {
+            if material.declares(name) {
+                let value = UniformValue::from_slice(data, material.shader().uniform(name)?.gl_type)?;
+                material.set(name, value)?;
+                found = true;
+            }
         }
+
+        if found {
+            Ok(())
+        } else {
+            Err(Error::MissingUniform(name.to_string()))
+        }
+    }
+
+    /// Draws `data` over the surface: a `width` x `height` grid of `format`
+    /// wrapped around the globe like the color map, rows from the north
+    /// pole down. `u_overlayRange`, `u_overlayLow` and `u_overlayHigh` color
+    /// data of one or two channels, and `u_overlayOpacity` fades it.
+    pub fn set_overlay<T: CreateArray>(
+        &mut self,
+        gl: &dyn GlBackend,
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        data: &[T]
+    ) -> Result<()> {
+        let overlay = Texture::from_data(gl, format, width, height, data, &overlay_options())?;
+        self.set_overlay_texture(gl, overlay, format.components())
     }
 
+    /// Draws a decoded image over the surface, wrapped around the globe
+    /// like the color map, its alpha blending it with the surface.
+    pub fn set_overlay_image(&mut self, gl: &dyn GlBackend, image: &Image) -> Result<()> {
+        let overlay = Texture::from_image(gl, image, &overlay_options())?;
+        self.set_overlay_texture(gl, overlay, 4)
+    }
+
+    fn set_overlay_texture(&mut self, gl: &dyn GlBackend, overlay: Texture, components: usize) -> Result<()> {
+        self.earth.set_texture(gl, "s_overlay", overlay)?;
+        set_managed(&mut self.earth, "u_overlayComponents", UniformValue::Int(components as i32))
+    }
+
+    /// Replaces the `width` x `height` rectangle at `x`, `y` of the overlay,
+    /// e.g. as new data comes in.
+    pub fn update_overlay<T: CreateArray>(
+        &mut self,
+        gl: &dyn GlBackend,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        data: &[T]
+    ) -> Result<()> {
+        self.earth.texture_mut("s_overlay")?.update(gl, x, y, width, height, data)
+    }
+
+    /// Replaces the markers over the globe. `usage` tells how often they are
+    /// going to change.
+    pub fn set_markers(&mut self, gl: &dyn GlBackend, markers: &[Marker], usage: BufferUsage) -> Result<()> {
+        self.markers.set(gl, markers, usage)
+    }
+
+    /// Moves the markers from `first` on, adding the ones past the last.
+    pub fn update_markers(&mut self, gl: &dyn GlBackend, first: usize, markers: &[Marker]) -> Result<()> {
+        self.markers.update(gl, first, markers)
+    }
+
+    pub fn set_layer_enabled(&mut self, layer: GlobeLayer, enabled: bool) -> Result<()> {
+        let layers = if enabled {
+            self.layers | layer as u32
+        } else {
+            self.layers & !(layer as u32)
+        };
+        self.set_layers(layers)
+    }
+
+    pub fn is_layer_enabled(&self, layer: GlobeLayer) -> bool {
+        self.layers & layer as u32 != 0
+    }
+
+    /// Advances the cloud drift by `dt` seconds.
+    pub fn update(&mut self, dt: f32) -> Result<()> {
+        self.cloud_offset = (self.cloud_offset + dt * self.cloud_speed / 360.0).fract();
+        set_managed(&mut self.clouds, "u_cloudOffset", UniformValue::Float(self.cloud_offset))
+    }
+}
+
+
+/// Sets a uniform the globe keeps up to date. The shader may have compiled
+/// it out, like `u_bumpScale` without a bump map, and the value is then
+/// left out; a reloaded shader declaring it with another type is an error.
+fn set_managed(renderable: &mut Renderable, name: &str, value: UniformValue) -> Result<()> {
+    let material = renderable.material_mut();
+    if material.declares(name) {
+        material.set(name, value)?;
+    }
+    Ok(())
+}
+
+/// The overlay wraps around in longitude, like the maps.
+fn overlay_options() -> TextureOptions {
+    TextureOptions { wrap_s: TextureWrap::Repeat, ..TextureOptions::default() }
+}
+
+
+fn scaling(scale: f32) -> Transform3<f32> {
+    Transform3::from_matrix_unchecked(
+        Similarity3::new(
+            Vector3::new(0.0f32, 0.0, 0.0),
+            Vector3::new(0.0f32, 0.0, 0.0),
+            scale
+        ).to_homogeneous()
+    )
 }
 
 
 impl Render for Globe {
-    fn render(&self, gl: &GL, model_matrix: &Transform3<f32>, camera: &Camera) {
+    fn render(&self, gl: &dyn GlBackend, model_matrix: &Transform3<f32>, camera: &Camera) {
         self.earth.render(gl, model_matrix, camera);
 
-        let atm_model_matrix = self.atmosphere_xform * model_matrix;
-        self.atmosphere.render(gl, &atm_model_matrix, camera);
+        if self.is_layer_enabled(GlobeLayer::Clouds) {
+            let clouds_model_matrix = model_matrix * self.clouds_xform;
+            self.clouds.render(gl, &clouds_model_matrix, camera);
+        }
+
+        // Before the atmosphere, which would hide the markers below it.
+        self.markers.render(gl, model_matrix, camera);
+
+        if self.is_layer_enabled(GlobeLayer::Atmosphere) {
+            let atm_model_matrix = model_matrix * self.atmosphere_xform;
+            self.atmosphere.render(gl, &atm_model_matrix, camera);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use web_sys::WebGl2RenderingContext as GL;
+    use super::*;
+    use crate::backend::{GlCall, MockBackend};
+    use crate::shader::{ShaderCache, ShaderRegistry};
+    use crate::options::WebClientOptions;
+
+    fn maps() -> GlobeMaps {
+        WebClientOptions::default().globe_maps()
+    }
+
+    fn globe(gl: &MockBackend, maps: &GlobeMaps) -> Globe {
+        globe_with_mesh(gl, maps, Mesh::uv_sphere(200.0, 40, 30).unwrap())
+    }
+
+    fn globe_with_mesh(gl: &MockBackend, maps: &GlobeMaps, mesh: Mesh) -> Globe {
+        Globe::new(gl, &mut ShaderCache::new(ShaderRegistry::embedded()), mesh, maps).unwrap()
+    }
+
+    #[test]
+    fn new_uploads_earth_clouds_and_atmosphere() {
+        let gl = MockBackend::new();
+        let globe = globe(&gl, &maps());
+        let calls = gl.calls();
+
+        let vertex_count = globe.mesh.vertex_count();
+
+        let vaos = calls.iter().filter(|c| matches!(c, GlCall::CreateVertexArray(_))).count();
+        assert_eq!(vaos, 4);
+
+        let attribute_uploads: Vec<_> = calls.iter()
+            .filter_map(|c| match c {
+                GlCall::BufferData { target: GL::ARRAY_BUFFER, data_type, len, .. } => Some((*data_type, *len)),
+                _ => None
+            })
+            .collect();
+        // One interleaved buffer per part.
+        assert_eq!(attribute_uploads, vec![
+            // Markers: created empty, then filled with none
+            (GL::UNSIGNED_BYTE, 0),
+            (GL::UNSIGNED_BYTE, 0),
+            // Earth: position, normal, tangent and uv
+            (GL::UNSIGNED_BYTE, vertex_count * 36),
+            // Clouds: position, normal and uv
+            (GL::UNSIGNED_BYTE, vertex_count * 28),
+            // Atmosphere: position and normal, padded to 4 bytes
+            (GL::UNSIGNED_BYTE, vertex_count * 20)
+        ]);
+
+        let earth_pointers: Vec<_> = calls.iter()
+            .filter_map(|c| match c {
+                GlCall::VertexAttribPointer { index, size, data_type, normalized, stride, offset } => {
+                    Some((*index, *size, *data_type, *normalized, *stride, *offset))
+                },
+                _ => None
+            })
+            // After the position, corner and color of the markers
+            .skip(3)
+            .take(4)
+            .collect();
+        assert_eq!(earth_pointers, vec![
+            (0, 3, GL::FLOAT, false, 36, 0),
+            (1, 3, GL::SHORT, true, 36, 12),
+            (2, 4, GL::SHORT, true, 36, 18),
+            (3, 2, GL::FLOAT, false, 36, 28)
+        ]);
+
+        let index_uploads: Vec<_> = calls.iter()
+            .filter_map(|c| match c {
+                GlCall::BufferData { target: GL::ELEMENT_ARRAY_BUFFER, data_type, len, .. } => Some((*data_type, *len)),
+                _ => None
+            })
+            .collect();
+        assert_eq!(index_uploads, vec![
+            (GL::UNSIGNED_INT, 0),
+            (GL::UNSIGNED_SHORT, globe.mesh.indices.len()),
+            (GL::UNSIGNED_SHORT, globe.mesh.indices.len()),
+            (GL::UNSIGNED_SHORT, globe.mesh.indices.len())
+        ]);
+
+        let images: Vec<_> = calls.iter()
+            .filter_map(|c| match c {
+                GlCall::LoadImage(_, src, _) => Some(src.as_str()),
+                _ => None
+            })
+            .collect();
+        assert_eq!(images, vec![
+            "/data/world.jpg",
+            "/data/earthspec1k.jpg",
+            "/data/earthbump1k.jpg",
+            "/data/earthlights1k.jpg",
+            "/data/earthcloudmap.jpg",
+            "/data/earthcloudmaptrans.jpg"
+        ]);
+    }
+
+    #[test]
+    fn new_uploads_u32_indices_for_large_meshes() {
+        let gl = MockBackend::new();
+        let globe = globe_with_mesh(&gl, &maps(), Mesh::uv_sphere(200.0, 512, 256).unwrap());
+
+        let index_types: Vec<_> = gl.calls().iter()
+            .filter_map(|c| match c {
+                GlCall::BufferData { target: GL::ELEMENT_ARRAY_BUFFER, data_type, len, .. } => Some((*data_type, *len)),
+                _ => None
+            })
+            // Past the markers, which always use u32 indices
+            .skip(1)
+            .collect();
+        assert_eq!(index_types, vec![(GL::UNSIGNED_INT, globe.mesh.indices.len()); 3]);
+
+        let draws: Vec<_> = {
+            gl.clear_calls();
+            let camera = Camera::new(30.0, 1.0, 1.0, 1000.0);
+            globe.render(&gl, &Transform3::identity(), &camera);
+            gl.calls().into_iter()
+                .filter(|c| matches!(c, GlCall::DrawElements { .. }))
+                .collect()
+        };
+        assert_eq!(draws[0], GlCall::DrawElements {
+            mode: GL::TRIANGLES,
+            count: globe.mesh.indices.len() as i32,
+            data_type: GL::UNSIGNED_INT,
+            offset: 0
+        });
+    }
+
+    #[test]
+    fn textures_get_distinct_units() {
+        let gl = MockBackend::new();
+        let globe = globe(&gl, &maps());
+        gl.clear_calls();
+        globe.earth.render(&gl, &Transform3::identity(), &Camera::new(1.0, 1.0, 1.0, 1000.0));
+
+        let mut samplers: Vec<_> = gl.calls().into_iter()
+            .filter_map(|c| match c {
+                GlCall::Uniform1i(Some(location), unit) => gl.uniform_name(location).map(|name| (unit, name)),
+                _ => None
+            })
+            .filter(|(_, name)| name.starts_with("s_"))
+            .collect();
+        samplers.sort();
+        let units: Vec<_> = samplers.iter().map(|(unit, _)| *unit).collect();
+        assert_eq!(units, vec![0, 1, 2, 3, 4]);
+
+        // The units of the maps read through the sampler they share, the
+        // overlay after them with its own parameters.
+        let bound: Vec<_> = gl.calls().into_iter()
+            .filter_map(|c| match c {
+                GlCall::BindSampler(unit, Some(sampler)) => Some((unit, sampler)),
+                _ => None
+            })
+            .collect();
+        assert_eq!(bound.len(), 4);
+        assert!(bound.iter().enumerate().all(|(i, (unit, sampler))| *unit == i as u32 && *sampler == bound[0].1));
+    }
+
+    #[test]
+    fn the_overlay_comes_back_with_its_updates() {
+        let gl = MockBackend::new();
+        let mut globe = globe(&gl, &maps());
+        let camera = Camera::new(1.0, 1.0, 1.0, 1000.0);
+
+        globe.set_overlay(&gl, TextureFormat::R32F, 2, 1, &[0.25f32, 0.5]).unwrap();
+        globe.update_overlay(&gl, 1, 0, 1, 1, &[0.75f32]).unwrap();
+        gl.clear_calls();
+        globe.earth.render(&gl, &Transform3::identity(), &camera);
+        assert!(gl.calls().iter().any(|c| matches!(c,
+            GlCall::Uniform1i(Some(location), 1) if gl.uniform_name(*location).as_deref() == Some("u_overlayComponents")
+        )));
+
+        gl.reset();
+        gl.clear_calls();
+        globe.restore(&gl).unwrap();
+        let overlay: Vec<u8> = [0.25f32, 0.75].iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert!(gl.calls().iter().any(|c| matches!(c,
+            GlCall::TexImage2D { internal_format, pixels: Some(pixels), .. } if *internal_format == GL::R32F as i32 && *pixels == overlay
+        )));
+
+        // Integer textures cannot be filtered, so they make no overlay.
+        assert!(globe.set_overlay(&gl, TextureFormat::R32UI, 1, 1, &[1u32]).is_err());
+    }
+
+    #[test]
+    fn images_make_overlays_too() {
+        let gl = MockBackend::new();
+        let mut globe = globe(&gl, &maps());
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, 2, 1);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.write_header().unwrap().write_image_data(&[255, 0, 0, 255, 0, 0, 255, 128]).unwrap();
+
+        gl.clear_calls();
+        globe.set_overlay_image(&gl, &Image::decode(&png).unwrap()).unwrap();
+        assert!(gl.calls().contains(&GlCall::TexImage2D {
+            target: GL::TEXTURE_2D,
+            level: 0,
+            internal_format: GL::RGBA8 as i32,
+            width: 2,
+            height: 1,
+            format: GL::RGBA,
+            data_type: GL::UNSIGNED_BYTE,
+            pixels: Some(vec![255, 0, 0, 255, 0, 0, 255, 128])
+        }));
+
+        gl.clear_calls();
+        globe.earth.render(&gl, &Transform3::identity(), &Camera::new(1.0, 1.0, 1.0, 1000.0));
+        assert!(gl.calls().iter().any(|c| matches!(c,
+            GlCall::Uniform1i(Some(location), 4) if gl.uniform_name(*location).as_deref() == Some("u_overlayComponents")
+        )));
+    }
+
+    #[test]
+    fn layers_can_be_toggled() {
+        let gl = MockBackend::new();
+        let mut globe = globe(&gl, &maps());
+        let camera = Camera::new(1.0, 1.0, 1.0, 1000.0);
+
+        let render = |globe: &Globe| {
+            gl.clear_calls();
+            globe.render(&gl, &Transform3::identity(), &camera);
+            let calls = gl.calls();
+            let draws = calls.iter().filter(|c| matches!(c, GlCall::DrawElements { .. })).count();
+            let layers = calls.iter()
+                .find_map(|c| match c {
+                    GlCall::Uniform1i(Some(location), value) if gl.uniform_name(*location).as_deref() == Some("u_layers") => Some(*value),
+                    _ => None
+                })
+                .unwrap();
+            (draws, layers)
+        };
+
+        assert_eq!(render(&globe), (3, 63));
+
+        globe.set_layer_enabled("clouds".parse().unwrap(), false).unwrap();
+        globe.set_layer_enabled(GlobeLayer::NightLights, false).unwrap();
+        assert!(!globe.is_layer_enabled(GlobeLayer::Clouds));
+        assert_eq!(render(&globe), (2, 63 & !16 & !8));
+
+        globe.set_layer_enabled(GlobeLayer::Clouds, true).unwrap();
+        assert_eq!(render(&globe).0, 3);
+
+        assert_eq!("haze".parse::<GlobeLayer>(), Err(Error::UnknownLayer("haze".to_string())));
+    }
+
+    #[test]
+    fn clouds_drift_and_wrap() {
+        let gl = MockBackend::new();
+        let mut globe = globe(&gl, &maps());
+        globe.cloud_speed = 36.0;
+
+        globe.update(2.5).unwrap();
+        assert!((globe.cloud_offset - 0.25).abs() < 1e-6);
+        globe.update(10.0).unwrap();
+        assert!((globe.cloud_offset - 0.25).abs() < 1e-5);
+    }
+
+    #[test]
+    fn managed_uniforms_of_the_wrong_type_are_reported() {
+        let gl = MockBackend::new();
+        let mut globe = globe(&gl, &maps());
+
+        assert!(set_managed(&mut globe.earth, "u_bumpScale", UniformValue::Int(3)).is_err());
+        assert!(set_managed(&mut globe.earth, "u_unknown", UniformValue::Int(3)).is_ok());
+    }
+
+    #[test]
+    fn uniforms_can_be_set_by_name() {
+        let gl = MockBackend::new();
+        let mut globe = globe(&gl, &maps());
+        let camera = Camera::new(1.0, 1.0, 1.0, 1000.0);
+        globe.render(&gl, &Transform3::identity(), &camera);
+
+        globe.set_uniform("u_atmosphereColor", &[0.6, 0.8, 1.0]).unwrap();
+        gl.clear_calls();
+        globe.render(&gl, &Transform3::identity(), &camera);
+
+        // Only the changed value is uploaded again.
+        let uploads: Vec<_> = gl.calls().iter()
+            .filter_map(|c| match c {
+                GlCall::Uniform3f(Some(location), x, y, z) => Some((gl.uniform_name(*location).unwrap(), [*x, *y, *z])),
+                GlCall::Uniform1f(Some(location), _) | GlCall::Uniform1i(Some(location), _) => Some((gl.uniform_name(*location).unwrap(), [0.0; 3])),
+                _ => None
+            })
+            .filter(|(name, _)| name != "u_sunDirection")
+            .collect();
+        assert_eq!(uploads, vec![("u_atmosphereColor".to_string(), [0.6, 0.8, 1.0])]);
+
+        assert_eq!(
+            globe.set_uniform("u_atmosphereColor", &[1.0]),
+            Err(Error::InvalidUniform("cannot use 1 numbers for vec3".to_string()))
+        );
+        assert_eq!(globe.set_uniform("u_haze", &[1.0]), Err(Error::MissingUniform("u_haze".to_string())));
+    }
+
+    #[test]
+    fn missing_maps_are_left_out() {
+        let gl = MockBackend::new();
+        let mut maps = maps();
+        maps.bump = None;
+        maps.night_lights = None;
+        globe(&gl, &maps);
+
+        let images: Vec<_> = gl.calls().into_iter()
+            .filter_map(|c| match c {
+                GlCall::LoadImage(_, src, _) => Some(src),
+                _ => None
+            })
+            .collect();
+        assert_eq!(images, vec![
+            "/data/world.jpg",
+            "/data/earthspec1k.jpg",
+            "/data/earthcloudmap.jpg",
+            "/data/earthcloudmaptrans.jpg"
+        ]);
+    }
+
+    #[test]
+    fn maps_wrap_in_longitude_and_use_mipmaps() {
+        let gl = MockBackend::new();
+        globe(&gl, &maps());
+
+        let calls = gl.calls();
+        let count = |call: GlCall| calls.iter().filter(|c| **c == call).count();
+        assert_eq!(count(GlCall::TexParameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::REPEAT as i32)), 6);
+        assert_eq!(count(GlCall::TexParameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR_MIPMAP_LINEAR as i32)), 6);
+        assert!(calls.iter().all(|c| !matches!(c, GlCall::LoadImage(_, _, upload) if !upload.generate_mipmaps)));
     }
 }
@@ -0,0 +1,289 @@
+use nalgebra::Transform3;
+use serde::Deserialize;
+
+use crate::backend::GlBackend;
+use crate::error::{Error, Result};
+use crate::geo::GeoPosition;
+use crate::shader::{ShaderCache, UniformValue};
+use super::{pack, BufferUsage, Camera, Material, Render, Renderable, VertexLayout};
+
+
+/// Corners of the square drawn for a marker, counter-clockwise.
+const CORNERS: [f32; 8] = [-1.0, -1.0, 1.0, -1.0, 1.0, 1.0, -1.0, 1.0];
+/// The two triangles covering the square, by corner.
+const QUAD: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+/// Width of a marker, relative to the globe radius.
+const MARKER_SIZE: f32 = 0.02;
+
+
+/// A dot over the globe, e.g. a city or a vehicle. Parsed from
+/// `{ latitude, longitude, altitude, color }`, where the altitude defaults
+/// to 0 and the color to an opaque orange.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Marker {
+    pub latitude: f32,
+    pub longitude: f32,
+    #[serde(default)]
+    pub altitude: f32,
+    /// RGBA, each in `[0, 1]`.
+    #[serde(default = "default_color")]
+    pub color: [f32; 4]
+}
+
+fn default_color() -> [f32; 4] {
+    [1.0, 0.5, 0.1, 1.0]
+}
+
+impl Marker {
+    fn position(&self) -> Result<GeoPosition> {
+        if ![self.latitude, self.longitude, self.altitude].iter().all(|v| v.is_finite()) {
+            return Err(Error::InvalidMarker(format!(
+                "position ({}, {}, {}) is not a number", self.latitude, self.longitude, self.altitude
+            )));
+        }
+        Ok(GeoPosition::new(self.latitude, self.longitude, self.altitude))
+    }
+}
+
+
+/// Markers over a globe of `radius`, drawn as dots facing the camera.
+///
+/// Their vertices live in dynamic buffers: moving some markers or adding
+/// more uploads only theirs.
+#[derive(Clone)]
+pub struct Markers {
+    renderable: Renderable,
+    layout: VertexLayout,
+    radius: f32,
+    /// Kept to upload them again after the context was lost.
+    markers: Vec<Marker>,
+    usage: BufferUsage
+}
+
+impl Markers {
+    pub fn new(gl: &dyn GlBackend, shaders: &mut ShaderCache, radius: f32) -> Result<Self> {
+        let mut renderable = Renderable::new(gl, shaders.get(gl, "markers", &[])?)?;
+        renderable.material_mut().set("u_markerSize", UniformValue::Float(radius * MARKER_SIZE))?;
+
+        let layout = VertexLayout::new()
+            .attribute::<f32>("a_position", 3)
+            .normalized::<i16>("a_corner", 2)
+            .normalized::<u8>("a_color", 4);
+
+        let mut markers = Markers {
+            renderable,
+            layout,
+            radius,
+            markers: Vec::new(),
+            usage: BufferUsage::Dynamic
+        };
+        markers.upload(gl)?;
+        Ok(markers)
+    }
+
+    pub fn len(&self) -> usize {
+        self.markers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.markers.is_empty()
+    }
+
+    pub fn material_mut(&mut self) -> &mut Material {
+        self.renderable.material_mut()
+    }
+
+    /// Replaces every marker. `usage` tells how often they are going to
+    /// change: `BufferUsage::Stream` suits markers moved about every frame.
+    pub fn set(&mut self, gl: &dyn GlBackend, markers: &[Marker], usage: BufferUsage) -> Result<()> {
+        let vertices = self.vertices(markers)?;
+        self.renderable.update_vertices(gl, "a_position", &vertices, usage)?;
+        self.renderable.update_indices(gl, &quads(0..markers.len()), usage)?;
+        self.markers = markers.to_vec();
+        self.usage = usage;
+        Ok(())
+    }
+
+    /// Overwrites the markers from `first` on, adding the ones past the
+    /// last marker.
+    pub fn update(&mut self, gl: &dyn GlBackend, first: usize, markers: &[Marker]) -> Result<()> {
+        let len = self.len();
+        if first > len {
+            return Err(Error::InvalidMarker(format!(
+                "cannot update from marker {} when there are {}", first, len
+            )));
+        }
+
+        let vertices = self.vertices(markers)?;
+        self.renderable.update_vertex_range(gl, "a_position", first * CORNERS.len() / 2, &vertices)?;
+        let end = first + markers.len();
+        if end > len {
+            self.renderable.update_index_range(gl, len * QUAD.len(), &quads(len..end))?;
+        }
+
+        let overlap = markers.len().min(len - first);
+        self.markers[first..first + overlap].copy_from_slice(&markers[..overlap]);
+        self.markers.extend_from_slice(&markers[overlap..]);
+        Ok(())
+    }
+
+    /// Creates the buffers again after the context was lost, and uploads
+    /// the markers into them.
+    pub fn restore(&mut self, gl: &dyn GlBackend) -> Result<()> {
+        self.renderable.restore(gl)?;
+        self.upload(gl)
+    }
+
+    fn upload(&mut self, gl: &dyn GlBackend) -> Result<()> {
+        // Creates the vertex buffer for the layout, which `set` then fills.
+        self.renderable.vertex_buffer::<u8>(gl, &self.layout, &[])?;
+        let markers = std::mem::take(&mut self.markers);
+        self.set(gl, &markers, self.usage)
+    }
+
+    /// Four vertices per marker, one for each corner of its square.
+    fn vertices(&self, markers: &[Marker]) -> Result<Vec<u8>> {
+        let mut positions = Vec::with_capacity(markers.len() * 12);
+        let mut corners = Vec::with_capacity(markers.len() * 8);
+        let mut colors = Vec::with_capacity(markers.len() * 16);
+        for marker in markers {
+            let position = marker.position()?.to_cartesian(self.radius);
+            for _ in 0..4 {
+                positions.extend_from_slice(position.coords.as_slice());
+                colors.extend_from_slice(&marker.color);
+            }
+            corners.extend_from_slice(&CORNERS);
+        }
+        pack(&self.layout, &[&positions, &corners, &colors])
+    }
+}
+
+/// Indices of the squares of the markers in `range`.
+fn quads(range: std::ops::Range<usize>) -> Vec<u32> {
+    range
+        .flat_map(|marker| QUAD.iter().map(move |corner| marker as u32 * 4 + corner))
+        .collect()
+}
+
+
+impl Render for Markers {
+    fn render(&self, gl: &dyn GlBackend, model_matrix: &Transform3<f32>, camera: &Camera) {
+        if !self.is_empty() {
+            self.renderable.render(gl, model_matrix, camera);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use web_sys::WebGl2RenderingContext as GL;
+    use super::*;
+    use crate::backend::{GlCall, MockBackend};
+    use crate::shader::ShaderRegistry;
+
+    fn marker(latitude: f32, longitude: f32, altitude: f32) -> Marker {
+        Marker { latitude, longitude, altitude, color: default_color() }
+    }
+
+    fn markers(gl: &MockBackend) -> Markers {
+        Markers::new(gl, &mut ShaderCache::new(ShaderRegistry::embedded()), 100.0).unwrap()
+    }
+
+    fn uploads(gl: &MockBackend) -> Vec<GlCall> {
+        gl.calls().into_iter()
+            .filter(|c| matches!(c, GlCall::BufferData { .. } | GlCall::BufferSubData { .. }))
+            .collect()
+    }
+
+    #[test]
+    fn set_uploads_a_square_per_marker() {
+        let gl = MockBackend::new();
+        let mut markers = markers(&gl);
+        gl.clear_calls();
+
+        let city = Marker { color: [0.0, 1.0, 0.0, 1.0], ..marker(0.0, 0.0, 0.0) };
+        markers.set(&gl, &[city, marker(90.0, 0.0, 10.0)], BufferUsage::Stream).unwrap();
+        assert_eq!(uploads(&gl), vec![
+            // Position, corner and color: 20 bytes per vertex
+            GlCall::BufferData { target: GL::ARRAY_BUFFER, data_type: GL::UNSIGNED_BYTE, len: 2 * 4 * 20, usage: GL::STREAM_DRAW },
+            GlCall::BufferData { target: GL::ELEMENT_ARRAY_BUFFER, data_type: GL::UNSIGNED_INT, len: 12, usage: GL::STREAM_DRAW }
+        ]);
+
+        let vertices = markers.vertices(&[city]).unwrap();
+        let position: Vec<_> = vertices[..12].chunks(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        assert_eq!(position, [100.0, 0.0, 0.0]);
+        assert_eq!(&vertices[12..20], [i16::MIN + 1, i16::MIN + 1].iter().flat_map(|v| v.to_le_bytes()).chain([0, 255, 0, 255]).collect::<Vec<_>>());
+
+        markers.render(&gl, &Transform3::identity(), &Camera::new(1.0, 1.0, 0.1, 1000.0));
+        assert!(gl.calls().contains(&GlCall::DrawElements { mode: GL::TRIANGLES, count: 12, data_type: GL::UNSIGNED_INT, offset: 0 }));
+    }
+
+    #[test]
+    fn update_uploads_only_the_given_markers() {
+        let gl = MockBackend::new();
+        let mut markers = markers(&gl);
+        markers.set(&gl, &[marker(0.0, 0.0, 0.0); 3], BufferUsage::Dynamic).unwrap();
+        gl.clear_calls();
+
+        // Moving the second one writes its 4 vertices in place.
+        markers.update(&gl, 1, &[marker(10.0, 20.0, 0.0)]).unwrap();
+        assert_eq!(uploads(&gl), vec![
+            GlCall::BufferSubData { target: GL::ARRAY_BUFFER, offset: 80, data_type: GL::UNSIGNED_BYTE, len: 80 }
+        ]);
+        assert_eq!(markers.markers[1], marker(10.0, 20.0, 0.0));
+        gl.clear_calls();
+
+        // Going past the end adds markers, growing both buffers.
+        markers.update(&gl, 2, &[marker(-10.0, 0.0, 0.0); 2]).unwrap();
+        assert_eq!(markers.len(), 4);
+        assert_eq!(uploads(&gl), vec![
+            GlCall::BufferSubData { target: GL::ARRAY_BUFFER, offset: 160, data_type: GL::UNSIGNED_BYTE, len: 160 },
+            GlCall::BufferSubData { target: GL::ELEMENT_ARRAY_BUFFER, offset: 72, data_type: GL::UNSIGNED_INT, len: 6 }
+        ]);
+        let grown = gl.calls().iter().filter(|c| matches!(c, GlCall::CopyBufferSubData { .. })).count();
+        assert_eq!(grown, 2);
+
+        markers.render(&gl, &Transform3::identity(), &Camera::new(1.0, 1.0, 0.1, 1000.0));
+        assert!(gl.calls().contains(&GlCall::DrawElements { mode: GL::TRIANGLES, count: 24, data_type: GL::UNSIGNED_INT, offset: 0 }));
+    }
+
+    #[test]
+    fn gaps_and_bad_positions_are_rejected() {
+        let gl = MockBackend::new();
+        let mut markers = markers(&gl);
+
+        assert!(matches!(markers.update(&gl, 1, &[marker(0.0, 0.0, 0.0)]), Err(Error::InvalidMarker(_))));
+        assert!(matches!(
+            markers.set(&gl, &[marker(f32::NAN, 0.0, 0.0)], BufferUsage::Dynamic),
+            Err(Error::InvalidMarker(_))
+        ));
+        assert!(markers.is_empty());
+
+        // Nothing to draw.
+        gl.clear_calls();
+        markers.render(&gl, &Transform3::identity(), &Camera::new(1.0, 1.0, 0.1, 1000.0));
+        assert!(gl.calls().is_empty());
+    }
+
+    #[test]
+    fn restore_uploads_the_markers_again() {
+        let gl = MockBackend::new();
+        let mut markers = markers(&gl);
+        markers.set(&gl, &[marker(0.0, 0.0, 0.0); 2], BufferUsage::Stream).unwrap();
+
+        gl.reset();
+        gl.clear_calls();
+        markers.restore(&gl).unwrap();
+        assert_eq!(uploads(&gl), vec![
+            GlCall::BufferData { target: GL::ARRAY_BUFFER, data_type: GL::UNSIGNED_BYTE, len: 0, usage: GL::STATIC_DRAW },
+            GlCall::BufferData { target: GL::ARRAY_BUFFER, data_type: GL::UNSIGNED_BYTE, len: 160, usage: GL::STREAM_DRAW },
+            GlCall::BufferData { target: GL::ELEMENT_ARRAY_BUFFER, data_type: GL::UNSIGNED_INT, len: 12, usage: GL::STREAM_DRAW }
+        ]);
+        assert_eq!(markers.len(), 2);
+    }
+}
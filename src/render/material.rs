@@ -0,0 +1,184 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::backend::GlBackend;
+use crate::error::{Error, Result};
+use crate::shader::{glsl_type_name, Shader, UniformValue};
+
+
+static NEXT_MATERIAL_ID: AtomicU64 = AtomicU64::new(1);
+
+
+/// A shader together with values for its uniforms.
+///
+/// Values are uploaded when the material is bound, and after that only when
+/// they change: a program keeps its uniform values between draws. When
+/// another material has bound the same shader in the meantime, all values
+/// are uploaded again.
+pub struct Material {
+    id: u64,
+    shader: Rc<Shader>,
+    values: HashMap<String, UniformValue>,
+    changed: RefCell<HashSet<String>>
+}
+
+impl Material {
+    pub fn new(shader: Rc<Shader>) -> Self {
+        Material {
+            id: NEXT_MATERIAL_ID.fetch_add(1, Ordering::Relaxed),
+            shader,
+            values: HashMap::new(),
+            changed: RefCell::new(HashSet::new())
+        }
+    }
+
+    pub fn shader(&self) -> &Rc<Shader> {
+        &self.shader
+    }
+
+    #[cfg(test)]
+    pub fn get(&self, name: &str) -> Option<&UniformValue> {
+        self.values.get(name)
+    }
+
+    /// Sets the value of the uniform `name`, which the shader has to declare
+    /// with a matching type.
+    pub fn set(&mut self, name: &str, value: UniformValue) -> Result<()> {
+        let uniform = self.shader.uniform(name)?;
+        if !value.matches(uniform.gl_type) {
+            return Err(Error::TypeMismatch {
+                name: name.to_string(),
+                expected: glsl_type_name(uniform.gl_type).to_string(),
+                found: value.glsl_type().to_string()
+            });
+        }
+
+        if self.values.get(name) != Some(&value) {
+            self.values.insert(name.to_string(), value);
+            self.changed.borrow_mut().insert(name.to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether the shader has an active uniform called `name`.
+    pub fn declares(&self, name: &str) -> bool {
+        self.shader.uniform(name).is_ok()
+    }
+
+    /// Makes the shader current and uploads the values it is missing.
+    pub fn bind(&self, gl: &dyn GlBackend) {
+        gl.use_program(Some(self.shader.program()));
+
+        let mut changed = self.changed.borrow_mut();
+        let upload_all = self.shader.mark_bound(self.id);
+        for (name, value) in &self.values {
+            if upload_all || changed.contains(name) {
+                value.upload(gl, self.shader.uniform_location(name));
+            }
+        }
+        changed.clear();
+    }
+}
+
+impl Clone for Material {
+    /// The copy is a separate material, with all its values still to upload.
+    fn clone(&self) -> Self {
+        Material {
+            id: NEXT_MATERIAL_ID.fetch_add(1, Ordering::Relaxed),
+            shader: self.shader.clone(),
+            values: self.values.clone(),
+            changed: RefCell::new(self.values.keys().cloned().collect())
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{GlCall, MockBackend};
+
+    static VS: &str = "#version 300 es\nin vec4 a_position;\nvoid main() {}\n";
+    static FS: &str = "#version 300 es\nuniform vec3 u_color;\nuniform float u_alpha;\nout vec4 outColor;\nvoid main() {}\n";
+
+    fn uploads(gl: &MockBackend) -> Vec<String> {
+        let mut names: Vec<_> = gl.calls().into_iter()
+            .filter_map(|c| match c {
+                GlCall::Uniform3f(Some(location), ..) | GlCall::Uniform1f(Some(location), _) => gl.uniform_name(location),
+                _ => None
+            })
+            .collect();
+        names.sort();
+        gl.clear_calls();
+        names
+    }
+
+    #[test]
+    fn only_changed_values_are_uploaded() {
+        let gl = MockBackend::new();
+        let mut material = Material::new(Rc::new(Shader::new(&gl, VS, FS).unwrap()));
+        material.set("u_color", UniformValue::Vec3([1.0, 0.0, 0.0])).unwrap();
+        material.set("u_alpha", UniformValue::Float(1.0)).unwrap();
+        gl.clear_calls();
+
+        material.bind(&gl);
+        assert_eq!(uploads(&gl), vec!["u_alpha", "u_color"]);
+
+        material.bind(&gl);
+        assert!(uploads(&gl).is_empty());
+
+        material.set("u_alpha", UniformValue::Float(0.5)).unwrap();
+        material.set("u_color", UniformValue::Vec3([1.0, 0.0, 0.0])).unwrap();
+        material.bind(&gl);
+        assert_eq!(uploads(&gl), vec!["u_alpha"]);
+        assert_eq!(material.get("u_alpha"), Some(&UniformValue::Float(0.5)));
+    }
+
+    #[test]
+    fn sharing_a_shader_uploads_everything_on_switch() {
+        let gl = MockBackend::new();
+        let shader = Rc::new(Shader::new(&gl, VS, FS).unwrap());
+        let mut red = Material::new(shader.clone());
+        red.set("u_color", UniformValue::Vec3([1.0, 0.0, 0.0])).unwrap();
+        let mut blue = red.clone();
+        blue.set("u_color", UniformValue::Vec3([0.0, 0.0, 1.0])).unwrap();
+        gl.clear_calls();
+
+        red.bind(&gl);
+        blue.bind(&gl);
+        red.bind(&gl);
+        let colors: Vec<_> = gl.calls().into_iter()
+            .filter_map(|c| match c {
+                GlCall::Uniform3f(_, r, _, b) => Some((r, b)),
+                _ => None
+            })
+            .collect();
+        assert_eq!(colors, vec![(1.0, 0.0), (0.0, 1.0), (1.0, 0.0)]);
+    }
+
+    #[test]
+    fn values_are_checked_against_the_shader() {
+        let gl = MockBackend::new();
+        let mut material = Material::new(Rc::new(Shader::new(&gl, VS, FS).unwrap()));
+
+        assert_eq!(
+            material.set("u_colour", UniformValue::Vec3([1.0, 0.0, 0.0])),
+            Err(Error::MissingUniform("u_colour".to_string()))
+        );
+        assert_eq!(
+            material.set("u_color", UniformValue::Vec4([1.0, 0.0, 0.0, 1.0])),
+            Err(Error::TypeMismatch { name: "u_color".to_string(), expected: "vec3".to_string(), found: "vec4".to_string() })
+        );
+        assert_eq!(material.get("u_color"), None);
+    }
+
+    #[test]
+    fn declared_uniforms() {
+        let gl = MockBackend::new();
+        let material = Material::new(Rc::new(Shader::new(&gl, VS, FS).unwrap()));
+        assert!(material.declares("u_color"));
+        assert!(!material.declares("u_colour"));
+    }
+}
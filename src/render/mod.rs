@@ -1,11 +1,25 @@
+mod buffer;
 mod camera;
+mod frame;
 mod globe;
+mod markers;
+mod material;
+mod orbit;
 mod renderable;
 mod renderer;
+mod sampler;
 mod texture;
+mod vertex;
 
+pub(in crate) use self::buffer::*;
 pub(in crate) use self::camera::*;
+pub(in crate) use self::frame::*;
 pub(in crate) use self::globe::*;
+pub(in crate) use self::markers::*;
+pub(in crate) use self::material::*;
+pub(in crate) use self::orbit::*;
 pub(in crate) use self::renderable::*;
 pub(in crate) use self::renderer::*;
+pub(in crate) use self::sampler::*;
 pub(in crate) use self::texture::*;
+pub(in crate) use self::vertex::*;
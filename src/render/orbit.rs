@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+use std::f32::consts::FRAC_PI_2;
+use nalgebra::{Point3, Unit, Vector3};
+
+use super::camera::Camera;
+use crate::geo;
+
+
+/// Keeps the camera just short of the poles, where the view direction would
+/// line up with the camera's up vector.
+const MAX_ELEVATION: f32 = FRAC_PI_2 - 0.01;
+
+/// Velocities below this are treated as rest.
+const REST_VELOCITY: f32 = 1e-4;
+
+
+/// An animated move between two orbit positions.
+#[derive(Clone, Debug)]
+struct Flight {
+    from: Unit<Vector3<f32>>,
+    to: Unit<Vector3<f32>>,
+    from_distance: f32,
+    to_distance: f32,
+    elapsed: f32,
+    duration: f32
+}
+
+
+/// Orbits the camera around its target in spherical coordinates.
+///
+/// Azimuth is measured around +y from +z towards +x, elevation from the xz
+/// plane towards +y. Input is fed in through the pointer and wheel methods,
+/// in CSS pixels, and takes effect on the next `update`. Motion left over
+/// when a drag is released, and wheel zooming, decay exponentially at
+/// `damping` per second.
+#[derive(Clone, Debug)]
+pub struct OrbitController {
+    target: Point3<f32>,
+    azimuth: f32,
+    elevation: f32,
+    distance: f32,
+    min_distance: f32,
+    max_distance: f32,
+    /// Radians of rotation per pixel dragged.
+    pub rotate_speed: f32,
+    /// Natural log of the zoom factor per wheel unit.
+    pub zoom_speed: f32,
+    pub damping: f32,
+    pointers: HashMap<i32, (f32, f32)>,
+    pending_rotation: (f32, f32),
+    pending_zoom: f32,
+    rotation_velocity: (f32, f32),
+    zoom_velocity: f32,
+    flight: Option<Flight>
+}
+
+impl OrbitController {
+    /// Starts from the current placement of `camera`. The orbit distance is
+    /// kept within `min_distance..=max_distance`.
+    pub fn new(camera: &Camera, min_distance: f32, max_distance: f32) -> Self {
+        let target = *camera.target();
+        let offset = camera.position() - target;
+        let distance = offset.norm();
+        let (azimuth, elevation) = if distance > 0.0 {
+            (offset.x.atan2(offset.z), (offset.y / distance).asin())
+        } else {
+            (0.0, 0.0)
+        };
+
+        OrbitController {
+            target,
+            azimuth,
+            elevation: elevation.clamp(-MAX_ELEVATION, MAX_ELEVATION),
+            distance: distance.max(min_distance).min(max_distance),
+            min_distance,
+            max_distance,
+            rotate_speed: 0.005,
+            zoom_speed: 0.001,
+            damping: 4.0,
+            pointers: HashMap::new(),
+            pending_rotation: (0.0, 0.0),
+            pending_zoom: 0.0,
+            rotation_velocity: (0.0, 0.0),
+            zoom_velocity: 0.0,
+            flight: None
+        }
+    }
+
+    pub fn position(&self) -> Point3<f32> {
+        let (sin_az, cos_az) = self.azimuth.sin_cos();
+        let (sin_el, cos_el) = self.elevation.sin_cos();
+        self.target + Vector3::new(cos_el * sin_az, sin_el, cos_el * cos_az) * self.distance
+    }
+
+    /// Moves the camera to `destination` over `duration` seconds, along the
+    /// great circle around the target and with eased in and out speed. Any
+    /// pointer or wheel input cancels the flight.
+    pub fn fly_to(&mut self, destination: &Point3<f32>, duration: f32) {
+        let offset = destination - self.target;
+        let to = match Unit::try_new(offset, 1e-6) {
+            Some(to) => to,
+            None => return
+        };
+
+        self.rotation_velocity = (0.0, 0.0);
+        self.zoom_velocity = 0.0;
+        self.flight = Some(Flight {
+            from: Unit::new_normalize(self.position() - self.target),
+            to,
+            from_distance: self.distance,
+            to_distance: offset.norm(),
+            elapsed: 0.0,
+            duration
+        });
+        if duration <= 0.0 {
+            self.update(0.0);
+        }
+    }
+
+    pub fn is_flying(&self) -> bool {
+        self.flight.is_some()
+    }
+
+    pub fn pointer_down(&mut self, id: i32, x: f32, y: f32) {
+        self.flight = None;
+        self.pointers.insert(id, (x, y));
+        self.rotation_velocity = (0.0, 0.0);
+        self.zoom_velocity = 0.0;
+    }
+
+    pub fn pointer_move(&mut self, id: i32, x: f32, y: f32) {
+        let previous = match self.pointers.get(&id) {
+            Some(previous) => *previous,
+            None => return
+        };
+
+        if self.pointers.len() == 2 {
+            let before = self.pinch_span();
+            self.pointers.insert(id, (x, y));
+            let after = self.pinch_span();
+            if before > 0.0 && after > 0.0 {
+                self.pending_zoom += (before / after).ln();
+            }
+        } else {
+            self.pointers.insert(id, (x, y));
+            if self.pointers.len() == 1 {
+                // Dragging right or down brings the far side of the globe into view.
+                self.pending_rotation.0 -= (x - previous.0) * self.rotate_speed;
+                self.pending_rotation.1 += (y - previous.1) * self.rotate_speed;
+            }
+        }
+    }
+
+    pub fn pointer_up(&mut self, id: i32) {
+        self.pointers.remove(&id);
+    }
+
+    /// Zooms out for positive `delta`, as reported by `WheelEvent.deltaY`.
+    pub fn wheel(&mut self, delta: f32) {
+        self.flight = None;
+        // Scaled so that the zoom integrated over the decay adds up to
+        // `delta * zoom_speed`.
+        self.zoom_velocity += delta * self.zoom_speed * self.damping;
+    }
+
+    /// Applies the input received since the last call and advances inertia
+    /// by `dt` seconds.
+    pub fn update(&mut self, dt: f32) {
+        if let Some(flight) = &mut self.flight {
+            flight.elapsed += dt;
+            let progress = if flight.duration > 0.0 { (flight.elapsed / flight.duration).min(1.0) } else { 1.0 };
+            let t = progress * progress * (3.0 - 2.0 * progress);
+
+            let direction = geo::great_circle(&flight.from, &flight.to, t);
+            let distance = flight.from_distance + (flight.to_distance - flight.from_distance) * t;
+            if progress >= 1.0 {
+                self.flight = None;
+            }
+
+            self.azimuth = direction.x.atan2(direction.z);
+            self.elevation = direction.y.asin().clamp(-MAX_ELEVATION, MAX_ELEVATION);
+            self.zoom((distance / self.distance).ln());
+            return;
+        }
+
+        let (d_azimuth, d_elevation) = std::mem::take(&mut self.pending_rotation);
+        let pending_zoom = std::mem::take(&mut self.pending_zoom);
+
+        // Distance covered over `dt` by a velocity decaying exponentially,
+        // so the total doesn't depend on the frame rate.
+        let decay = (-self.damping * dt).exp();
+        let travel = if self.damping > 0.0 { (1.0 - decay) / self.damping } else { dt };
+
+        if !self.pointers.is_empty() {
+            // Follow the pointer exactly and remember how fast it went, so
+            // the motion carries on after release.
+            self.rotation_velocity = if dt > 0.0 {
+                (d_azimuth / dt, d_elevation / dt)
+            } else {
+                (0.0, 0.0)
+            };
+            self.rotate(d_azimuth, d_elevation);
+        } else {
+            self.rotate(self.rotation_velocity.0 * travel, self.rotation_velocity.1 * travel);
+        }
+        self.zoom(pending_zoom + self.zoom_velocity * travel);
+
+        self.rotation_velocity.0 *= decay;
+        self.rotation_velocity.1 *= decay;
+        self.zoom_velocity *= decay;
+        if self.rotation_velocity.0.abs().max(self.rotation_velocity.1.abs()) < REST_VELOCITY {
+            self.rotation_velocity = (0.0, 0.0);
+        }
+        if self.zoom_velocity.abs() < REST_VELOCITY {
+            self.zoom_velocity = 0.0;
+        }
+    }
+
+    /// Places `camera` at the current orbit position, looking at the target.
+    pub fn apply(&self, camera: &mut Camera) {
+        let position = self.position();
+        camera.set_position(position.x, position.y, position.z);
+        camera.set_target(self.target.x, self.target.y, self.target.z);
+    }
+
+    fn rotate(&mut self, d_azimuth: f32, d_elevation: f32) {
+        self.azimuth = (self.azimuth + d_azimuth) % std::f32::consts::TAU;
+        self.elevation = (self.elevation + d_elevation).clamp(-MAX_ELEVATION, MAX_ELEVATION);
+    }
+
+    fn zoom(&mut self, log_factor: f32) {
+        self.distance = (self.distance * log_factor.exp()).max(self.min_distance).min(self.max_distance);
+    }
+
+    fn pinch_span(&self) -> f32 {
+        let mut points = self.pointers.values();
+        match (points.next(), points.next()) {
+            (Some(a), Some(b)) => (a.0 - b.0).hypot(a.1 - b.1),
+            _ => 0.0
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn controller() -> OrbitController {
+        let mut camera = Camera::new(1.0, 1.0, 1.0, 1000.0);
+        camera.set_position(0.0, 0.0, 500.0);
+        camera.set_target(0.0, 0.0, 0.0);
+        OrbitController::new(&camera, 201.0, 800.0)
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn starts_from_the_camera_placement() {
+        let mut camera = Camera::new(1.0, 1.0, 1.0, 1000.0);
+        camera.set_position(300.0, 300.0, 0.0);
+        camera.set_target(0.0, 100.0, 0.0);
+        let orbit = OrbitController::new(&camera, 10.0, 1000.0);
+
+        assert_close(orbit.azimuth, FRAC_PI_2);
+        assert_close(orbit.elevation, (200.0f32 / 300.0).atan());
+        assert_close(orbit.distance, 200.0f32.hypot(300.0));
+
+        let position = orbit.position();
+        assert_close(position.x, 300.0);
+        assert_close(position.y, 300.0);
+        assert_close(position.z, 0.0);
+    }
+
+    #[test]
+    fn drag_rotates_and_carries_on_after_release() {
+        let mut orbit = controller();
+        orbit.pointer_down(1, 100.0, 100.0);
+        orbit.pointer_move(1, 80.0, 100.0);
+        orbit.update(0.1);
+
+        assert_close(orbit.azimuth, 20.0 * orbit.rotate_speed);
+        assert_close(orbit.elevation, 0.0);
+
+        orbit.pointer_up(1);
+        let released_at = orbit.azimuth;
+        orbit.update(0.1);
+        let first_step = orbit.azimuth - released_at;
+        orbit.update(0.1);
+        let second_step = orbit.azimuth - released_at - first_step;
+
+        assert!(first_step > 0.0);
+        assert!(second_step > 0.0 && second_step < first_step);
+
+        for _ in 0..200 {
+            orbit.update(0.1);
+        }
+        let resting = orbit.azimuth;
+        orbit.update(0.1);
+        assert_eq!(orbit.azimuth, resting);
+    }
+
+    #[test]
+    fn holding_still_stops_the_motion() {
+        let mut orbit = controller();
+        orbit.pointer_down(1, 100.0, 100.0);
+        orbit.pointer_move(1, 150.0, 100.0);
+        orbit.update(0.1);
+        orbit.update(0.1);
+        orbit.pointer_up(1);
+
+        let azimuth = orbit.azimuth;
+        orbit.update(0.1);
+        assert_eq!(orbit.azimuth, azimuth);
+    }
+
+    #[test]
+    fn elevation_stops_short_of_the_poles() {
+        let mut orbit = controller();
+        orbit.pointer_down(1, 0.0, 0.0);
+        orbit.pointer_move(1, 0.0, 10_000.0);
+        orbit.update(0.1);
+
+        assert_close(orbit.elevation, MAX_ELEVATION);
+        assert!(orbit.position().x.abs() < 500.0);
+    }
+
+    #[test]
+    fn wheel_zoom_is_clamped() {
+        let mut orbit = controller();
+        orbit.wheel(-100.0);
+        for _ in 0..100 {
+            orbit.update(0.05);
+        }
+        assert!(orbit.distance < 500.0);
+        // Within what the rest threshold cuts off.
+        assert!((orbit.distance / (500.0 * (-100.0 * orbit.zoom_speed).exp()) - 1.0).abs() < 1e-4);
+
+        orbit.wheel(-1.0e5);
+        for _ in 0..100 {
+            orbit.update(0.05);
+        }
+        assert_eq!(orbit.distance, 201.0);
+
+        orbit.wheel(1.0e5);
+        for _ in 0..100 {
+            orbit.update(0.05);
+        }
+        assert_eq!(orbit.distance, 800.0);
+    }
+
+    #[test]
+    fn pinch_zooms_without_rotating() {
+        let mut orbit = controller();
+        orbit.pointer_down(1, 100.0, 100.0);
+        orbit.pointer_down(2, 200.0, 100.0);
+        orbit.pointer_move(2, 300.0, 100.0);
+        orbit.update(0.1);
+
+        assert_close(orbit.distance, 250.0);
+        assert_close(orbit.azimuth, 0.0);
+    }
+
+    #[test]
+    fn fly_to_follows_the_great_circle() {
+        let mut orbit = controller();
+        let destination = Point3::new(0.0, 300.0, -300.0);
+        orbit.fly_to(&destination, 2.0);
+
+        let start = Unit::new_normalize(orbit.position().coords);
+        let end = Unit::new_normalize(destination.coords);
+        for _ in 0..10 {
+            orbit.update(0.1);
+            let direction = Unit::new_normalize(orbit.position().coords);
+            // Stays in the plane spanned by the start and end directions.
+            assert!(direction.dot(&start.cross(&end)).abs() < 1e-4);
+        }
+
+        // Eased: halfway in time is halfway along the arc.
+        let direction = Unit::new_normalize(orbit.position().coords);
+        assert_close(direction.angle(&start), start.angle(&end) / 2.0);
+        assert_close(orbit.distance, (500.0 + 300.0 * 2.0f32.sqrt()) / 2.0);
+
+        for _ in 0..20 {
+            orbit.update(0.1);
+        }
+        assert!(!orbit.is_flying());
+        assert!((orbit.position() - destination).norm() < 1e-2);
+    }
+
+    #[test]
+    fn input_cancels_a_flight() {
+        let mut orbit = controller();
+        orbit.fly_to(&Point3::new(500.0, 0.0, 0.0), 1.0);
+        orbit.update(0.1);
+        orbit.pointer_down(1, 0.0, 0.0);
+        assert!(!orbit.is_flying());
+
+        let azimuth = orbit.azimuth;
+        orbit.update(0.1);
+        assert_eq!(orbit.azimuth, azimuth);
+    }
+
+    #[test]
+    fn apply_moves_the_camera() {
+        let mut orbit = controller();
+        orbit.pointer_down(1, 0.0, 0.0);
+        orbit.pointer_move(1, -FRAC_PI_2 / orbit.rotate_speed, 0.0);
+        orbit.update(0.0);
+
+        let mut camera = Camera::new(1.0, 1.0, 1.0, 1000.0);
+        orbit.apply(&mut camera);
+        assert_close(camera.position().x, 500.0);
+        assert_close(camera.position().z, 0.0);
+    }
+}
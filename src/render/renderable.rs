@@ -1,146 +1,462 @@
-use std::mem::size_of;
+use std::cell::RefCell;
 use std::rc::Rc;
 use js_sys::*;
-use wasm_bindgen::prelude::*;
-use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen::JsCast;
 use web_sys::WebGl2RenderingContext as GL;
 use web_sys::*;
 
+use crate::backend::{ArrayData, GlBackend, Handle, VertexArrayId};
+use crate::error::{Error, Result};
+use crate::geometry::Indices;
 use crate::utils::get_memory_buffer;
 use super::Camera;
-use super::Texture;
-use crate::shader::Shader;
+use super::Material;
+use super::{target_name, Sampler, Texture, TextureOptions};
+use super::{Buffer, BufferUsage, VertexLayout};
+use crate::shader::{float_components, glsl_type_name, is_integer_sampler, sampler_target, Shader, UniformValue};
 
-use std::cell::RefCell;
-use wasm_bindgen::__rt::std::collections::HashMap;
-use nalgebra::{Isometry3, Rotation3, Similarity3, Transform3};
+use std::mem::{size_of, size_of_val};
+use nalgebra::{Rotation3, Transform3};
 
 
 pub trait Render {
-    fn render(&self, gl: &GL, model_matrix: &Transform3<f32>, camera: &Camera);
+    fn render(&self, gl: &dyn GlBackend, model_matrix: &Transform3<f32>, camera: &Camera);
+}
+
+/// Lets a renderable be shared with the scene while its owner keeps
+/// changing it.
+impl<T: Render> Render for RefCell<T> {
+    fn render(&self, gl: &dyn GlBackend, model_matrix: &Transform3<f32>, camera: &Camera) {
+        self.borrow().render(gl, model_matrix, camera);
+    }
+}
+
+
+/// A vertex buffer together with the layout of its data and the shader
+/// locations its attributes are bound to.
+#[derive(Clone, Debug)]
+struct VertexBuffer {
+    buffer: Buffer,
+    layout: VertexLayout,
+    /// One per attribute of the layout, `None` once the attribute reads from
+    /// another buffer.
+    locations: Vec<Option<u32>>
+}
+
+impl VertexBuffer {
+    fn active_locations(&self) -> impl std::iter::Iterator<Item = u32> + '_ {
+        self.locations.iter().flatten().copied()
+    }
+
+    /// Points the attributes at this buffer, which has to be bound to
+    /// `ARRAY_BUFFER`.
+    fn point_attributes(&self, gl: &dyn GlBackend) {
+        let stride = self.layout.pointer_stride();
+        for (attribute, location) in self.layout.attributes().iter().zip(&self.locations) {
+            if let Some(location) = location {
+                gl.vertex_attrib_pointer(
+                    *location,
+                    attribute.components,
+                    attribute.data_type,
+                    attribute.normalized,
+                    stride,
+                    attribute.offset
+                );
+            }
+        }
+    }
+}
+
+
+/// A texture read by a sampler uniform, through the texture unit of the
+/// binding's index.
+#[derive(Clone)]
+struct TextureBinding {
+    uniform: String,
+    texture: Texture,
+    /// Overrides the sampling parameters of the texture.
+    sampler: Option<Sampler>
 }
 
 
 #[derive(Clone)]
 pub struct Renderable {
-    shader: Rc<Shader>,
-    vao: WebGlVertexArrayObject,
-    attributes: HashMap<String, u32>,
+    material: Material,
+    vao: Handle<VertexArrayId>,
+    vertex_buffers: Vec<VertexBuffer>,
+    index_buffer: Option<Buffer>,
     num_indices: u32,
-    indices_type: u32,
-    textures: HashMap<String, Texture>
+    indices_type: IndexFormat,
+    /// In the order their uniforms were first set, which gives them their
+    /// texture unit.
+    textures: Vec<TextureBinding>
 }
 
 impl Renderable {
-    pub fn new(gl: &GL, shader: Rc<Shader>) -> Self {
-        let vao = gl.create_vertex_array().unwrap();
-        let attributes = HashMap::new();
-        let textures = HashMap::new();
-        Renderable {
-            shader,
+    pub fn new(gl: &dyn GlBackend, shader: Rc<Shader>) -> Result<Self> {
+        let vao = gl.create_vertex_array()
+            .map(|vao| gl.resources().track(vao))
+            .ok_or(Error::ObjectCreation("vertex array"))?;
+        Ok(Renderable {
+            material: Material::new(shader),
             vao,
-            attributes,
+            vertex_buffers: Vec::new(),
+            index_buffer: None,
             num_indices: 0,
-            indices_type: GL::UNSIGNED_SHORT,
-            textures
+            indices_type: IndexFormat::U16,
+            textures: Vec::new()
+        })
+    }
+
+    pub fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    /// Uploads `data` for the attribute `name`, `size` components per
+    /// vertex. The shader has to declare the attribute as a float type with
+    /// at least `size` components; missing ones take their default value.
+    /// Only the tests upload attributes one at a time; the renderer packs
+    /// them into one buffer per layout with `vertex_buffer`.
+    #[cfg(test)]
+    pub fn vertex_attribute<T: CreateArray>(&mut self, gl: &dyn GlBackend, name: &str, data: &[T], size: i32) -> Result<()> {
+        self.check_attribute(name, size)?;
+        if !data.len().is_multiple_of(size as usize) {
+            return Err(Error::TypeMismatch {
+                name: name.to_string(),
+                expected: format!("a multiple of {} values", size),
+                found: format!("{} values", data.len())
+            });
         }
+        self.vertex_buffer(gl, &VertexLayout::new().attribute::<T>(name, size), data)
     }
 
-    pub fn vertex_attribute<T: CreateArray>(&mut self, gl: &GL, name: &str, data: &[T], size: i32) {
-        let attr_location = self.shader.get_attrib_location(&gl, name);
-        if attr_location.is_none() {
-           log!("Cannot find attribute'{}'", name);
-            return
+    /// Uploads the vertices in `data`, laid out as `layout` describes, into
+    /// a single buffer. Mixed types can be passed as bytes.
+    ///
+    /// Uploading the same layout again reuses the buffer it went to before.
+    pub fn vertex_buffer<T: CreateArray>(&mut self, gl: &dyn GlBackend, layout: &VertexLayout, data: &[T]) -> Result<()> {
+        let locations = layout.attributes().iter()
+            .map(|attribute| self.check_attribute(&attribute.name, attribute.components))
+            .collect::<Result<Vec<_>>>()?;
+        check_vertex_data(layout, size_of_val(data))?;
+
+        gl.bind_vertex_array(Some(self.vao.id()));
+
+        let result = match self.vertex_buffers.iter().position(|b| &b.layout == layout) {
+            Some(index) => {
+                let buffer = &mut self.vertex_buffers[index].buffer;
+                let usage = buffer.usage();
+                buffer.replace(gl, T::array_data(data), usage).map(|_| index)
+            }
+            None => Buffer::new(gl, GL::ARRAY_BUFFER, T::array_data(data), BufferUsage::Static).map(|buffer| {
+                self.vertex_buffers.push(VertexBuffer { buffer, layout: layout.clone(), locations: Vec::new() });
+                self.vertex_buffers.len() - 1
+            })
+        };
+        if let Ok(index) = result {
+            self.claim(gl, index, &locations);
         }
 
-        gl.bind_vertex_array(Some(&self.vao));
+        gl.bind_vertex_array(None);
+        result.map(|_| ())
+    }
 
-        let data_location = data.as_ptr() as u32 / size_of::<T>() as u32;
-        let array = T::create_array(data_location, data_location + data.len() as u32);
-        let buffer = gl.create_buffer();
-        gl.bind_buffer(GL::ARRAY_BUFFER, buffer.as_ref());
-        gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &array, GL::STATIC_DRAW);
-        gl.vertex_attrib_pointer_with_i32(attr_location.unwrap(), size, T::data_type(), false, 0, 0);
+    /// Replaces the contents of the vertex buffer that holds the attribute
+    /// `name`. `data` has whole vertices of that buffer's layout.
+    pub fn update_vertices<T: CreateArray>(&mut self, gl: &dyn GlBackend, name: &str, data: &[T], usage: BufferUsage) -> Result<()> {
+        let index = self.buffer_of(name)?;
+        let vertex_buffer = &mut self.vertex_buffers[index];
+        check_vertex_data(&vertex_buffer.layout, size_of_val(data))?;
+        vertex_buffer.buffer.replace(gl, T::array_data(data), usage)
+    }
+
+    /// Overwrites the vertices from `first_vertex` on in the vertex buffer
+    /// that holds the attribute `name`, growing it when they do not fit.
+    /// They can start at most right after the last vertex.
+    pub fn update_vertex_range<T: CreateArray>(&mut self, gl: &dyn GlBackend, name: &str, first_vertex: usize, data: &[T]) -> Result<()> {
+        let index = self.buffer_of(name)?;
+        let vertex_buffer = &mut self.vertex_buffers[index];
+        check_vertex_data(&vertex_buffer.layout, size_of_val(data))?;
+        let stride = vertex_buffer.layout.stride() as usize;
+        let vertex_count = vertex_buffer.buffer.byte_len() / stride;
+        if first_vertex > vertex_count {
+            return Err(Error::BufferUpdate(format!(
+                "cannot update from vertex {} when there are {}", first_vertex, vertex_count
+            )));
+        }
+        let offset = first_vertex * stride;
 
+        gl.bind_vertex_array(Some(self.vao.id()));
+        let result = vertex_buffer.buffer.write(gl, offset, T::array_data(data));
+        if result == Ok(true) {
+            vertex_buffer.point_attributes(gl);
+        }
         gl.bind_vertex_array(None);
-        self.attributes.insert(name.to_string(), attr_location.unwrap());
+        result.map(|_| ())
+    }
+
+    /// Location of the attribute `name`, which the shader has to declare as
+    /// a float type with at least `components` components.
+    fn check_attribute(&self, name: &str, components: i32) -> Result<u32> {
+        let attribute = self.material.shader().attribute(name)?;
+        match float_components(attribute.gl_type) {
+            Some(declared) if (1..=declared).contains(&components) => Ok(attribute.location),
+            _ => Err(Error::TypeMismatch {
+                name: name.to_string(),
+                expected: glsl_type_name(attribute.gl_type).to_string(),
+                found: format!("{} components", components)
+            })
+        }
+    }
+
+    /// Index of the vertex buffer the attribute `name` reads from.
+    fn buffer_of(&self, name: &str) -> Result<usize> {
+        let location = self.material.shader().attribute(name)?.location;
+        self.vertex_buffers.iter()
+            .position(|b| b.active_locations().any(|l| l == location))
+            .ok_or_else(|| Error::MissingAttribute(name.to_string()))
+    }
+
+    /// Makes the attributes at `locations` read from the vertex buffer at
+    /// `index`, which has to be bound. Buffers left without attributes are
+    /// released.
+    fn claim(&mut self, gl: &dyn GlBackend, index: usize, locations: &[u32]) {
+        for (i, other) in self.vertex_buffers.iter_mut().enumerate() {
+            if i != index {
+                for location in other.locations.iter_mut() {
+                    if location.is_some_and(|l| locations.contains(&l)) {
+                        *location = None;
+                    }
+                }
+            }
+        }
+        self.vertex_buffers[index].locations = locations.iter().copied().map(Some).collect();
+        self.vertex_buffers[index].point_attributes(gl);
+
+        self.vertex_buffers.retain(|b| b.active_locations().next().is_some());
     }
 
-    pub fn index_buffer<T: CreateArray>(&mut self, gl: &GL, data: &[T]) {
-        gl.bind_vertex_array(Some(&self.vao));
+    pub fn index_buffer<T: IndexType>(&mut self, gl: &dyn GlBackend, data: &[T]) -> Result<()> {
+        let usage = self.index_buffer.as_ref().map_or(BufferUsage::Static, Buffer::usage);
+        self.update_indices(gl, data, usage)
+    }
 
-        let data_location = data.as_ptr() as u32 / size_of::<T>() as u32;
-        let array = T::create_array(data_location, data_location + data.len() as u32);
-        let buffer = gl.create_buffer();
-        gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, buffer.as_ref());
-        gl.buffer_data_with_array_buffer_view(GL::ELEMENT_ARRAY_BUFFER, &array, GL::STATIC_DRAW);
-        self.num_indices = data.len() as u32;
-        self.indices_type = T::data_type();
+    /// Replaces the indices, in the index buffer there already is if any.
+    pub fn update_indices<T: IndexType>(&mut self, gl: &dyn GlBackend, data: &[T], usage: BufferUsage) -> Result<()> {
+        gl.bind_vertex_array(Some(self.vao.id()));
+
+        let result = match self.index_buffer.as_mut() {
+            Some(buffer) => buffer.replace(gl, T::array_data(data), usage),
+            None => Buffer::new(gl, GL::ELEMENT_ARRAY_BUFFER, T::array_data(data), usage)
+                .map(|buffer| self.index_buffer = Some(buffer))
+        };
+        if result.is_ok() {
+            self.num_indices = data.len() as u32;
+            self.indices_type = T::FORMAT;
+        }
 
         gl.bind_vertex_array(None);
+        result
     }
 
-    pub fn texture(&mut self, gl: Rc<GL>, src: &str, texture_name: &str) {
-        let texture = Texture::new(gl.clone(), src);
-        self.textures.insert(texture_name.to_string(), texture);
+    /// Overwrites the indices from `first` on, growing the index buffer
+    /// when they do not fit. They have to be of the type already in use,
+    /// and can start at most right after the last index.
+    pub fn update_index_range<T: IndexType>(&mut self, gl: &dyn GlBackend, first: usize, data: &[T]) -> Result<()> {
+        if first > self.num_indices as usize {
+            return Err(Error::BufferUpdate(format!(
+                "cannot update from index {} when there are {}", first, self.num_indices
+            )));
+        }
+        let buffer = match self.index_buffer.as_mut() {
+            Some(_) if self.indices_type != T::FORMAT => {
+                return Err(Error::TypeMismatch {
+                    name: "indices".to_string(),
+                    expected: self.indices_type.name().to_string(),
+                    found: T::FORMAT.name().to_string()
+                });
+            }
+            Some(buffer) => buffer,
+            None => return self.update_indices(gl, data, BufferUsage::Dynamic)
+        };
+
+        gl.bind_vertex_array(Some(self.vao.id()));
+        let result = buffer.write(gl, first * size_of::<T>(), T::array_data(data));
+        if result.is_ok() {
+            self.num_indices = self.num_indices.max((first + data.len()) as u32);
+        }
+        gl.bind_vertex_array(None);
+        result.map(|_| ())
     }
 
-    fn bind(&self, gl: &GL) {
-        gl.use_program(Some(&self.shader.program));
-        gl.bind_vertex_array(Some(&self.vao));
-        for (name, location) in &self.attributes {
-            gl.enable_vertex_attrib_array(*location);
+    pub fn indices(&mut self, gl: &dyn GlBackend, indices: &Indices) -> Result<()> {
+        match indices {
+            Indices::U16(data) => self.index_buffer(gl, data.as_slice()),
+            Indices::U32(data) => self.index_buffer(gl, data.as_slice())
         }
-        let mut texture_unit = 0i32;
-        for (texture_name, texture) in &self.textures {
-            let location = self.shader.get_uniform_location(gl, &texture_name);
-            gl.active_texture(GL::TEXTURE0 + texture_unit as u32);
-            gl.bind_texture(GL::TEXTURE_2D, Some(texture.get_texture()));
-            gl.uniform1i(location.as_ref(), texture_unit);
+    }
+
+    /// Loads the image at `src` for the sampler `texture_name`, sampled as
+    /// `options` describes.
+    pub fn texture(&mut self, gl: &dyn GlBackend, src: &str, texture_name: &str, options: &TextureOptions) -> Result<()> {
+        self.check_sampler(texture_name, GL::TEXTURE_2D, false)?;
+        let texture = Texture::new(gl, src, options)?;
+        self.set_texture(gl, texture_name, texture)
+    }
+
+    /// Reads `texture` from the sampler uniform `name`, which has to be of
+    /// a type matching the texture target, and reading integers exactly
+    /// when the texture holds them. Each uniform keeps the texture
+    /// unit it got when first set, in order, so that replacing its texture
+    /// leaves the others alone.
+    pub fn set_texture(&mut self, gl: &dyn GlBackend, name: &str, texture: Texture) -> Result<()> {
+        self.check_sampler(name, texture.target(), texture.is_integer())?;
+        if let Some(binding) = self.textures.iter_mut().find(|b| b.uniform == name) {
+            binding.texture = texture;
+            return Ok(());
         }
+
+        let available = gl.max_combined_texture_image_units();
+        let needed = self.textures.len() + 1;
+        if needed > available as usize {
+            return Err(Error::TextureUnits { needed, available });
+        }
+        self.material.set(name, UniformValue::Sampler(self.textures.len() as i32))?;
+        self.textures.push(TextureBinding { uniform: name.to_string(), texture, sampler: None });
+        Ok(())
+    }
+
+    /// Samples the texture set for `name` with `sampler` from now on.
+    pub fn set_sampler(&mut self, name: &str, sampler: &Sampler) -> Result<()> {
+        let binding = self.textures.iter_mut()
+            .find(|b| b.uniform == name)
+            .ok_or_else(|| Error::MissingTexture(name.to_string()))?;
+        binding.sampler = Some(sampler.clone());
+        Ok(())
+    }
+
+    /// The texture set for the sampler uniform `name`.
+    pub fn texture_mut(&mut self, name: &str) -> Result<&mut Texture> {
+        self.textures.iter_mut()
+            .find(|b| b.uniform == name)
+            .map(|binding| &mut binding.texture)
+            .ok_or_else(|| Error::MissingTexture(name.to_string()))
     }
 
-    fn unbind(&self, gl: &GL) {
-        for texture_unit in 0..self.textures.len() {
-            gl.active_texture(GL::TEXTURE0 + texture_unit as u32);
-            gl.bind_texture(GL::TEXTURE_2D, None);
+    /// Checks that the uniform `name` is a sampler reading from `target`,
+    /// and reading integers if `integer` is set.
+    fn check_sampler(&self, name: &str, target: u32, integer: bool) -> Result<()> {
+        let uniform = self.material.shader().uniform(name)?;
+        if sampler_target(uniform.gl_type) != Some(target) || is_integer_sampler(uniform.gl_type) != integer {
+            let found = match (sampler_target(uniform.gl_type), integer) {
+                (Some(_), false) => format!("a {} texture", target_name(target)),
+                (Some(_), true) => format!("an integer {} texture", target_name(target)),
+                (None, _) => "a texture".to_string()
+            };
+            return Err(Error::TypeMismatch {
+                name: name.to_string(),
+                expected: glsl_type_name(uniform.gl_type).to_string(),
+                found
+            });
         }
-        for (name, location) in &self.attributes {
-            gl.disable_vertex_attrib_array(*location);
+        Ok(())
+    }
+
+    pub fn textures(&self) -> impl std::iter::Iterator<Item = &Texture> {
+        self.textures.iter().map(|binding| &binding.texture)
+    }
+
+    /// Creates the vertex array and textures again after the context was
+    /// lost. Vertex and index data has to be uploaded again; until then
+    /// nothing is drawn.
+    pub fn restore(&mut self, gl: &dyn GlBackend) -> Result<()> {
+        self.vao = gl.create_vertex_array()
+            .map(|vao| gl.resources().track(vao))
+            .ok_or(Error::ObjectCreation("vertex array"))?;
+        self.vertex_buffers.clear();
+        self.index_buffer = None;
+        self.num_indices = 0;
+        for binding in &mut self.textures {
+            binding.texture.restore(gl)?;
+            if let Some(sampler) = &binding.sampler {
+                sampler.restore(gl)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn bind(&self, gl: &dyn GlBackend) {
+        self.material.bind(gl);
+        gl.bind_vertex_array(Some(self.vao.id()));
+        for location in self.vertex_buffers.iter().flat_map(VertexBuffer::active_locations) {
+            gl.enable_vertex_attrib_array(location);
+        }
+        for (unit, binding) in self.textures.iter().enumerate() {
+            gl.active_texture(GL::TEXTURE0 + unit as u32);
+            gl.bind_texture(binding.texture.target(), Some(binding.texture.get_texture()));
+            if let Some(sampler) = &binding.sampler {
+                gl.bind_sampler(unit as u32, Some(sampler.id()));
+            }
+        }
+    }
+
+    fn unbind(&self, gl: &dyn GlBackend) {
+        for (unit, binding) in self.textures.iter().enumerate() {
+            gl.active_texture(GL::TEXTURE0 + unit as u32);
+            gl.bind_texture(binding.texture.target(), None);
+            if binding.sampler.is_some() {
+                gl.bind_sampler(unit as u32, None);
+            }
+        }
+        for location in self.vertex_buffers.iter().flat_map(VertexBuffer::active_locations) {
+            gl.disable_vertex_attrib_array(location);
         }
         gl.bind_vertex_array(None);
         gl.use_program(None);
     }
 }
 
+/// Checks that `len` bytes hold whole vertices of `layout`.
+fn check_vertex_data(layout: &VertexLayout, len: usize) -> Result<()> {
+    let stride = layout.stride() as usize;
+    if stride == 0 || !len.is_multiple_of(stride) {
+        let names: Vec<_> = layout.attributes().iter().map(|a| a.name.as_str()).collect();
+        return Err(Error::TypeMismatch {
+            name: names.join(", "),
+            expected: format!("a multiple of {} bytes", stride),
+            found: format!("{} bytes", len)
+        });
+    }
+    Ok(())
+}
+
+
+
 impl Render for Renderable {
-    fn render(&self, gl: &GL, model_matrix: &Transform3<f32>, camera: &Camera) {
+    fn render(&self, gl: &dyn GlBackend, model_matrix: &Transform3<f32>, camera: &Camera) {
         self.bind(gl);
 
-        let view_m = camera.view().to_homogeneous();
-        let projection_m = camera.projection();
+        // The view and projection come from the FrameUniforms block.
         let model_view_m = camera.view() * model_matrix;
         let model_view_rot_m: Rotation3<f32> = nalgebra::convert_unchecked(model_view_m);
         let normal_m = model_view_rot_m.inverse().transpose();
 
-        let proj_uni = self.shader.get_uniform_location(gl, "u_projectionMatrix");
-        gl.uniform_matrix4fv_with_f32_array(proj_uni.as_ref(), false, projection_m.as_matrix().as_slice());
-
-        let model_view_uni = self.shader.get_uniform_location(gl, "u_modelViewMatrix");
-        gl.uniform_matrix4fv_with_f32_array(model_view_uni.as_ref(), false, model_view_m.to_homogeneous().as_slice());
+        let model_view_uni = self.material.shader().uniform_location("u_modelViewMatrix");
+        gl.uniform_matrix4fv(model_view_uni, false, model_view_m.to_homogeneous().as_slice());
 
-        let normal_matrix_uni = self.shader.get_uniform_location(gl, "u_normalMatrix");
-        gl.uniform_matrix3fv_with_f32_array(normal_matrix_uni.as_ref(), false, normal_m.matrix().as_slice());
+        let normal_matrix_uni = self.material.shader().uniform_location("u_normalMatrix");
+        gl.uniform_matrix3fv(normal_matrix_uni, false, normal_m.matrix().as_slice());
 
-        gl.draw_elements_with_i32(GL::TRIANGLES, self.num_indices as i32, self.indices_type, 0);
+        gl.draw_elements(GL::TRIANGLES, self.num_indices as i32, self.indices_type.to_gl(), 0);
 
         self.unbind(gl);
     }
 }
 
 
-pub trait CreateArray {
+pub trait CreateArray: Sized {
     fn create_array(begin: u32, end: u32) -> Object;
+    fn array_data(data: &[Self]) -> ArrayData<'_>;
     fn data_type() -> u32;
 }
 
@@ -151,6 +467,9 @@ impl CreateArray for f32 {
             .dyn_into()
             .unwrap()
     }
+    fn array_data(data: &[Self]) -> ArrayData<'_> {
+        ArrayData::F32(data)
+    }
     fn data_type() -> u32 {
         GL::FLOAT
     }
@@ -163,11 +482,29 @@ impl CreateArray for u8 {
             .dyn_into()
             .unwrap()
     }
+    fn array_data(data: &[Self]) -> ArrayData<'_> {
+        ArrayData::U8(data)
+    }
     fn data_type() -> u32 {
         GL::UNSIGNED_BYTE
     }
 }
 
+impl CreateArray for i16 {
+    fn create_array(begin: u32, end: u32) -> Object {
+        Int16Array::new(&get_memory_buffer())
+            .subarray(begin, end)
+            .dyn_into()
+            .unwrap()
+    }
+    fn array_data(data: &[Self]) -> ArrayData<'_> {
+        ArrayData::I16(data)
+    }
+    fn data_type() -> u32 {
+        GL::SHORT
+    }
+}
+
 impl CreateArray for u16 {
     fn create_array(begin: u32, end: u32) -> Object {
         Uint16Array::new(&get_memory_buffer())
@@ -175,6 +512,9 @@ impl CreateArray for u16 {
             .dyn_into()
             .unwrap()
     }
+    fn array_data(data: &[Self]) -> ArrayData<'_> {
+        ArrayData::U16(data)
+    }
     fn data_type() -> u32 {
         GL::UNSIGNED_SHORT
     }
@@ -187,6 +527,9 @@ impl CreateArray for u32 {
             .dyn_into()
             .unwrap()
     }
+    fn array_data(data: &[Self]) -> ArrayData<'_> {
+        ArrayData::U32(data)
+    }
     fn data_type() -> u32 {
         GL::UNSIGNED_INT
     }
@@ -194,3 +537,478 @@ impl CreateArray for u32 {
 
 
 
+/// The types `drawElements` takes indices of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexFormat {
+    U8,
+    U16,
+    U32
+}
+
+impl IndexFormat {
+    fn to_gl(self) -> u32 {
+        match self {
+            IndexFormat::U8 => GL::UNSIGNED_BYTE,
+            IndexFormat::U16 => GL::UNSIGNED_SHORT,
+            IndexFormat::U32 => GL::UNSIGNED_INT
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            IndexFormat::U8 => "u8",
+            IndexFormat::U16 => "u16",
+            IndexFormat::U32 => "u32"
+        }
+    }
+}
+
+/// Array types that can hold indices.
+pub trait IndexType: CreateArray {
+    const FORMAT: IndexFormat;
+}
+
+impl IndexType for u8 {
+    const FORMAT: IndexFormat = IndexFormat::U8;
+}
+
+impl IndexType for u16 {
+    const FORMAT: IndexFormat = IndexFormat::U16;
+}
+
+impl IndexType for u32 {
+    const FORMAT: IndexFormat = IndexFormat::U32;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::TextureFormat;
+    use crate::backend::{GlCall, MockBackend, ResourceStats, TextureId};
+
+    static VS: &str = "#version 300 es\nin vec4 a_position;\nin vec4 a_color;\nvoid main() {}\n";
+    static FS: &str = "#version 300 es\nuniform sampler2D s_texture;\nuniform float u_scale;\nout vec4 outColor;\nvoid main() {}\n";
+
+    fn renderable(gl: &MockBackend) -> Renderable {
+        Renderable::new(gl, Rc::new(Shader::new(gl, VS, FS).unwrap())).unwrap()
+    }
+
+    #[test]
+    fn unknown_attributes_are_rejected() {
+        let gl = MockBackend::new();
+        let mut r = renderable(&gl);
+
+        assert_eq!(
+            r.vertex_attribute(&gl, "a_normal", &[0.0f32, 0.0, 1.0], 3),
+            Err(Error::MissingAttribute("a_normal".to_string()))
+        );
+        assert!(r.vertex_attribute(&gl, "a_position", &[0.0f32, 0.0, 1.0], 3).is_ok());
+    }
+
+    #[test]
+    fn unknown_samplers_are_rejected() {
+        let gl = MockBackend::new();
+        let mut r = renderable(&gl);
+
+        assert_eq!(
+            r.texture(&gl, "/data/world.jpg", "s_diffuse", &TextureOptions::default()),
+            Err(Error::MissingUniform("s_diffuse".to_string()))
+        );
+        assert!(r.texture(&gl, "/data/world.jpg", "s_texture", &TextureOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn attribute_data_is_checked_against_the_declaration() {
+        let gl = MockBackend::new();
+        let mut r = renderable(&gl);
+
+        assert_eq!(
+            r.vertex_attribute(&gl, "a_position", &[0.0f32; 10], 5),
+            Err(Error::TypeMismatch {
+                name: "a_position".to_string(),
+                expected: "vec4".to_string(),
+                found: "5 components".to_string()
+            })
+        );
+        assert_eq!(
+            r.vertex_attribute(&gl, "a_position", &[0.0f32; 4], 3),
+            Err(Error::TypeMismatch {
+                name: "a_position".to_string(),
+                expected: "a multiple of 3 values".to_string(),
+                found: "4 values".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn interleaved_vertices_share_one_buffer() {
+        let gl = MockBackend::new();
+        let mut r = renderable(&gl);
+        gl.clear_calls();
+
+        // Two vertices of three floats followed by four normalized bytes.
+        let layout = VertexLayout::new()
+            .attribute::<f32>("a_position", 3)
+            .normalized::<u8>("a_color", 4);
+        let mut vertex = Vec::new();
+        for value in &[1.0f32, 2.0, 3.0] {
+            vertex.extend_from_slice(&value.to_le_bytes());
+        }
+        vertex.extend_from_slice(&[255, 0, 0, 255]);
+        let data = [vertex.clone(), vertex].concat();
+        r.vertex_buffer(&gl, &layout, &data).unwrap();
+
+        let calls = gl.calls();
+        assert_eq!(calls.iter().filter(|c| matches!(c, GlCall::CreateBuffer(_))).count(), 1);
+        assert!(calls.contains(&GlCall::VertexAttribPointer {
+            index: 0, size: 3, data_type: GL::FLOAT, normalized: false, stride: 16, offset: 0
+        }));
+        assert!(calls.contains(&GlCall::VertexAttribPointer {
+            index: 1, size: 4, data_type: GL::UNSIGNED_BYTE, normalized: true, stride: 16, offset: 12
+        }));
+
+        assert_eq!(
+            r.vertex_buffer(&gl, &layout, &data[..20]),
+            Err(Error::TypeMismatch {
+                name: "a_position, a_color".to_string(),
+                expected: "a multiple of 16 bytes".to_string(),
+                found: "20 bytes".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn buffers_are_kept_and_reused() {
+        let gl = MockBackend::new();
+        let mut r = renderable(&gl);
+
+        r.vertex_attribute(&gl, "a_position", &[0.0f32, 0.0, 1.0], 3).unwrap();
+        r.vertex_attribute(&gl, "a_color", &[255u16, 0, 0, 65535], 4).unwrap();
+        r.index_buffer(&gl, &[0u16, 1, 2]).unwrap();
+        let buffers: Vec<_> = r.vertex_buffers.iter().map(|b| b.buffer.id()).collect();
+        let indices = r.index_buffer.clone();
+        assert_eq!(buffers.len(), 2);
+
+        gl.clear_calls();
+        r.vertex_attribute(&gl, "a_position", &[1.0f32, 0.0, 1.0], 3).unwrap();
+        r.index_buffer(&gl, &[2u16, 1, 0]).unwrap();
+        assert!(!gl.calls().iter().any(|c| matches!(c, GlCall::CreateBuffer(_))));
+        assert_eq!(r.vertex_buffers.iter().map(|b| b.buffer.id()).collect::<Vec<_>>(), buffers);
+        assert_eq!(r.index_buffer, indices);
+
+        // Moving both attributes into an interleaved buffer drops the old ones.
+        let layout = VertexLayout::new()
+            .attribute::<f32>("a_position", 3)
+            .attribute::<f32>("a_color", 4);
+        r.vertex_buffer(&gl, &layout, &[0.0f32; 7]).unwrap();
+        assert_eq!(r.vertex_buffers.len(), 1);
+        assert_eq!(r.vertex_buffers[0].locations, vec![Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn attributes_can_be_updated_in_place() {
+        let gl = MockBackend::new();
+        let mut r = renderable(&gl);
+        let layout = VertexLayout::new()
+            .attribute::<f32>("a_position", 4)
+            .attribute::<f32>("a_color", 4);
+        r.vertex_buffer(&gl, &layout, &[0.0f32; 16]).unwrap();
+        let buffer = r.vertex_buffers[0].buffer.id();
+
+        gl.clear_calls();
+        r.update_vertices(&gl, "a_color", &[1.0f32; 24], BufferUsage::Stream).unwrap();
+        assert_eq!(gl.calls(), vec![
+            GlCall::BindBuffer(GL::ARRAY_BUFFER, Some(buffer)),
+            GlCall::BufferData { target: GL::ARRAY_BUFFER, data_type: GL::FLOAT, len: 24, usage: GL::STREAM_DRAW }
+        ]);
+
+        gl.clear_calls();
+        r.update_vertex_range(&gl, "a_position", 1, &[1.0f32; 8]).unwrap();
+        assert!(gl.calls().contains(
+            &GlCall::BufferSubData { target: GL::ARRAY_BUFFER, offset: 32, data_type: GL::FLOAT, len: 8 }
+        ));
+        // Vertex 4 would leave vertex 3 unwritten.
+        assert_eq!(
+            r.update_vertex_range(&gl, "a_position", 4, &[1.0f32; 8]),
+            Err(Error::BufferUpdate("cannot update from vertex 4 when there are 3".to_string()))
+        );
+
+        assert_eq!(
+            r.update_vertices(&gl, "a_color", &[1.0f32; 4], BufferUsage::Stream),
+            Err(Error::TypeMismatch {
+                name: "a_position, a_color".to_string(),
+                expected: "a multiple of 32 bytes".to_string(),
+                found: "16 bytes".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn growing_a_vertex_buffer_points_the_attributes_again() {
+        let gl = MockBackend::new();
+        let mut r = renderable(&gl);
+        r.vertex_attribute(&gl, "a_position", &[0.0f32; 8], 4).unwrap();
+
+        gl.clear_calls();
+        r.update_vertex_range(&gl, "a_position", 2, &[1.0f32; 4]).unwrap();
+        let calls = gl.calls();
+        let new = r.vertex_buffers[0].buffer.id();
+        let pointed = calls.iter().position(|c| matches!(c, GlCall::VertexAttribPointer { index: 0, .. })).unwrap();
+        assert!(calls[..pointed].contains(&GlCall::BindBuffer(GL::ARRAY_BUFFER, Some(new))));
+        assert_eq!(calls.first(), Some(&GlCall::BindVertexArray(Some(r.vao.id()))));
+        // The buffer doubled, but only holds 3 vertices so far.
+        assert_eq!(
+            r.update_vertex_range(&gl, "a_position", 4, &[1.0f32; 4]),
+            Err(Error::BufferUpdate("cannot update from vertex 4 when there are 3".to_string()))
+        );
+        assert_eq!(
+            r.update_vertex_range(&gl, "a_color", 0, &[1.0f32; 4]),
+            Err(Error::MissingAttribute("a_color".to_string()))
+        );
+    }
+
+    #[test]
+    fn index_ranges_extend_the_draw() {
+        let gl = MockBackend::new();
+        let mut r = renderable(&gl);
+        r.update_indices(&gl, &[0u16, 1, 2], BufferUsage::Dynamic).unwrap();
+        let old = r.index_buffer.as_ref().unwrap().id();
+
+        r.update_index_range(&gl, 3, &[2u16, 1, 3]).unwrap();
+        assert_eq!(r.num_indices, 6);
+        assert_eq!(
+            r.update_index_range(&gl, 7, &[0u16]),
+            Err(Error::BufferUpdate("cannot update from index 7 when there are 6".to_string()))
+        );
+        assert_eq!(r.num_indices, 6);
+        gl.delete_released();
+        assert!(gl.calls().contains(&GlCall::DeleteBuffer(old)));
+
+        assert_eq!(
+            r.update_index_range(&gl, 0, &[0u32, 1, 2]),
+            Err(Error::TypeMismatch {
+                name: "indices".to_string(),
+                expected: "u16".to_string(),
+                found: "u32".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn dropping_the_last_clone_releases_everything() {
+        let gl = MockBackend::new();
+        let mut r = renderable(&gl);
+        r.vertex_attribute(&gl, "a_position", &[0.0f32; 4], 4).unwrap();
+        r.index_buffer(&gl, &[0u16, 1, 2]).unwrap();
+        r.texture(&gl, "/data/world.jpg", "s_texture", &TextureOptions::default()).unwrap();
+        let shared = r.clone();
+        let (vao, texture) = (r.vao.id(), r.textures[0].texture.get_texture());
+
+        let stats = gl.resources().stats();
+        assert_eq!((stats.buffers, stats.textures, stats.vertex_arrays), (2, 1, 1));
+        assert_eq!(stats.bytes, 16 + 6 + 4);
+
+        drop(r);
+        gl.clear_calls();
+        gl.delete_released();
+        assert!(gl.calls().is_empty());
+
+        drop(shared);
+        gl.delete_released();
+        let calls = gl.calls();
+        assert_eq!(calls.iter().filter(|c| matches!(c, GlCall::DeleteBuffer(_))).count(), 2);
+        assert!(calls.contains(&GlCall::DeleteVertexArray(vao)));
+        assert!(calls.contains(&GlCall::DeleteTexture(texture)));
+        // The shader went with its last renderable.
+        assert_eq!(calls.iter().filter(|c| matches!(c, GlCall::DeleteProgram(_))).count(), 1);
+        assert_eq!(gl.resources().stats(), ResourceStats::default());
+    }
+
+    #[test]
+    fn restoring_recreates_the_objects() {
+        let gl = MockBackend::new();
+        let mut r = renderable(&gl);
+        r.vertex_attribute(&gl, "a_position", &[0.0f32; 4], 4).unwrap();
+        r.index_buffer(&gl, &[0u16, 1, 2]).unwrap();
+        r.texture(&gl, "/data/world.jpg", "s_texture", &TextureOptions::default()).unwrap();
+        let (vao, texture) = (r.vao.id(), r.textures[0].texture.get_texture());
+
+        gl.reset();
+        gl.clear_calls();
+        r.restore(&gl).unwrap();
+
+        assert_ne!(r.vao.id(), vao);
+        assert_ne!(r.textures[0].texture.get_texture(), texture);
+        let texture = r.textures[0].texture.get_texture();
+        assert!(gl.calls().iter().any(|c| matches!(c, GlCall::LoadImage(t, src, _) if *t == texture && src == "/data/world.jpg")));
+        assert_eq!(r.num_indices, 0);
+        let stats = gl.resources().stats();
+        assert_eq!((stats.buffers, stats.textures, stats.vertex_arrays), (0, 1, 1));
+
+        r.vertex_attribute(&gl, "a_position", &[0.0f32; 4], 4).unwrap();
+        r.index_buffer(&gl, &[0u16, 1, 2]).unwrap();
+        assert_eq!(r.num_indices, 3);
+    }
+
+    static MULTI_FS: &str = "#version 300 es\nuniform sampler2D s_color;\nuniform sampler2D s_bump;\nuniform samplerCube s_sky;\nout vec4 outColor;\nvoid main() {}\n";
+
+    fn bound_textures(gl: &MockBackend) -> Vec<(u32, u32, Option<TextureId>)> {
+        let mut unit = 0;
+        gl.calls().into_iter()
+            .filter_map(|c| match c {
+                GlCall::ActiveTexture(texture_unit) => {
+                    unit = texture_unit - GL::TEXTURE0;
+                    None
+                },
+                GlCall::BindTexture(target, texture) => Some((unit, target, texture)),
+                _ => None
+            })
+            .collect()
+    }
+
+    #[test]
+    fn texture_units_follow_the_order_samplers_were_set_in() {
+        let gl = MockBackend::new();
+        let mut r = Renderable::new(&gl, Rc::new(Shader::new(&gl, VS, MULTI_FS).unwrap())).unwrap();
+        let options = TextureOptions::default();
+        r.texture(&gl, "/data/bump.jpg", "s_bump", &options).unwrap();
+        r.texture(&gl, "/data/world.jpg", "s_color", &options).unwrap();
+        assert_eq!(r.material.get("s_bump"), Some(&UniformValue::Sampler(0)));
+        assert_eq!(r.material.get("s_color"), Some(&UniformValue::Sampler(1)));
+
+        // Replacing a texture keeps its unit.
+        r.texture(&gl, "/data/bump2.jpg", "s_bump", &options).unwrap();
+        let (bump, color) = (r.textures[0].texture.get_texture(), r.textures[1].texture.get_texture());
+        gl.clear_calls();
+        r.render(&gl, &Transform3::identity(), &Camera::new(1.0, 1.0, 1.0, 1000.0));
+        assert_eq!(bound_textures(&gl), vec![
+            (0, GL::TEXTURE_2D, Some(bump)),
+            (1, GL::TEXTURE_2D, Some(color)),
+            (0, GL::TEXTURE_2D, None),
+            (1, GL::TEXTURE_2D, None)
+        ]);
+    }
+
+    #[test]
+    fn textures_have_to_match_the_sampler_type() {
+        let gl = MockBackend::new();
+        let mut r = Renderable::new(&gl, Rc::new(Shader::new(&gl, VS, MULTI_FS).unwrap())).unwrap();
+
+        assert_eq!(
+            r.texture(&gl, "/data/sky.jpg", "s_sky", &TextureOptions::default()),
+            Err(Error::TypeMismatch {
+                name: "s_sky".to_string(),
+                expected: "samplerCube".to_string(),
+                found: "a 2D texture".to_string()
+            })
+        );
+        assert!(!gl.calls().iter().any(|c| matches!(c, GlCall::LoadImage(..))));
+    }
+
+    #[test]
+    fn cube_maps_and_arrays_bind_to_their_samplers() {
+        let gl = MockBackend::new();
+        let fs = "#version 300 es\nuniform samplerCube s_sky;\nuniform mediump sampler2DArray s_layers;\nout vec4 outColor;\nvoid main() {}\n";
+        let mut r = Renderable::new(&gl, Rc::new(Shader::new(&gl, VS, fs).unwrap())).unwrap();
+        let options = TextureOptions::default();
+        let sky = Texture::from_data_with_target(&gl, GL::TEXTURE_CUBE_MAP, TextureFormat::RGBA8, [1, 1, 6], &[0u8; 24], &options).unwrap();
+        let layers = Texture::from_data_with_target(&gl, GL::TEXTURE_2D_ARRAY, TextureFormat::R8, [2, 2, 4], &[0u8; 16], &options).unwrap();
+
+        assert_eq!(
+            r.set_texture(&gl, "s_sky", layers.clone()),
+            Err(Error::TypeMismatch {
+                name: "s_sky".to_string(),
+                expected: "samplerCube".to_string(),
+                found: "a 2D array texture".to_string()
+            })
+        );
+        r.set_texture(&gl, "s_sky", sky.clone()).unwrap();
+        r.set_texture(&gl, "s_layers", layers.clone()).unwrap();
+
+        gl.clear_calls();
+        r.render(&gl, &Transform3::identity(), &Camera::new(1.0, 1.0, 1.0, 1000.0));
+        assert_eq!(bound_textures(&gl), vec![
+            (0, GL::TEXTURE_CUBE_MAP, Some(sky.get_texture())),
+            (1, GL::TEXTURE_2D_ARRAY, Some(layers.get_texture())),
+            (0, GL::TEXTURE_CUBE_MAP, None),
+            (1, GL::TEXTURE_2D_ARRAY, None)
+        ]);
+    }
+
+    #[test]
+    fn integer_textures_need_an_integer_sampler() {
+        let gl = MockBackend::new();
+        let fs = "#version 300 es\nuniform sampler2D s_color;\nuniform usampler2D s_ids;\nout vec4 outColor;\nvoid main() {}\n";
+        let mut r = Renderable::new(&gl, Rc::new(Shader::new(&gl, VS, fs).unwrap())).unwrap();
+        let ids = Texture::from_data(&gl, TextureFormat::R32UI, 1, 1, &[7u32], &TextureOptions::default()).unwrap();
+
+        assert_eq!(
+            r.set_texture(&gl, "s_color", ids.clone()),
+            Err(Error::TypeMismatch {
+                name: "s_color".to_string(),
+                expected: "sampler2D".to_string(),
+                found: "an integer 2D texture".to_string()
+            })
+        );
+        assert!(r.set_texture(&gl, "s_ids", ids).is_ok());
+        assert!(r.texture(&gl, "/data/world.jpg", "s_ids", &TextureOptions::default()).is_err());
+    }
+
+    #[test]
+    fn texture_units_are_limited() {
+        let gl = MockBackend::new();
+        let mut r = Renderable::new(&gl, Rc::new(Shader::new(&gl, VS, MULTI_FS).unwrap())).unwrap();
+        gl.set_max_texture_units(1);
+
+        r.texture(&gl, "/data/world.jpg", "s_color", &TextureOptions::default()).unwrap();
+        assert_eq!(
+            r.texture(&gl, "/data/bump.jpg", "s_bump", &TextureOptions::default()),
+            Err(Error::TextureUnits { needed: 2, available: 1 })
+        );
+        assert_eq!(r.material.get("s_bump"), None);
+    }
+
+    #[test]
+    fn samplers_are_bound_with_their_textures() {
+        let gl = MockBackend::new();
+        let mut r = Renderable::new(&gl, Rc::new(Shader::new(&gl, VS, MULTI_FS).unwrap())).unwrap();
+        let sampler = Sampler::new(&gl, &TextureOptions::default()).unwrap();
+        assert_eq!(r.set_sampler("s_color", &sampler), Err(Error::MissingTexture("s_color".to_string())));
+
+        r.texture(&gl, "/data/bump.jpg", "s_bump", &TextureOptions::default()).unwrap();
+        r.texture(&gl, "/data/world.jpg", "s_color", &TextureOptions::default()).unwrap();
+        r.set_sampler("s_color", &sampler).unwrap();
+        gl.clear_calls();
+        r.render(&gl, &Transform3::identity(), &Camera::new(1.0, 1.0, 1.0, 1000.0));
+
+        let samplers: Vec<_> = gl.calls().into_iter()
+            .filter(|c| matches!(c, GlCall::BindSampler(..)))
+            .collect();
+        assert_eq!(samplers, vec![GlCall::BindSampler(1, Some(sampler.id())), GlCall::BindSampler(1, None)]);
+    }
+
+    #[test]
+    fn textures_need_a_sampler() {
+        let gl = MockBackend::new();
+        let mut r = renderable(&gl);
+
+        assert_eq!(
+            r.texture(&gl, "/data/world.jpg", "u_scale", &TextureOptions::default()),
+            Err(Error::TypeMismatch {
+                name: "u_scale".to_string(),
+                expected: "float".to_string(),
+                found: "a texture".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn out_of_memory_is_reported() {
+        let gl = MockBackend::new();
+        let mut r = renderable(&gl);
+
+        gl.set_error(GL::OUT_OF_MEMORY);
+        assert_eq!(r.index_buffer(&gl, &[0u16, 1, 2]), Err(Error::OutOfMemory));
+        assert_eq!(r.num_indices, 0);
+    }
+}
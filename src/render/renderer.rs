@@ -1,38 +1,59 @@
-use std::rc::Rc;
 use web_sys::WebGl2RenderingContext as GL;
-use web_sys::*;
 use wasm_bindgen::JsValue;
-use wasm_bindgen::JsCast;
+use nalgebra::{Unit, Vector3};
 
-pub(self) use self::super::camera::*;
-pub(self) use self::super::globe::*;
-pub(self) use self::super::renderable::*;
-use nalgebra::{Transform3, Similarity3, Vector3};
+use crate::backend::GlBackend;
+use super::camera::*;
+use super::{FrameUniformBuffer, FrameUniforms};
+use crate::scene::Scene;
 
 
 #[derive(Clone)]
 pub struct Renderer {
+    clear_color: [f32; 4],
+    frame_uniforms: Option<FrameUniformBuffer>
 }
 
 impl Renderer {
 
-    pub fn new(gl: Rc<GL>) -> Self {
-        Renderer {}
+    pub fn new(clear_color: [f32; 4]) -> Self {
+        Renderer { clear_color, frame_uniforms: None }
     }
 
-    pub fn init(&mut self, gl: &GL) -> Result<(), JsValue> {
+    /// Sets up the context state and the objects the renderer draws with.
+    /// Called again once a lost context is restored.
+    pub fn init(&mut self, gl: &dyn GlBackend) -> Result<(), JsValue> {
+        gl.enable(GL::DEPTH_TEST);
+        self.frame_uniforms = Some(FrameUniformBuffer::new(gl)?);
         Ok(())
     }
 
-    pub fn render(&self, gl: &GL, camera: &Camera, renderables: &[Box<dyn Render>]) -> Result<(), JsValue>{
-        let canvas: HtmlCanvasElement = gl.canvas().unwrap().dyn_into()?;
+    /// Draws the visible nodes of `scene`. `time` and `sun_direction` go
+    /// into the `FrameUniforms` block along with the camera.
+    pub fn render(
+        &self,
+        gl: &dyn GlBackend,
+        camera: &Camera,
+        scene: &Scene,
+        time: f32,
+        sun_direction: Unit<Vector3<f32>>
+    ) -> Result<(), JsValue> {
+        // Objects dropped since the last frame are no longer drawn with.
+        gl.delete_released();
+
+        let (width, height) = gl.drawing_buffer_size();
+
+        if let Some(buffer) = &self.frame_uniforms {
+            buffer.upload(gl, &FrameUniforms::new(camera, (width, height), time, sun_direction));
+        }
 
         // Set background color
-        gl.clear_color(0.0, 0.0, 0.0, 1.0);
+        let [r, g, b, a] = self.clear_color;
+        gl.clear_color(r, g, b, a);
         gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
 
         // Set the viewport
-        gl.viewport(0, 0, canvas.width() as i32, canvas.height() as i32);
+        gl.viewport(0, 0, width, height);
 
         // Set options
         gl.enable(GL::BLEND);
@@ -42,8 +63,7 @@ impl Renderer {
         gl.cull_face(GL::BACK);
 
         // Draw elements
-        let model_matrix = Transform3::identity();
-        for r in renderables {
+        for (model_matrix, r) in scene.visible_renderables() {
             r.render(gl, &model_matrix, camera);
         }
 
@@ -56,3 +76,168 @@ impl Renderer {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{GlCall, MockBackend, ResourceStats};
+    use crate::shader::{ShaderCache, ShaderRegistry};
+    use crate::geometry::Mesh;
+    use crate::options::WebClientOptions;
+    use crate::render::{Globe, Renderable};
+    use crate::scene::Node;
+    use std::rc::Rc;
+
+    fn sun() -> Unit<Vector3<f32>> {
+        Vector3::x_axis()
+    }
+
+    fn globe(gl: &MockBackend) -> Globe {
+        let maps = WebClientOptions::default().globe_maps();
+        Globe::new(gl, &mut ShaderCache::new(ShaderRegistry::embedded()), Mesh::uv_sphere(200.0, 40, 30).unwrap(), &maps).unwrap()
+    }
+
+    #[test]
+    fn render_draws_every_renderable() {
+        let gl = MockBackend::new();
+        let globe = globe(&gl);
+        let camera = Camera::new(30.0, 1.0, 1.0, 1000.0);
+        let mut scene = Scene::new();
+        scene.add(scene.root(), Node::with_renderable(Rc::new(globe))).unwrap();
+        gl.clear_calls();
+
+        Renderer::new([0.1, 0.2, 0.3, 1.0]).render(&gl, &camera, &scene, 0.0, sun()).unwrap();
+        let calls = gl.calls();
+
+        assert_eq!(calls[0], GlCall::ClearColor(0.1, 0.2, 0.3, 1.0));
+        assert_eq!(calls[1], GlCall::Clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT));
+        assert_eq!(calls[2], GlCall::Viewport(0, 0, 1024, 1024));
+
+        let draws: Vec<_> = calls.iter()
+            .filter(|c| matches!(c, GlCall::DrawElements { .. }))
+            .collect();
+        assert_eq!(draws.len(), 3);
+
+        let bound_textures: Vec<_> = calls.iter()
+            .filter(|c| matches!(c, GlCall::BindTexture(_, Some(_))))
+            .collect();
+        // The six maps and the overlay
+        assert_eq!(bound_textures.len(), 7);
+
+        assert_eq!(calls.last(), Some(&GlCall::Disable(GL::CULL_FACE)));
+    }
+
+    #[test]
+    fn viewport_follows_the_drawing_buffer() {
+        let gl = MockBackend::new();
+        let camera = Camera::new(1.0, 1.0, 1.0, 1000.0);
+        gl.set_drawing_buffer_size(1920, 1080);
+
+        Renderer::new([0.0, 0.0, 0.0, 1.0]).render(&gl, &camera, &Scene::new(), 0.0, sun()).unwrap();
+
+        assert!(gl.calls().contains(&GlCall::Viewport(0, 0, 1920, 1080)));
+    }
+
+    #[test]
+    fn render_uses_world_transforms_and_skips_hidden_nodes() {
+        let gl = MockBackend::new();
+        let shader = Rc::new(crate::shader::Shader::new(
+            &gl,
+            "#version 300 es\nuniform mat4 u_modelViewMatrix;\nvoid main() {}\n",
+            "#version 300 es\nout vec4 outColor;\nvoid main() {}\n"
+        ).unwrap());
+        let mut camera = Camera::new(1.0, 1.0, 1.0, 1000.0);
+        camera.set_position(0.0, 0.0, 10.0);
+
+        let mut scene = Scene::new();
+        let group = scene.add(scene.root(), Node::new()).unwrap();
+        scene.set_translation(group, Vector3::new(1.0, 2.0, 3.0)).unwrap();
+        let shown = Renderable::new(&gl, shader.clone()).unwrap();
+        scene.add(group, Node::with_renderable(Rc::new(shown))).unwrap();
+        let hidden = scene.add(scene.root(), Node::with_renderable(Rc::new(Renderable::new(&gl, shader).unwrap()))).unwrap();
+        scene.set_visible(hidden, false).unwrap();
+        gl.clear_calls();
+
+        Renderer::new([0.0, 0.0, 0.0, 1.0]).render(&gl, &camera, &scene, 0.0, sun()).unwrap();
+        let calls = gl.calls();
+
+        let model_views: Vec<_> = calls.iter()
+            .filter_map(|c| match c {
+                GlCall::UniformMatrix4fv(Some(location), data) if gl.uniform_name(*location).as_deref() == Some("u_modelViewMatrix") => Some(data.clone()),
+                _ => None
+            })
+            .collect();
+        assert_eq!(model_views.len(), 1);
+        // Column-major: the translation is in the last column.
+        assert_eq!(&model_views[0][12..15], &[1.0, 2.0, -7.0]);
+    }
+
+    #[test]
+    fn swapped_out_scenes_are_deleted_before_the_next_frame() {
+        let gl = MockBackend::new();
+        let globe = globe(&gl);
+        let camera = Camera::new(1.0, 1.0, 1.0, 1000.0);
+        let mut scene = Scene::new();
+        let node = scene.add(scene.root(), Node::with_renderable(Rc::new(globe))).unwrap();
+        let renderer = Renderer::new([0.0, 0.0, 0.0, 1.0]);
+        let live = gl.resources().stats();
+        assert_eq!((live.buffers, live.vertex_arrays, live.programs), (8, 4, 4));
+        assert!(live.uniform_locations > 0);
+
+        scene.remove(node).unwrap();
+        gl.clear_calls();
+        renderer.render(&gl, &camera, &scene, 0.0, sun()).unwrap();
+
+        let calls = gl.calls();
+        let deletes = calls.iter().take_while(|c| !matches!(c, GlCall::ClearColor(..))).count();
+        // The maps share one sampler.
+        assert_eq!(live.samplers, 1);
+        assert_eq!(deletes, 8 + 4 + 4 + live.textures + live.samplers);
+        assert_eq!(gl.resources().stats(), ResourceStats::default());
+    }
+
+    #[test]
+    fn frame_uniforms_are_uploaded_once_per_frame() {
+        let gl = MockBackend::new();
+        let globe = globe(&gl);
+        let camera = Camera::new(1.0, 1.0, 1.0, 1000.0);
+        let mut scene = Scene::new();
+        scene.add(scene.root(), Node::with_renderable(Rc::new(globe))).unwrap();
+        let mut renderer = Renderer::new([0.0, 0.0, 0.0, 1.0]);
+        gl.clear_calls();
+        renderer.init(&gl).unwrap();
+        let buffer = gl.calls().into_iter()
+            .find_map(|c| match c {
+                GlCall::CreateBuffer(buffer) => Some(buffer),
+                _ => None
+            })
+            .unwrap();
+        gl.clear_calls();
+
+        renderer.render(&gl, &camera, &scene, 0.0, sun()).unwrap();
+        let calls = gl.calls();
+
+        let uploads: Vec<_> = calls.iter()
+            .filter(|c| matches!(c, GlCall::BufferData { target: GL::UNIFORM_BUFFER, .. }))
+            .collect();
+        assert_eq!(uploads, vec![&GlCall::BufferData {
+            target: GL::UNIFORM_BUFFER,
+            data_type: GL::FLOAT,
+            len: FrameUniforms::LEN,
+            usage: GL::DYNAMIC_DRAW
+        }]);
+        let upload = calls.iter().position(|c| matches!(c, GlCall::BufferData { target: GL::UNIFORM_BUFFER, .. })).unwrap();
+        let first_draw = calls.iter().position(|c| matches!(c, GlCall::DrawElements { .. })).unwrap();
+        assert!(upload < first_draw);
+        assert!(calls.contains(&GlCall::BindBufferBase(GL::UNIFORM_BUFFER, 0, Some(buffer))));
+
+        // Draws only upload their own matrices.
+        let matrices: Vec<_> = calls.iter()
+            .filter_map(|c| match c {
+                GlCall::UniformMatrix4fv(Some(location), _) => gl.uniform_name(*location),
+                _ => None
+            })
+            .collect();
+        assert_eq!(matrices, vec!["u_modelViewMatrix"; 3]);
+    }
+}
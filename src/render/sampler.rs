@@ -0,0 +1,84 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::backend::{GlBackend, Handle, Resource, SamplerId};
+use crate::error::{Error, Result};
+use super::TextureOptions;
+
+
+/// A sampler object, which samples the textures it is bound with using its
+/// own parameters instead of theirs. Clones share the object, so that many
+/// textures can be read the same way.
+#[derive(Clone)]
+pub struct Sampler(Rc<SamplerObject>);
+
+struct SamplerObject {
+    handle: RefCell<Handle<SamplerId>>,
+    options: TextureOptions
+}
+
+impl Sampler {
+    /// Only the wrap, filter and anisotropy options apply.
+    pub fn new(gl: &dyn GlBackend, options: &TextureOptions) -> Result<Self> {
+        let handle = create(gl, options)?;
+        Ok(Sampler(Rc::new(SamplerObject { handle: RefCell::new(handle), options: *options })))
+    }
+
+    pub fn id(&self) -> SamplerId {
+        self.0.handle.borrow().id()
+    }
+
+    /// Creates the sampler object again after the context was lost. Clones
+    /// share the new object, so restoring one of them is enough; further
+    /// calls do nothing.
+    pub fn restore(&self, gl: &dyn GlBackend) -> Result<()> {
+        if gl.resources().is_live(Resource::Sampler(self.id())) {
+            return Ok(());
+        }
+        let handle = create(gl, &self.0.options)?;
+        self.0.handle.replace(handle);
+        Ok(())
+    }
+}
+
+
+fn create(gl: &dyn GlBackend, options: &TextureOptions) -> Result<Handle<SamplerId>> {
+    let sampler = gl.create_sampler()
+        .ok_or(Error::ObjectCreation("sampler"))?;
+    options.apply_sampling(
+        gl,
+        |pname, param| gl.sampler_parameteri(sampler, pname, param),
+        |pname, param| gl.sampler_parameterf(sampler, pname, param)
+    );
+    Ok(gl.resources().track(sampler))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use web_sys::WebGl2RenderingContext as GL;
+    use super::*;
+    use crate::backend::{GlCall, MockBackend};
+    use crate::render::TextureWrap;
+
+    #[test]
+    fn clones_share_the_object() {
+        let gl = MockBackend::new();
+        let sampler = Sampler::new(&gl, &TextureOptions { wrap_s: TextureWrap::Repeat, ..TextureOptions::default() }).unwrap();
+        let shared = sampler.clone();
+        assert!(gl.calls().contains(&GlCall::SamplerParameteri(sampler.id(), GL::TEXTURE_WRAP_S, GL::REPEAT as i32)));
+
+        gl.reset();
+        sampler.restore(&gl).unwrap();
+        shared.restore(&gl).unwrap();
+        let id = sampler.id();
+        assert_eq!(shared.id(), id);
+        assert_eq!(gl.calls().iter().filter(|c| matches!(c, GlCall::CreateSampler(_))).count(), 2);
+
+        drop(sampler);
+        drop(shared);
+        gl.clear_calls();
+        gl.delete_released();
+        assert_eq!(gl.calls(), vec![GlCall::DeleteSampler(id)]);
+    }
+}
@@ -1,74 +1,1070 @@
-use std::rc::Rc;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
 use web_sys::WebGl2RenderingContext as GL;
-use web_sys::*;
-use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
 
+use crate::backend::{ArrayData, GlBackend, Handle, ImageLoad, ImageUpload, Resource, TextureId, TextureState};
+use crate::backend::TEXTURE_MAX_ANISOTROPY_EXT;
+use crate::error::{Error, Result};
+use crate::image::Image;
+use super::CreateArray;
 
+
+/// What happens to texture coordinates outside of `[0, 1]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TextureWrap {
+    ClampToEdge,
+    /// Tiles the image, e.g. to wrap around the globe in longitude.
+    Repeat,
+    MirroredRepeat
+}
+
+impl TextureWrap {
+    fn to_gl(self) -> u32 {
+        match self {
+            TextureWrap::ClampToEdge => GL::CLAMP_TO_EDGE,
+            TextureWrap::Repeat => GL::REPEAT,
+            TextureWrap::MirroredRepeat => GL::MIRRORED_REPEAT
+        }
+    }
+}
+
+/// Filter used when the texture is drawn smaller than its size. The
+/// mipmap modes read from the mipmaps, which are then generated once the
+/// image has loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MinFilter {
+    Nearest,
+    Linear,
+    NearestMipmapNearest,
+    LinearMipmapNearest,
+    NearestMipmapLinear,
+    /// Trilinear filtering.
+    LinearMipmapLinear
+}
+
+impl MinFilter {
+    fn to_gl(self) -> u32 {
+        match self {
+            MinFilter::Nearest => GL::NEAREST,
+            MinFilter::Linear => GL::LINEAR,
+            MinFilter::NearestMipmapNearest => GL::NEAREST_MIPMAP_NEAREST,
+            MinFilter::LinearMipmapNearest => GL::LINEAR_MIPMAP_NEAREST,
+            MinFilter::NearestMipmapLinear => GL::NEAREST_MIPMAP_LINEAR,
+            MinFilter::LinearMipmapLinear => GL::LINEAR_MIPMAP_LINEAR
+        }
+    }
+
+    fn uses_mipmaps(self) -> bool {
+        !matches!(self, MinFilter::Nearest | MinFilter::Linear)
+    }
+}
+
+/// Filter used when the texture is drawn larger than its size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MagFilter {
+    Nearest,
+    Linear
+}
+
+impl MagFilter {
+    fn to_gl(self) -> u32 {
+        match self {
+            MagFilter::Nearest => GL::NEAREST,
+            MagFilter::Linear => GL::LINEAR
+        }
+    }
+}
+
+
+/// Storage of a texture filled from memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureFormat {
+    R8,
+    RG8,
+    RGBA8,
+    /// RGBA8 in sRGB, which the GPU converts to linear when sampling.
+    SRGBA8,
+    R16F,
+    R32F,
+    RGBA16F,
+    /// Read with a `usampler2D`.
+    R32UI
+}
+
+impl TextureFormat {
+    const ALL: [TextureFormat; 8] = [
+        TextureFormat::R8,
+        TextureFormat::RG8,
+        TextureFormat::RGBA8,
+        TextureFormat::SRGBA8,
+        TextureFormat::R16F,
+        TextureFormat::R32F,
+        TextureFormat::RGBA16F,
+        TextureFormat::R32UI
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            TextureFormat::R8 => "r8",
+            TextureFormat::RG8 => "rg8",
+            TextureFormat::RGBA8 => "rgba8",
+            TextureFormat::SRGBA8 => "srgba8",
+            TextureFormat::R16F => "r16f",
+            TextureFormat::R32F => "r32f",
+            TextureFormat::RGBA16F => "rgba16f",
+            TextureFormat::R32UI => "r32ui"
+        }
+    }
+
+    fn internal_format(self) -> u32 {
+        match self {
+            TextureFormat::R8 => GL::R8,
+            TextureFormat::RG8 => GL::RG8,
+            TextureFormat::RGBA8 => GL::RGBA8,
+            TextureFormat::SRGBA8 => GL::SRGB8_ALPHA8,
+            TextureFormat::R16F => GL::R16F,
+            TextureFormat::R32F => GL::R32F,
+            TextureFormat::RGBA16F => GL::RGBA16F,
+            TextureFormat::R32UI => GL::R32UI
+        }
+    }
+
+    /// Layout of the uploaded pixels.
+    fn format(self) -> u32 {
+        match self {
+            TextureFormat::R8 | TextureFormat::R16F | TextureFormat::R32F => GL::RED,
+            TextureFormat::RG8 => GL::RG,
+            TextureFormat::RGBA8 | TextureFormat::SRGBA8 | TextureFormat::RGBA16F => GL::RGBA,
+            TextureFormat::R32UI => GL::RED_INTEGER
+        }
+    }
+
+    pub fn components(self) -> usize {
+        match self {
+            TextureFormat::R8 | TextureFormat::R16F | TextureFormat::R32F | TextureFormat::R32UI => 1,
+            TextureFormat::RG8 => 2,
+            TextureFormat::RGBA8 | TextureFormat::SRGBA8 | TextureFormat::RGBA16F => 4
+        }
+    }
+
+    /// In bytes, as stored by the GPU.
+    fn texel_size(self) -> usize {
+        match self {
+            TextureFormat::R8 => 1,
+            TextureFormat::RG8 | TextureFormat::R16F => 2,
+            TextureFormat::RGBA8 | TextureFormat::SRGBA8 | TextureFormat::R32F | TextureFormat::R32UI => 4,
+            TextureFormat::RGBA16F => 8
+        }
+    }
+
+    /// GL type of uploaded pixels, given the type of the data, or `None`
+    /// when the data does not fit the format. `u16` values are half floats.
+    fn pixel_type(self, data_type: u32) -> Option<u32> {
+        match (self, data_type) {
+            (TextureFormat::R8 | TextureFormat::RG8 | TextureFormat::RGBA8 | TextureFormat::SRGBA8, GL::UNSIGNED_BYTE) => Some(GL::UNSIGNED_BYTE),
+            (TextureFormat::R16F | TextureFormat::RGBA16F, GL::UNSIGNED_SHORT) => Some(GL::HALF_FLOAT),
+            (TextureFormat::R16F | TextureFormat::RGBA16F | TextureFormat::R32F, GL::FLOAT) => Some(GL::FLOAT),
+            (TextureFormat::R32UI, GL::UNSIGNED_INT) => Some(GL::UNSIGNED_INT),
+            _ => None
+        }
+    }
+
+    /// The data `pixel_type` accepts, for messages.
+    fn data_names(self) -> &'static str {
+        match self {
+            TextureFormat::R8 | TextureFormat::RG8 | TextureFormat::RGBA8 | TextureFormat::SRGBA8 => "u8 data",
+            TextureFormat::R16F | TextureFormat::RGBA16F => "u16 or f32 data",
+            TextureFormat::R32F => "f32 data",
+            TextureFormat::R32UI => "u32 data"
+        }
+    }
+
+    /// Whether `LINEAR` filtering applies. Integer textures are never
+    /// filtered, and 32-bit float ones need an extension WebGL2 does not
+    /// guarantee.
+    fn is_filterable(self) -> bool {
+        !matches!(self, TextureFormat::R32F | TextureFormat::R32UI)
+    }
+
+    pub fn is_integer(self) -> bool {
+        self == TextureFormat::R32UI
+    }
+}
+
+impl FromStr for TextureFormat {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        TextureFormat::ALL.iter()
+            .copied()
+            .find(|format| format.name() == name)
+            .ok_or_else(|| Error::UnknownTextureFormat(name.to_string()))
+    }
+}
+
+
+/// The texels of a data texture, kept in the type they were uploaded as.
+/// No format takes `i16` data.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Texels {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+    F32(Vec<f32>)
+}
+
+impl Texels {
+    fn of(data: ArrayData) -> Option<Texels> {
+        match data {
+            ArrayData::U8(data) => Some(Texels::U8(data.to_vec())),
+            ArrayData::I16(_) => None,
+            ArrayData::U16(data) => Some(Texels::U16(data.to_vec())),
+            ArrayData::U32(data) => Some(Texels::U32(data.to_vec())),
+            ArrayData::F32(data) => Some(Texels::F32(data.to_vec()))
+        }
+    }
+
+    fn data(&self) -> ArrayData<'_> {
+        match self {
+            Texels::U8(texels) => ArrayData::U8(texels),
+            Texels::U16(texels) => ArrayData::U16(texels),
+            Texels::U32(texels) => ArrayData::U32(texels),
+            Texels::F32(texels) => ArrayData::F32(texels)
+        }
+    }
+
+    /// The `len` values from `start` on, e.g. one face of a cube map.
+    fn range(&self, start: usize, len: usize) -> ArrayData<'_> {
+        match self {
+            Texels::U8(texels) => ArrayData::U8(&texels[start..start + len]),
+            Texels::U16(texels) => ArrayData::U16(&texels[start..start + len]),
+            Texels::U32(texels) => ArrayData::U32(&texels[start..start + len]),
+            Texels::F32(texels) => ArrayData::F32(&texels[start..start + len])
+        }
+    }
+
+    /// Copies the rows of `data`, `row` values each, into rows `stride`
+    /// values apart from `start` on. Returns `false` when `data` is of
+    /// another type.
+    fn write(&mut self, start: usize, stride: usize, row: usize, data: ArrayData) -> bool {
+        fn copy<T: Copy>(texels: &mut [T], start: usize, stride: usize, row: usize, data: &[T]) {
+            for (i, values) in data.chunks(row.max(1)).enumerate() {
+                let offset = start + i * stride;
+                texels[offset..offset + values.len()].copy_from_slice(values);
+            }
+        }
+
+        match (self, data) {
+            (Texels::U8(texels), ArrayData::U8(data)) => copy(texels, start, stride, row, data),
+            (Texels::U16(texels), ArrayData::U16(data)) => copy(texels, start, stride, row, data),
+            (Texels::U32(texels), ArrayData::U32(data)) => copy(texels, start, stride, row, data),
+            (Texels::F32(texels), ArrayData::F32(data)) => copy(texels, start, stride, row, data),
+            _ => return false
+        }
+        true
+    }
+}
+
+
+/// How a texture is sampled and how its image is uploaded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureOptions {
+    pub wrap_s: TextureWrap,
+    pub wrap_t: TextureWrap,
+    pub min_filter: MinFilter,
+    pub mag_filter: MagFilter,
+    /// Samples taken along the direction the texture is squeezed in, which
+    /// keeps it sharp at grazing angles. 1 turns anisotropic filtering off;
+    /// higher values are capped by what the GPU supports.
+    pub anisotropy: f32,
+    pub flip_y: bool,
+    pub premultiply_alpha: bool,
+    /// Stores the image as `SRGB8_ALPHA8`, so that shaders read linear
+    /// values. Meant for color maps, not for data such as bump maps.
+    pub srgb: bool,
+    /// RGBA color shown until the image has loaded, and in its place when
+    /// it fails to load.
+    pub placeholder: [u8; 4]
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        TextureOptions {
+            wrap_s: TextureWrap::ClampToEdge,
+            wrap_t: TextureWrap::ClampToEdge,
+            min_filter: MinFilter::LinearMipmapLinear,
+            mag_filter: MagFilter::Linear,
+            anisotropy: 1.0,
+            flip_y: false,
+            premultiply_alpha: false,
+            srgb: false,
+            placeholder: [128, 128, 128, 255]
+        }
+    }
+}
+
+impl TextureOptions {
+    fn internal_format(&self) -> u32 {
+        if self.srgb {
+            GL::SRGB8_ALPHA8
+        } else {
+            GL::RGBA8
+        }
+    }
+
+    /// Sets the wrap, filter and anisotropy parameters through `set_i` and
+    /// `set_f`, for a texture or a sampler object.
+    pub(super) fn apply_sampling(&self, gl: &dyn GlBackend, set_i: impl Fn(u32, i32), set_f: impl Fn(u32, f32)) {
+        set_i(GL::TEXTURE_MIN_FILTER, self.min_filter.to_gl() as i32);
+        set_i(GL::TEXTURE_MAG_FILTER, self.mag_filter.to_gl() as i32);
+        set_i(GL::TEXTURE_WRAP_S, self.wrap_s.to_gl() as i32);
+        set_i(GL::TEXTURE_WRAP_T, self.wrap_t.to_gl() as i32);
+        if self.anisotropy > 1.0 {
+            let max = gl.max_anisotropy();
+            if max > 1.0 {
+                set_f(TEXTURE_MAX_ANISOTROPY_EXT, self.anisotropy.min(max));
+            }
+        }
+    }
+
+    /// The options a data texture of `format` is sampled with: there are no
+    /// mipmaps, and formats that cannot be filtered are read with
+    /// `NEAREST`.
+    fn for_data(&self, format: TextureFormat) -> TextureOptions {
+        if !format.is_filterable() {
+            return TextureOptions {
+                min_filter: MinFilter::Nearest,
+                mag_filter: MagFilter::Nearest,
+                anisotropy: 1.0,
+                ..*self
+            };
+        }
+        let min_filter = match self.min_filter {
+            MinFilter::Nearest | MinFilter::NearestMipmapNearest | MinFilter::NearestMipmapLinear => MinFilter::Nearest,
+            _ => MinFilter::Linear
+        };
+        TextureOptions { min_filter, ..*self }
+    }
+
+    fn upload(&self) -> ImageUpload {
+        ImageUpload {
+            internal_format: self.internal_format(),
+            flip_y: self.flip_y,
+            premultiply_alpha: self.premultiply_alpha,
+            generate_mipmaps: self.min_filter.uses_mipmaps()
+        }
+    }
+}
+
+
+/// A texture loaded from an image URL, which is kept to load it again when
+/// the context is restored, or filled from memory.
+///
+/// Until an image has loaded, and for good when it fails to, the texture
+/// is a single pixel of the placeholder color.
 #[derive(Clone)]
 pub struct Texture {
-    texture: WebGlTexture
+    texture: Handle<TextureId>,
+    /// `GL::TEXTURE_2D`, `GL::TEXTURE_CUBE_MAP`, ...
+    target: u32,
+    source: Source,
+    options: TextureOptions
+}
+
+#[derive(Clone)]
+enum Source {
+    Image { src: String, load: ImageLoad },
+    /// Filled by `Texture::from_data` and `Texture::update`. A copy of the
+    /// texels is kept to upload them again when the context is restored.
+    /// `size` is width, height and depth, see `Texture::from_data_with_target`.
+    Data { format: TextureFormat, size: [u32; 3], pixel_type: u32, texels: Texels }
 }
 
 impl Texture {
-    pub fn new(gl: Rc<GL>, src: &str) -> Texture {
-        let texture = gl.create_texture();
+    pub fn new(gl: &dyn GlBackend, src: &str, options: &TextureOptions) -> Result<Texture> {
+        // Images are always 2D.
+        let target = GL::TEXTURE_2D;
+        let (texture, load) = load(gl, target, src, options)?;
+        Ok(Texture {
+            texture,
+            target,
+            source: Source::Image { src: src.to_string(), load },
+            options: *options
+        })
+    }
+
+    /// Creates a `width` x `height` texture of `format` holding `data`, row
+    /// by row from `t = 0`. Only the wrap, filter and anisotropy options
+    /// apply, see `TextureOptions::for_data`.
+    pub fn from_data<T: CreateArray>(
+        gl: &dyn GlBackend,
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        data: &[T],
+        options: &TextureOptions
+    ) -> Result<Texture> {
+        Texture::from_data_with_target(gl, GL::TEXTURE_2D, format, [width, height, 1], data, options)
+    }
 
-        gl.bind_texture(GL::TEXTURE_2D, texture.as_ref());
+    /// Creates a texture of `target` and `format` holding `data`, with
+    /// `size` giving its width, height and depth:
+    ///
+    /// - `GL::TEXTURE_2D` has a depth of 1, as in `Texture::from_data`.
+    /// - `GL::TEXTURE_CUBE_MAP` has six square faces, +X, -X, +Y, -Y, +Z
+    ///   and -Z in that order, and so a depth of 6.
+    /// - `GL::TEXTURE_2D_ARRAY` and `GL::TEXTURE_3D` have `depth` layers or
+    ///   slices, one after the other.
+    pub fn from_data_with_target<T: CreateArray>(
+        gl: &dyn GlBackend,
+        target: u32,
+        format: TextureFormat,
+        size: [u32; 3],
+        data: &[T],
+        options: &TextureOptions
+    ) -> Result<Texture> {
+        check_size(target, size)?;
+        let [width, height, depth] = size;
+        let pixel_type = check_data::<T>(format, width, height * depth, data.len())?;
+        let options = options.for_data(format);
+        let texels = Texels::of(T::array_data(data)).expect("check_data turns away i16 data");
+        let texture = allocate(gl, target, format, size, &options, pixel_type, &texels)?;
+        Ok(Texture {
+            texture,
+            target,
+            source: Source::Data { format, size, pixel_type, texels },
+            options
+        })
+    }
 
-        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
-        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
-        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
-        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+    /// Uploads an image decoded in Rust as RGBA8, or sRGB if `options` ask
+    /// for it. Rows are flipped and alpha premultiplied on the CPU as the
+    /// options say, so the texture matches one loaded by `Texture::new`.
+    pub fn from_image(gl: &dyn GlBackend, image: &Image, options: &TextureOptions) -> Result<Texture> {
+        let format = if options.srgb { TextureFormat::SRGBA8 } else { TextureFormat::RGBA8 };
+        let mut image = image.clone();
+        if options.flip_y {
+            image.flip_rows();
+        }
+        if options.premultiply_alpha {
+            image.premultiply_alpha();
+        }
+        Texture::from_data(gl, format, image.width(), image.height(), image.pixels(), options)
+    }
 
-        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_u8_array_and_src_offset(
-            GL::TEXTURE_2D,
-            0,
-            GL::RGBA as i32,
-            1,
-            1,
+    pub fn get_texture(&self) -> TextureId {
+        self.texture.id()
+    }
+
+    pub fn target(&self) -> u32 {
+        self.target
+    }
+
+    /// Whether the texture holds integers, to be read with an integer
+    /// sampler.
+    pub fn is_integer(&self) -> bool {
+        match self.source {
+            Source::Data { format, .. } => format.is_integer(),
+            Source::Image { .. } => false
+        }
+    }
+
+    /// Data textures are always loaded.
+    #[cfg(test)]
+    pub fn state(&self) -> TextureState {
+        match &self.source {
+            Source::Image { load, .. } => load.state(),
+            Source::Data { .. } => TextureState::Loaded
+        }
+    }
+
+    /// Replaces the texels of the `width` x `height` rectangle at `x`, `y`
+    /// of a 2D data texture with `data`, e.g. to refresh an overlay every
+    /// frame. `data` has to be of the type the texture was created with.
+    pub fn update<T: CreateArray>(&mut self, gl: &dyn GlBackend, x: u32, y: u32, width: u32, height: u32, data: &[T]) -> Result<()> {
+        let (format, texture_width, texture_height, texels) = match &mut self.source {
+            Source::Data { format, size: [width, height, _], texels, .. } => (*format, *width, *height, texels),
+            Source::Image { src, .. } => {
+                return Err(Error::TextureUpdate(format!("'{}' is loaded from an image", src)));
+            }
+        };
+        if self.target != GL::TEXTURE_2D {
+            return Err(Error::TextureUpdate(format!("{} textures are only filled whole", target_name(self.target))));
+        }
+        if width > texture_width || x > texture_width - width || height > texture_height || y > texture_height - height {
+            return Err(Error::TextureUpdate(format!(
+                "{}x{} at ({}, {}) is outside of the {}x{} texture",
+                width, height, x, y, texture_width, texture_height
+            )));
+        }
+        let pixel_type = check_data::<T>(format, width, height, data.len())?;
+
+        let components = format.components();
+        let start = (y as usize * texture_width as usize + x as usize) * components;
+        let stride = texture_width as usize * components;
+        if !texels.write(start, stride, width as usize * components, T::array_data(data)) {
+            return Err(Error::TextureUpdate(format!(
+                "{} data does not match the data the texture was created with", data_type_name(T::data_type())
+            )));
+        }
+
+        gl.bind_texture(self.target, Some(self.texture.id()));
+        // Rows of data are tightly packed.
+        gl.pixel_storei(GL::UNPACK_ALIGNMENT, 1);
+        gl.tex_sub_image_2d(
+            self.target,
             0,
-            GL::RGBA,
-            GL::UNSIGNED_BYTE,
-            &[0u8, 255u8, 0u8, 255u8],
-            0
+            x as i32,
+            y as i32,
+            width as i32,
+            height as i32,
+            format.format(),
+            pixel_type,
+            T::array_data(data)
         );
+        gl.pixel_storei(GL::UNPACK_ALIGNMENT, 4);
+        gl.bind_texture(self.target, None);
+        Ok(())
+    }
+
+    /// Creates the texture again after the context holding the previous
+    /// one was lost. Images are loaded again; data textures get their
+    /// last texels back.
+    pub fn restore(&mut self, gl: &dyn GlBackend) -> Result<()> {
+        match &mut self.source {
+            Source::Image { src, load: image_load } => {
+                let (texture, new_load) = load(gl, self.target, src, &self.options)?;
+                self.texture = texture;
+                *image_load = new_load;
+            },
+            Source::Data { format, size, pixel_type, texels } => {
+                self.texture = allocate(gl, self.target, *format, *size, &self.options, *pixel_type, texels)?;
+            }
+        }
+        Ok(())
+    }
+}
 
-        gl.bind_texture(GL::TEXTURE_2D, None);
 
-        let image = Rc::new(HtmlImageElement::new().unwrap());
+/// Creates a `target` texture showing the placeholder and starts loading
+/// the image at `src` into it.
+fn load(gl: &dyn GlBackend, target: u32, src: &str, options: &TextureOptions) -> Result<(Handle<TextureId>, ImageLoad)> {
+    let texture = gl.create_texture()
+        .ok_or(Error::ObjectCreation("texture"))?;
 
-        let gl_cloned = gl.clone();
-        let image_clone = image.clone();
-        let texture_clone = texture.clone();
+    gl.bind_texture(target, Some(texture));
 
-        let onload = Closure::wrap(Box::new(move || {
-            gl_cloned.bind_texture(GL::TEXTURE_2D, texture_clone.as_ref());
-            gl_cloned.tex_image_2d_with_u32_and_u32_and_html_image_element(
-                GL::TEXTURE_2D,
-                0,
-                GL::RGBA as i32,
-                GL::RGBA,
-                GL::UNSIGNED_BYTE,
-                &image_clone
-            );
-            gl_cloned.generate_mipmap(GL::TEXTURE_2D);
-            gl.bind_texture(GL::TEXTURE_2D, None);
-        }) as Box<dyn Fn()>);
+    options.apply_sampling(
+        gl,
+        |pname, param| gl.tex_parameteri(target, pname, param),
+        |pname, param| gl.tex_parameterf(target, pname, param)
+    );
 
-        image.set_onload(Some(onload.as_ref().unchecked_ref()));
-        image.set_src(src);
+    // A single pixel is a complete mipmap chain, so the placeholder shows
+    // with any filter.
+    gl.tex_image_2d(
+        target,
+        0,
+        options.internal_format() as i32,
+        1,
+        1,
+        0,
+        GL::RGBA,
+        GL::UNSIGNED_BYTE,
+        Some(ArrayData::U8(&options.placeholder))
+    );
 
-        onload.forget();
+    gl.bind_texture(target, None);
+    let texture = gl.resources().track(texture);
+    gl.resources().set_size(Resource::Texture(texture.id()), 4);
 
-        Texture { texture: texture.unwrap() }
+    let load = gl.load_image(texture.id(), src, options.upload())?;
+
+    Ok((texture, load))
+}
+
+/// Creates a data texture of `target` filled with `texels`, uploaded as
+/// `pixel_type`. Cube maps are uploaded face by face.
+fn allocate(
+    gl: &dyn GlBackend,
+    target: u32,
+    format: TextureFormat,
+    [width, height, depth]: [u32; 3],
+    options: &TextureOptions,
+    pixel_type: u32,
+    texels: &Texels
+) -> Result<Handle<TextureId>> {
+    let texture = gl.create_texture()
+        .ok_or(Error::ObjectCreation("texture"))?;
+
+    gl.bind_texture(target, Some(texture));
+    options.apply_sampling(
+        gl,
+        |pname, param| gl.tex_parameteri(target, pname, param),
+        |pname, param| gl.tex_parameterf(target, pname, param)
+    );
+
+    gl.pixel_storei(GL::UNPACK_ALIGNMENT, 1);
+    let upload_2d = |target: u32, pixels: ArrayData| gl.tex_image_2d(
+        target,
+        0,
+        format.internal_format() as i32,
+        width as i32,
+        height as i32,
+        0,
+        format.format(),
+        pixel_type,
+        Some(pixels)
+    );
+    match target {
+        GL::TEXTURE_CUBE_MAP => {
+            let face = width as usize * height as usize * format.components();
+            for i in 0..6 {
+                upload_2d(GL::TEXTURE_CUBE_MAP_POSITIVE_X + i, texels.range(i as usize * face, face));
+            }
+        },
+        GL::TEXTURE_2D_ARRAY | GL::TEXTURE_3D => gl.tex_image_3d(
+            target,
+            0,
+            format.internal_format() as i32,
+            width as i32,
+            height as i32,
+            depth as i32,
+            0,
+            format.format(),
+            pixel_type,
+            Some(texels.data())
+        ),
+        _ => upload_2d(target, texels.data())
     }
+    gl.pixel_storei(GL::UNPACK_ALIGNMENT, 4);
+    gl.bind_texture(target, None);
+
+    let texture = gl.resources().track(texture);
+    let bytes = width as usize * height as usize * depth as usize * format.texel_size();
+    gl.resources().set_size(Resource::Texture(texture.id()), bytes);
+    Ok(texture)
+}
 
-    pub fn get_texture(&self) -> &WebGlTexture {
-        &self.texture
+/// Checks that a data texture of `target` can be `size` large.
+fn check_size(target: u32, [width, height, depth]: [u32; 3]) -> Result<()> {
+    let fits = match target {
+        GL::TEXTURE_2D => depth == 1,
+        GL::TEXTURE_CUBE_MAP => depth == 6 && width == height,
+        GL::TEXTURE_2D_ARRAY | GL::TEXTURE_3D => depth > 0,
+        _ => return Err(Error::InvalidTexture(format!("0x{:x} is not a texture target", target)))
+    };
+    if !fits {
+        return Err(Error::InvalidTexture(format!(
+            "a {} texture cannot be {}x{}x{}", target_name(target), width, height, depth
+        )));
     }
+    Ok(())
 }
 
+/// Checks that `len` values of type `T` fill `width` x `height` texels of
+/// `format`, and returns the GL type to upload them as.
+fn check_data<T: CreateArray>(format: TextureFormat, width: u32, height: u32, len: usize) -> Result<u32> {
+    let name = format!("{:?}", format);
+    let pixel_type = format.pixel_type(T::data_type()).ok_or_else(|| Error::TypeMismatch {
+        name: name.clone(),
+        expected: format.data_names().to_string(),
+        found: format!("{} data", data_type_name(T::data_type()))
+    })?;
+
+    let expected = width as usize * height as usize * format.components();
+    if len != expected {
+        return Err(Error::TypeMismatch {
+            name,
+            expected: format!("{} values", expected),
+            found: format!("{} values", len)
+        });
+    }
+    Ok(pixel_type)
+}
+
+fn data_type_name(data_type: u32) -> &'static str {
+    match data_type {
+        GL::UNSIGNED_BYTE => "u8",
+        GL::UNSIGNED_SHORT => "u16",
+        GL::UNSIGNED_INT => "u32",
+        GL::SHORT => "i16",
+        GL::FLOAT => "f32",
+        _ => "unknown"
+    }
+}
+
+
+/// Name of a texture target for messages, e.g. "2D".
+pub fn target_name(target: u32) -> &'static str {
+    match target {
+        GL::TEXTURE_2D => "2D",
+        GL::TEXTURE_3D => "3D",
+        GL::TEXTURE_CUBE_MAP => "cube map",
+        GL::TEXTURE_2D_ARRAY => "2D array",
+        _ => "unknown"
+    }
+}
+
+
+/// How far the images of a set of textures have loaded.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextureReport {
+    pub pending: usize,
+    pub loaded: usize,
+    pub failed: Vec<FailedTexture>
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FailedTexture {
+    pub src: String,
+    pub reason: String
+}
+
+impl TextureReport {
+    pub fn of<'a>(textures: impl IntoIterator<Item = &'a Texture>) -> Self {
+        let mut report = TextureReport::default();
+        for texture in textures {
+            // Data textures have nothing to load.
+            if let Source::Image { src, load } = &texture.source {
+                match load.state() {
+                    TextureState::Pending => report.pending += 1,
+                    TextureState::Loaded => report.loaded += 1,
+                    TextureState::Failed(reason) => report.failed.push(FailedTexture { src: src.clone(), reason })
+                }
+            }
+        }
+        report
+    }
+
+    /// Whether every load has either succeeded or failed.
+    pub fn is_settled(&self) -> bool {
+        self.pending == 0
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{GlCall, MockBackend};
+
+    #[test]
+    fn the_report_follows_the_loads() {
+        let gl = MockBackend::new();
+        let color = Texture::new(&gl, "/data/world.jpg", &TextureOptions::default()).unwrap();
+        let clouds = Texture::new(&gl, "/data/clouds.jpg", &TextureOptions::default()).unwrap();
+        assert_eq!(color.state(), TextureState::Pending);
+
+        let report = TextureReport::of([&color, &clouds]);
+        assert_eq!((report.pending, report.is_settled()), (2, false));
+
+        gl.finish_image(color.get_texture(), TextureState::Loaded);
+        gl.finish_image(clouds.get_texture(), TextureState::Failed("404".to_string()));
+        let report = TextureReport::of([&color, &clouds]);
+        assert!(report.is_settled());
+        assert_eq!(report, TextureReport {
+            pending: 0,
+            loaded: 1,
+            failed: vec![FailedTexture { src: "/data/clouds.jpg".to_string(), reason: "404".to_string() }]
+        });
+    }
+
+    #[test]
+    fn options_set_the_sampling_and_the_upload() {
+        let gl = MockBackend::new();
+        let options = TextureOptions {
+            wrap_s: TextureWrap::Repeat,
+            min_filter: MinFilter::LinearMipmapLinear,
+            anisotropy: 8.0,
+            flip_y: true,
+            srgb: true,
+            ..TextureOptions::default()
+        };
+        let texture = Texture::new(&gl, "/data/world.jpg", &options).unwrap();
+
+        let calls = gl.calls();
+        assert!(calls.contains(&GlCall::TexParameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::REPEAT as i32)));
+        assert!(calls.contains(&GlCall::TexParameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32)));
+        assert!(calls.contains(&GlCall::TexParameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR_MIPMAP_LINEAR as i32)));
+        assert!(calls.contains(&GlCall::TexParameterf(GL::TEXTURE_2D, TEXTURE_MAX_ANISOTROPY_EXT, 8.0)));
+        assert!(calls.iter().any(|c| matches!(c, GlCall::TexImage2D { internal_format, .. } if *internal_format == GL::SRGB8_ALPHA8 as i32)));
+        assert_eq!(calls.last(), Some(&GlCall::LoadImage(
+            texture.get_texture(),
+            "/data/world.jpg".to_string(),
+            ImageUpload { internal_format: GL::SRGB8_ALPHA8, flip_y: true, premultiply_alpha: false, generate_mipmaps: true }
+        )));
+    }
+
+    #[test]
+    fn anisotropy_is_capped_by_the_gpu() {
+        let gl = MockBackend::new();
+        let options = TextureOptions { anisotropy: 8.0, min_filter: MinFilter::Linear, ..TextureOptions::default() };
 
+        gl.set_max_anisotropy(4.0);
+        Texture::new(&gl, "/data/world.jpg", &options).unwrap();
+        assert!(gl.calls().contains(&GlCall::TexParameterf(GL::TEXTURE_2D, TEXTURE_MAX_ANISOTROPY_EXT, 4.0)));
+        assert!(matches!(gl.calls().last(), Some(GlCall::LoadImage(_, _, ImageUpload { generate_mipmaps: false, .. }))));
 
+        // Without the extension the parameter is left alone.
+        gl.set_max_anisotropy(1.0);
+        gl.clear_calls();
+        Texture::new(&gl, "/data/world.jpg", &options).unwrap();
+        assert!(!gl.calls().iter().any(|c| matches!(c, GlCall::TexParameterf(..))));
+    }
+
+    #[test]
+    fn restoring_loads_the_image_again() {
+        let gl = MockBackend::new();
+        let mut texture = Texture::new(&gl, "/data/world.jpg", &TextureOptions::default()).unwrap();
+        gl.finish_image(texture.get_texture(), TextureState::Loaded);
+
+        gl.reset();
+        texture.restore(&gl).unwrap();
+        assert_eq!(texture.state(), TextureState::Pending);
+    }
+
+    #[test]
+    fn data_is_uploaded_in_its_format() {
+        let gl = MockBackend::new();
+        let heights = [0u16; 6];
+        let texture = Texture::from_data(&gl, TextureFormat::R16F, 3, 2, &heights, &TextureOptions::default()).unwrap();
+        assert_eq!(texture.state(), TextureState::Loaded);
+
+        let calls = gl.calls();
+        assert!(calls.contains(&GlCall::TexImage2D {
+            target: GL::TEXTURE_2D,
+            level: 0,
+            internal_format: GL::R16F as i32,
+            width: 3,
+            height: 2,
+            format: GL::RED,
+            data_type: GL::HALF_FLOAT,
+            pixels: Some(vec![0; 12])
+        }));
+        assert!(calls.contains(&GlCall::PixelStorei(GL::UNPACK_ALIGNMENT, 1)));
+        // Data textures have no mipmaps to sample.
+        assert!(calls.contains(&GlCall::TexParameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32)));
+        assert_eq!(gl.resources().stats().bytes, 12);
+
+        gl.clear_calls();
+        Texture::from_data(&gl, TextureFormat::R32UI, 1, 1, &[7u32], &TextureOptions::default()).unwrap();
+        assert!(gl.calls().contains(&GlCall::TexParameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::NEAREST as i32)));
+    }
+
+    #[test]
+    fn data_has_to_fit_the_format() {
+        let gl = MockBackend::new();
+        let options = TextureOptions::default();
+
+        assert_eq!(
+            Texture::from_data(&gl, TextureFormat::RGBA8, 1, 1, &[0.0f32; 4], &options).err(),
+            Some(Error::TypeMismatch {
+                name: "RGBA8".to_string(),
+                expected: "u8 data".to_string(),
+                found: "f32 data".to_string()
+            })
+        );
+        // No format takes signed shorts.
+        assert_eq!(
+            Texture::from_data(&gl, TextureFormat::R16F, 1, 1, &[0i16], &options).err(),
+            Some(Error::TypeMismatch {
+                name: "R16F".to_string(),
+                expected: "u16 or f32 data".to_string(),
+                found: "i16 data".to_string()
+            })
+        );
+        assert_eq!(
+            Texture::from_data(&gl, TextureFormat::RG8, 2, 2, &[0u8; 4], &options).err(),
+            Some(Error::TypeMismatch {
+                name: "RG8".to_string(),
+                expected: "8 values".to_string(),
+                found: "4 values".to_string()
+            })
+        );
+        assert!(!gl.calls().iter().any(|c| matches!(c, GlCall::CreateTexture(_))));
+    }
+
+    #[test]
+    fn regions_are_updated_within_the_texture() {
+        let gl = MockBackend::new();
+        let mut texture = Texture::from_data(&gl, TextureFormat::R32F, 4, 4, &[0.0f32; 16], &TextureOptions::default()).unwrap();
+        gl.clear_calls();
+
+        texture.update(&gl, 2, 1, 2, 3, &[1.0f32; 6]).unwrap();
+        assert!(gl.calls().contains(&GlCall::TexSubImage2D {
+            target: GL::TEXTURE_2D,
+            x: 2,
+            y: 1,
+            width: 2,
+            height: 3,
+            format: GL::RED,
+            data_type: GL::FLOAT,
+            len: 6
+        }));
+
+        assert_eq!(
+            texture.update(&gl, 3, 0, 2, 1, &[1.0f32; 2]),
+            Err(Error::TextureUpdate("2x1 at (3, 0) is outside of the 4x4 texture".to_string()))
+        );
+        let mut image = Texture::new(&gl, "/data/world.jpg", &TextureOptions::default()).unwrap();
+        assert_eq!(
+            image.update(&gl, 0, 0, 1, 1, &[0u8; 4]),
+            Err(Error::TextureUpdate("'/data/world.jpg' is loaded from an image".to_string()))
+        );
+    }
+
+    #[test]
+    fn restored_data_textures_get_their_texels_back() {
+        let gl = MockBackend::new();
+        let texels: Vec<u8> = (0..16).collect();
+        let mut texture = Texture::from_data(&gl, TextureFormat::RGBA8, 2, 2, &texels, &TextureOptions::default()).unwrap();
+        // The right column.
+        texture.update(&gl, 1, 0, 1, 2, &[100u8, 101, 102, 103, 104, 105, 106, 107]).unwrap();
+
+        gl.reset();
+        gl.clear_calls();
+        texture.restore(&gl).unwrap();
+        assert!(gl.calls().contains(&GlCall::TexImage2D {
+            target: GL::TEXTURE_2D,
+            level: 0,
+            internal_format: GL::RGBA8 as i32,
+            width: 2,
+            height: 2,
+            format: GL::RGBA,
+            data_type: GL::UNSIGNED_BYTE,
+            pixels: Some(vec![0, 1, 2, 3, 100, 101, 102, 103, 8, 9, 10, 11, 104, 105, 106, 107])
+        }));
+        assert_eq!(gl.resources().stats().textures, 1);
+    }
 
+    #[test]
+    fn updates_keep_the_type_of_the_texels() {
+        let gl = MockBackend::new();
+        let mut texture = Texture::from_data(&gl, TextureFormat::RGBA16F, 1, 1, &[0.0f32; 4], &TextureOptions::default()).unwrap();
+
+        // Half floats fit the format, but not the copy kept for restoring.
+        assert_eq!(
+            texture.update(&gl, 0, 0, 1, 1, &[0u16; 4]),
+            Err(Error::TextureUpdate("u16 data does not match the data the texture was created with".to_string()))
+        );
+        assert!(texture.update(&gl, 0, 0, 1, 1, &[1.0f32; 4]).is_ok());
+    }
+
+    #[test]
+    fn cube_maps_are_uploaded_face_by_face() {
+        let gl = MockBackend::new();
+        let texels: Vec<u8> = (0..6).collect();
+        let mut sky = Texture::from_data_with_target(
+            &gl, GL::TEXTURE_CUBE_MAP, TextureFormat::R8, [1, 1, 6], &texels, &TextureOptions::default()
+        ).unwrap();
+        assert_eq!(sky.target(), GL::TEXTURE_CUBE_MAP);
+
+        let faces = |gl: &MockBackend| -> Vec<(u32, Option<Vec<u8>>)> {
+            gl.calls().into_iter()
+                .filter_map(|c| match c {
+                    GlCall::TexImage2D { target, pixels, .. } => Some((target, pixels)),
+                    _ => None
+                })
+                .collect()
+        };
+        let expected: Vec<_> = (0..6).map(|i| (GL::TEXTURE_CUBE_MAP_POSITIVE_X + i, Some(vec![i as u8]))).collect();
+        assert_eq!(faces(&gl), expected);
+        assert!(gl.calls().contains(&GlCall::TexParameteri(GL::TEXTURE_CUBE_MAP, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32)));
+        assert_eq!(gl.resources().stats().bytes, 6);
+
+        gl.reset();
+        gl.clear_calls();
+        sky.restore(&gl).unwrap();
+        assert_eq!(faces(&gl), expected);
+        assert_eq!(
+            sky.update(&gl, 0, 0, 1, 1, &[0u8]),
+            Err(Error::TextureUpdate("cube map textures are only filled whole".to_string()))
+        );
+    }
+
+    #[test]
+    fn arrays_and_3d_textures_are_uploaded_in_one_go() {
+        let gl = MockBackend::new();
+        let layers = Texture::from_data_with_target(
+            &gl, GL::TEXTURE_2D_ARRAY, TextureFormat::RG8, [2, 1, 3], &[1u8; 12], &TextureOptions::default()
+        ).unwrap();
+        assert!(gl.calls().contains(&GlCall::BindTexture(GL::TEXTURE_2D_ARRAY, Some(layers.get_texture()))));
+        assert!(gl.calls().contains(&GlCall::TexImage3D {
+            target: GL::TEXTURE_2D_ARRAY,
+            level: 0,
+            internal_format: GL::RG8 as i32,
+            width: 2,
+            height: 1,
+            depth: 3,
+            format: GL::RG,
+            data_type: GL::UNSIGNED_BYTE,
+            pixels: Some(vec![1; 12])
+        }));
+
+        gl.clear_calls();
+        Texture::from_data_with_target(
+            &gl, GL::TEXTURE_3D, TextureFormat::R32F, [2, 2, 2], &[0.0f32; 8], &TextureOptions::default()
+        ).unwrap();
+        assert!(gl.calls().iter().any(|c| matches!(c, GlCall::TexImage3D { target: GL::TEXTURE_3D, depth: 2, .. })));
+        assert!(!gl.calls().iter().any(|c| matches!(c, GlCall::TexImage2D { .. })));
+    }
+
+    #[test]
+    fn data_has_to_fit_the_target() {
+        let gl = MockBackend::new();
+        let options = TextureOptions::default();
+
+        assert_eq!(
+            Texture::from_data_with_target(&gl, GL::TEXTURE_CUBE_MAP, TextureFormat::R8, [2, 1, 6], &[0u8; 12], &options).err(),
+            Some(Error::InvalidTexture("a cube map texture cannot be 2x1x6".to_string()))
+        );
+        assert_eq!(
+            Texture::from_data_with_target(&gl, GL::TEXTURE_2D, TextureFormat::R8, [1, 1, 2], &[0u8; 2], &options).err(),
+            Some(Error::InvalidTexture("a 2D texture cannot be 1x1x2".to_string()))
+        );
+        assert_eq!(
+            Texture::from_data_with_target(&gl, GL::TEXTURE_CUBE_MAP_POSITIVE_X, TextureFormat::R8, [1, 1, 1], &[0u8], &options).err(),
+            Some(Error::InvalidTexture("0x8515 is not a texture target".to_string()))
+        );
+        // Every layer needs its texels.
+        assert_eq!(
+            Texture::from_data_with_target(&gl, GL::TEXTURE_2D_ARRAY, TextureFormat::R8, [2, 2, 2], &[0u8; 4], &options).err(),
+            Some(Error::TypeMismatch {
+                name: "R8".to_string(),
+                expected: "8 values".to_string(),
+                found: "4 values".to_string()
+            })
+        );
+        assert!(!gl.calls().iter().any(|c| matches!(c, GlCall::CreateTexture(_))));
+    }
+
+    #[test]
+    fn decoded_images_upload_as_data() {
+        let gl = MockBackend::new();
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, 2, 1);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.write_header().unwrap().write_image_data(&[0; 6]).unwrap();
+        let image = Image::decode(&png).unwrap();
+
+        let options = TextureOptions { srgb: true, ..TextureOptions::default() };
+        let texture = Texture::from_image(&gl, &image, &options).unwrap();
+        assert_eq!(texture.state(), TextureState::Loaded);
+        assert!(gl.calls().contains(&GlCall::TexImage2D {
+            target: GL::TEXTURE_2D,
+            level: 0,
+            internal_format: GL::SRGB8_ALPHA8 as i32,
+            width: 2,
+            height: 1,
+            format: GL::RGBA,
+            data_type: GL::UNSIGNED_BYTE,
+            pixels: Some(vec![0, 0, 0, 255, 0, 0, 0, 255])
+        }));
+        assert!(!gl.calls().iter().any(|c| matches!(c, GlCall::LoadImage(..))));
+    }
+}
@@ -0,0 +1,310 @@
+use std::mem::size_of;
+use web_sys::WebGl2RenderingContext as GL;
+
+use crate::error::{Error, Result};
+use super::CreateArray;
+
+
+/// Where one attribute is found within a vertex.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VertexAttribute {
+    pub name: String,
+    pub components: i32,
+    /// `GL::FLOAT`, `GL::UNSIGNED_BYTE`, ...
+    pub data_type: u32,
+    /// Whether integer values are mapped to `[0, 1]` instead of being
+    /// converted as they are.
+    pub normalized: bool,
+    /// In bytes, from the start of the vertex.
+    pub offset: i32
+}
+
+impl VertexAttribute {
+    pub fn size(&self) -> i32 {
+        self.components * type_size(self.data_type)
+    }
+}
+
+
+/// How attributes are laid out in a vertex buffer. A layout with a single
+/// attribute describes a plain array; with more, the attributes of each
+/// vertex are interleaved.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VertexLayout {
+    attributes: Vec<VertexAttribute>
+}
+
+impl VertexLayout {
+    pub fn new() -> Self {
+        VertexLayout::default()
+    }
+
+    /// Appends an attribute of `components` values of type `T`, after the
+    /// previous ones.
+    pub fn attribute<T: CreateArray>(self, name: &str, components: i32) -> Self {
+        self.packed::<T>(name, components, false)
+    }
+
+    /// Appends an attribute of `components` integers of type `T`, read by
+    /// the shader as floats in `[0, 1]`, or `[-1, 1]` for signed types.
+    pub fn normalized<T: CreateArray>(self, name: &str, components: i32) -> Self {
+        self.packed::<T>(name, components, true)
+    }
+
+    /// Adds an attribute at an explicit offset. The stride grows to fit it.
+    pub fn with_attribute(mut self, attribute: VertexAttribute) -> Self {
+        self.attributes.push(attribute);
+        self
+    }
+
+    fn packed<T: CreateArray>(self, name: &str, components: i32, normalized: bool) -> Self {
+        // WebGL wants offsets to be a multiple of the component size.
+        let offset = align(self.end(), size_of::<T>() as i32);
+        self.with_attribute(VertexAttribute {
+            name: name.to_string(),
+            components,
+            data_type: T::data_type(),
+            normalized,
+            offset
+        })
+    }
+
+    fn end(&self) -> i32 {
+        self.attributes.iter()
+            .map(|a| a.offset + a.size())
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn attributes(&self) -> &[VertexAttribute] {
+        &self.attributes
+    }
+
+    /// Bytes from one vertex to the next. Interleaved vertices are padded
+    /// to a multiple of 4 bytes.
+    pub fn stride(&self) -> i32 {
+        match self.attributes.len() {
+            1 => self.end(),
+            _ => align(self.end(), 4)
+        }
+    }
+
+    /// The stride to pass to `vertexAttribPointer`, where 0 stands for
+    /// tightly packed values.
+    pub(super) fn pointer_stride(&self) -> i32 {
+        match self.attributes.as_slice() {
+            [only] if only.offset == 0 => 0,
+            _ => self.stride()
+        }
+    }
+}
+
+
+/// Packs float streams into vertices of `layout`, one stream per attribute
+/// in order, each holding whole values for the same number of vertices.
+/// Values of normalized attributes are scaled from `[0, 1]`, or `[-1, 1]`
+/// for signed types, and clamped; other integers are rounded.
+pub fn pack(layout: &VertexLayout, streams: &[&[f32]]) -> Result<Vec<u8>> {
+    let attributes = layout.attributes();
+    if streams.len() != attributes.len() {
+        return Err(Error::TypeMismatch {
+            name: "vertex layout".to_string(),
+            expected: format!("{} streams", attributes.len()),
+            found: format!("{} streams", streams.len())
+        });
+    }
+
+    let mut vertex_count = None;
+    for (attribute, stream) in attributes.iter().zip(streams) {
+        let components = attribute.components as usize;
+        if !stream.len().is_multiple_of(components) {
+            return Err(Error::TypeMismatch {
+                name: attribute.name.clone(),
+                expected: format!("a multiple of {} values", components),
+                found: format!("{} values", stream.len())
+            });
+        }
+        match vertex_count {
+            Some((count, ref first)) if count != stream.len() / components => {
+                return Err(Error::TypeMismatch {
+                    name: attribute.name.clone(),
+                    expected: format!("{} vertices like '{}'", count, first),
+                    found: format!("{} vertices", stream.len() / components)
+                });
+            }
+            Some(_) => {},
+            None => vertex_count = Some((stream.len() / components, &attribute.name))
+        }
+    }
+    let vertex_count = vertex_count.map_or(0, |(count, _)| count);
+    let stride = layout.stride() as usize;
+
+    let mut data = vec![0; vertex_count * stride];
+    for (attribute, stream) in attributes.iter().zip(streams) {
+        let components = attribute.components as usize;
+        let size = type_size(attribute.data_type) as usize;
+        for vertex in 0..vertex_count {
+            for component in 0..components {
+                let start = vertex * stride + attribute.offset as usize + component * size;
+                write_value(attribute, stream[vertex * components + component], &mut data[start..start + size])?;
+            }
+        }
+    }
+    Ok(data)
+}
+
+fn write_value(attribute: &VertexAttribute, value: f32, out: &mut [u8]) -> Result<()> {
+    // Float to integer casts saturate, which clamps normalized values.
+    let scaled = |max: f32| if attribute.normalized { (value * max).round() } else { value.round() };
+    match attribute.data_type {
+        GL::FLOAT => out.copy_from_slice(&value.to_le_bytes()),
+        GL::BYTE => out.copy_from_slice(&(scaled(i8::MAX as f32) as i8).to_le_bytes()),
+        GL::UNSIGNED_BYTE => out.copy_from_slice(&(scaled(u8::MAX as f32) as u8).to_le_bytes()),
+        GL::SHORT => out.copy_from_slice(&(scaled(i16::MAX as f32) as i16).to_le_bytes()),
+        GL::UNSIGNED_SHORT => out.copy_from_slice(&(scaled(u16::MAX as f32) as u16).to_le_bytes()),
+        GL::INT => out.copy_from_slice(&(scaled(i32::MAX as f32) as i32).to_le_bytes()),
+        GL::UNSIGNED_INT => out.copy_from_slice(&(scaled(u32::MAX as f32) as u32).to_le_bytes()),
+        _ => {
+            return Err(Error::TypeMismatch {
+                name: attribute.name.clone(),
+                expected: "float or integer data".to_string(),
+                found: format!("data of type {:#x}", attribute.data_type)
+            });
+        }
+    }
+    Ok(())
+}
+
+
+/// Size in bytes of a value of a vertex data type.
+pub fn type_size(data_type: u32) -> i32 {
+    match data_type {
+        GL::BYTE | GL::UNSIGNED_BYTE => 1,
+        GL::SHORT | GL::UNSIGNED_SHORT | GL::HALF_FLOAT => 2,
+        _ => 4
+    }
+}
+
+fn align(value: i32, alignment: i32) -> i32 {
+    (value + alignment - 1) / alignment * alignment
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_attributes_are_aligned() {
+        let layout = VertexLayout::new()
+            .attribute::<f32>("a_position", 3)
+            .normalized::<u8>("a_color", 3)
+            .normalized::<u16>("a_uv", 2);
+
+        let offsets: Vec<_> = layout.attributes().iter().map(|a| (a.offset, a.normalized)).collect();
+        assert_eq!(offsets, vec![(0, false), (12, true), (16, true)]);
+        assert_eq!(layout.stride(), 20);
+        assert_eq!(layout.attributes()[2].data_type, GL::UNSIGNED_SHORT);
+    }
+
+    #[test]
+    fn explicit_offsets_grow_the_stride() {
+        let layout = VertexLayout::new()
+            .with_attribute(VertexAttribute {
+                name: "a_normal".to_string(),
+                components: 3,
+                data_type: GL::FLOAT,
+                normalized: false,
+                offset: 16
+            })
+            .with_attribute(VertexAttribute {
+                name: "a_position".to_string(),
+                components: 3,
+                data_type: GL::FLOAT,
+                normalized: false,
+                offset: 0
+            });
+
+        assert_eq!(layout.stride(), 28);
+        assert_eq!(layout.pointer_stride(), 28);
+        let colors = VertexLayout::new().normalized::<u8>("a_color", 3);
+        assert_eq!((colors.stride(), colors.pointer_stride()), (3, 0));
+    }
+
+    #[test]
+    fn streams_are_packed_per_vertex() {
+        let layout = VertexLayout::new()
+            .attribute::<f32>("a_position", 1)
+            .normalized::<i16>("a_normal", 3)
+            .normalized::<u8>("a_color", 1);
+        let positions = [0.5, 2.0];
+        let normals = [1.0, -1.0, 0.0, 0.0, 2.0, -0.5];
+        let colors = [1.0, 0.0];
+
+        let data = pack(&layout, &[&positions, &normals, &colors]).unwrap();
+        assert_eq!(layout.stride(), 12);
+        assert_eq!(data.len(), 24);
+        assert_eq!(&data[0..4], &0.5f32.to_le_bytes());
+        assert_eq!(&data[4..6], &i16::MAX.to_le_bytes());
+        assert_eq!(&data[6..8], &(-i16::MAX).to_le_bytes());
+        assert_eq!(data[10], 255);
+        // Out of range values are clamped.
+        assert_eq!(&data[18..20], &i16::MAX.to_le_bytes());
+        assert_eq!(&data[20..22], &(-16384i16).to_le_bytes());
+    }
+
+    #[test]
+    fn unknown_types_are_not_packed() {
+        let layout = VertexLayout::new().with_attribute(VertexAttribute {
+            name: "a_uv".to_string(),
+            components: 2,
+            data_type: GL::HALF_FLOAT,
+            normalized: false,
+            offset: 0
+        });
+        assert!(pack(&layout, &[&[0.0, 0.0]]).is_err());
+    }
+
+    fn color_layout() -> VertexLayout {
+        VertexLayout::new()
+            .attribute::<f32>("a_position", 3)
+            .normalized::<u8>("a_color", 4)
+    }
+
+    #[test]
+    fn every_attribute_needs_a_stream() {
+        assert_eq!(
+            pack(&color_layout(), &[&[0.0; 6]]),
+            Err(Error::TypeMismatch {
+                name: "vertex layout".to_string(),
+                expected: "2 streams".to_string(),
+                found: "1 streams".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn streams_hold_whole_values() {
+        assert_eq!(
+            pack(&color_layout(), &[&[0.0; 6], &[1.0; 7]]),
+            Err(Error::TypeMismatch {
+                name: "a_color".to_string(),
+                expected: "a multiple of 4 values".to_string(),
+                found: "7 values".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn streams_hold_the_same_vertices() {
+        assert_eq!(
+            pack(&color_layout(), &[&[0.0; 6], &[1.0; 12]]),
+            Err(Error::TypeMismatch {
+                name: "a_color".to_string(),
+                expected: "2 vertices like 'a_position'".to_string(),
+                found: "3 vertices".to_string()
+            })
+        );
+        assert_eq!(pack(&color_layout(), &[&[0.0; 6], &[1.0; 8]]).unwrap().len(), 2 * 16);
+    }
+}
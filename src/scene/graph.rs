@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use nalgebra::{Matrix4, Transform3, UnitQuaternion, Vector3};
+
+use crate::error::{Error, Result};
+use crate::render::Render;
+use super::node::*;
+
+
+/// Tree of nodes rooted at a node that always exists.
+///
+/// World matrices are cached on the nodes. Changing a node's transform or
+/// parent only marks it and its descendants dirty; matrices are recomputed
+/// the next time they are asked for.
+pub struct Scene {
+    root: NodeId,
+    nodes: HashMap<NodeId, Node>
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        let root = NodeId::new_v4();
+        let mut nodes = HashMap::new();
+        nodes.insert(root, Node::new());
+        Scene { root, nodes }
+    }
+
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    pub fn get(&self, id: NodeId) -> Result<&Node> {
+        self.nodes.get(&id).ok_or_else(|| Error::UnknownNode(id.to_string()))
+    }
+
+    fn get_mut(&mut self, id: NodeId) -> Result<&mut Node> {
+        self.nodes.get_mut(&id).ok_or_else(|| Error::UnknownNode(id.to_string()))
+    }
+
+    /// Adds `node` as the last child of `parent`.
+    pub fn add(&mut self, parent: NodeId, mut node: Node) -> Result<NodeId> {
+        let id = NodeId::new_v4();
+        self.get_mut(parent)?.children.push(id);
+
+        node.parent = Some(parent);
+        node.children.clear();
+        node.dirty.set(true);
+        self.nodes.insert(id, node);
+        Ok(id)
+    }
+
+    /// Removes a node together with all of its descendants.
+    pub fn remove(&mut self, id: NodeId) -> Result<()> {
+        if id == self.root {
+            return Err(Error::InvalidParent("the root node cannot be removed".to_string()));
+        }
+        let parent = self.get(id)?.parent;
+        if let Some(parent) = parent {
+            self.get_mut(parent)?.children.retain(|child| *child != id);
+        }
+
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if let Some(node) = self.nodes.remove(&id) {
+                stack.extend(node.children);
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves a node, with its descendants, under `parent`. The local
+    /// transform is kept, so the node moves with its new parent.
+    pub fn reparent(&mut self, id: NodeId, parent: NodeId) -> Result<()> {
+        self.get(parent)?;
+        if id == self.root {
+            return Err(Error::InvalidParent("the root node cannot be moved".to_string()));
+        }
+        if self.is_ancestor(id, parent)? {
+            return Err(Error::InvalidParent(format!("{} is a descendant of {}", parent, id)));
+        }
+
+        let old_parent = self.get(id)?.parent;
+        if let Some(old_parent) = old_parent {
+            self.get_mut(old_parent)?.children.retain(|child| *child != id);
+        }
+        self.get_mut(parent)?.children.push(id);
+        self.get_mut(id)?.parent = Some(parent);
+        self.mark_dirty(id);
+        Ok(())
+    }
+
+    /// Whether `ancestor` is `id` or one of its ancestors.
+    fn is_ancestor(&self, ancestor: NodeId, id: NodeId) -> Result<bool> {
+        let mut current = Some(id);
+        while let Some(node) = current {
+            if node == ancestor {
+                return Ok(true);
+            }
+            current = self.get(node)?.parent;
+        }
+        Ok(false)
+    }
+
+    pub fn set_translation(&mut self, id: NodeId, translation: Vector3<f32>) -> Result<()> {
+        self.get_mut(id)?.translation = translation;
+        self.mark_dirty(id);
+        Ok(())
+    }
+
+    pub fn set_rotation(&mut self, id: NodeId, rotation: UnitQuaternion<f32>) -> Result<()> {
+        self.get_mut(id)?.rotation = rotation;
+        self.mark_dirty(id);
+        Ok(())
+    }
+
+    pub fn set_scale(&mut self, id: NodeId, scale: Vector3<f32>) -> Result<()> {
+        self.get_mut(id)?.scale = scale;
+        self.mark_dirty(id);
+        Ok(())
+    }
+
+    /// Hides or shows a node and all of its descendants.
+    pub fn set_visible(&mut self, id: NodeId, visible: bool) -> Result<()> {
+        self.get_mut(id)?.visible = visible;
+        Ok(())
+    }
+
+    fn mark_dirty(&self, id: NodeId) {
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if let Some(node) = self.nodes.get(&id) {
+                // A node is never clean below a dirty one, so the walk can
+                // stop at nodes that are dirty already.
+                if !node.dirty.replace(true) {
+                    stack.extend(&node.children);
+                }
+            }
+        }
+    }
+
+    /// Transform from the node's local space to world space.
+    pub fn world_matrix(&self, id: NodeId) -> Result<Matrix4<f32>> {
+        let node = self.get(id)?;
+        if node.dirty.get() {
+            let parent_world = match node.parent {
+                Some(parent) => self.world_matrix(parent)?,
+                None => Matrix4::identity()
+            };
+            node.world.set(parent_world * node.local_matrix());
+            node.dirty.set(false);
+        }
+        Ok(node.world.get())
+    }
+
+    /// Renderables of all visible nodes with their world transforms, parents
+    /// before children.
+    pub fn visible_renderables(&self) -> Vec<(Transform3<f32>, Rc<dyn Render>)> {
+        let mut renderables = Vec::new();
+        let mut stack = vec![self.root];
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[&id];
+            if !node.visible {
+                continue;
+            }
+            if let Some(renderable) = &node.renderable {
+                let world = self.world_matrix(id).expect("scene nodes are consistent");
+                renderables.push((Transform3::from_matrix_unchecked(world), renderable.clone()));
+            }
+            stack.extend(node.children.iter().rev());
+        }
+        renderables
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+    use nalgebra::Point3;
+    use super::*;
+
+    fn assert_maps(matrix: Matrix4<f32>, from: [f32; 3], to: [f32; 3]) {
+        let mapped = matrix.transform_point(&Point3::from(from));
+        assert!((mapped - Point3::from(to)).norm() < 1e-5, "{} != {:?}", mapped, to);
+    }
+
+    #[test]
+    fn world_matrices_compose_parent_first() {
+        let mut scene = Scene::new();
+        let parent = scene.add(scene.root(), Node::new()).unwrap();
+        let child = scene.add(parent, Node::new()).unwrap();
+
+        scene.set_translation(parent, Vector3::new(10.0, 0.0, 0.0)).unwrap();
+        scene.set_rotation(parent, UnitQuaternion::from_axis_angle(&Vector3::y_axis(), FRAC_PI_2)).unwrap();
+        scene.set_scale(child, Vector3::new(2.0, 2.0, 2.0)).unwrap();
+        scene.set_translation(child, Vector3::new(0.0, 0.0, 1.0)).unwrap();
+
+        // Scaled, moved along the child's z, rotated onto x, then moved by the parent.
+        assert_maps(scene.world_matrix(child).unwrap(), [1.0, 0.0, 0.0], [10.0 + 1.0, 0.0, -2.0]);
+    }
+
+    #[test]
+    fn changes_reach_cached_descendants() {
+        let mut scene = Scene::new();
+        let parent = scene.add(scene.root(), Node::new()).unwrap();
+        let child = scene.add(parent, Node::new()).unwrap();
+        let grandchild = scene.add(child, Node::new()).unwrap();
+
+        assert_maps(scene.world_matrix(grandchild).unwrap(), [0.0; 3], [0.0; 3]);
+        assert!(!scene.get(child).unwrap().dirty.get());
+
+        scene.set_translation(parent, Vector3::new(0.0, 5.0, 0.0)).unwrap();
+        assert!(scene.get(grandchild).unwrap().dirty.get());
+        assert_maps(scene.world_matrix(grandchild).unwrap(), [0.0; 3], [0.0, 5.0, 0.0]);
+
+        // A second change before the matrices are read again.
+        scene.world_matrix(parent).unwrap();
+        scene.set_translation(parent, Vector3::new(0.0, 6.0, 0.0)).unwrap();
+        assert_maps(scene.world_matrix(grandchild).unwrap(), [0.0; 3], [0.0, 6.0, 0.0]);
+    }
+
+    #[test]
+    fn reparenting_keeps_the_local_transform() {
+        let mut scene = Scene::new();
+        let a = scene.add(scene.root(), Node::new()).unwrap();
+        let b = scene.add(scene.root(), Node::new()).unwrap();
+        let child = scene.add(a, Node::new()).unwrap();
+        scene.set_translation(a, Vector3::new(1.0, 0.0, 0.0)).unwrap();
+        scene.set_translation(b, Vector3::new(0.0, 1.0, 0.0)).unwrap();
+
+        assert_maps(scene.world_matrix(child).unwrap(), [0.0; 3], [1.0, 0.0, 0.0]);
+        scene.reparent(child, b).unwrap();
+        assert_maps(scene.world_matrix(child).unwrap(), [0.0; 3], [0.0, 1.0, 0.0]);
+        assert!(scene.get(a).unwrap().children.is_empty());
+        assert_eq!(scene.get(b).unwrap().children, vec![child]);
+    }
+
+    #[test]
+    fn cycles_and_root_changes_are_rejected() {
+        let mut scene = Scene::new();
+        let parent = scene.add(scene.root(), Node::new()).unwrap();
+        let child = scene.add(parent, Node::new()).unwrap();
+
+        assert!(matches!(scene.reparent(parent, child), Err(Error::InvalidParent(_))));
+        assert!(matches!(scene.reparent(parent, parent), Err(Error::InvalidParent(_))));
+        assert!(matches!(scene.reparent(scene.root(), parent), Err(Error::InvalidParent(_))));
+        assert!(matches!(scene.remove(scene.root()), Err(Error::InvalidParent(_))));
+        assert_eq!(scene.get(child).unwrap().parent, Some(parent));
+    }
+
+    #[test]
+    fn remove_takes_the_subtree() {
+        let mut scene = Scene::new();
+        let parent = scene.add(scene.root(), Node::new()).unwrap();
+        let child = scene.add(parent, Node::new()).unwrap();
+        let sibling = scene.add(scene.root(), Node::new()).unwrap();
+
+        scene.remove(parent).unwrap();
+        assert_eq!(scene.get(child).err(), Some(Error::UnknownNode(child.to_string())));
+        assert_eq!(scene.get(scene.root()).unwrap().children, vec![sibling]);
+        assert!(scene.remove(parent).is_err());
+    }
+
+    #[test]
+    fn ids_round_trip_through_strings() {
+        let id = NodeId::new_v4();
+        assert_eq!(NodeId::parse(&id.to_string()), Ok(id));
+        assert_eq!(NodeId::parse("globe"), Err(Error::UnknownNode("globe".to_string())));
+    }
+}
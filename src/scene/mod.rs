@@ -0,0 +1,5 @@
+mod graph;
+mod node;
+
+pub(in crate) use self::graph::*;
+pub(in crate) use self::node::*;
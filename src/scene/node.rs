@@ -0,0 +1,81 @@
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+use nalgebra::{Matrix4, Translation3, UnitQuaternion, Vector3};
+use uuid::Uuid;
+
+use crate::error::Error;
+use crate::render::Render;
+
+
+/// Stable identifier of a scene node, exchanged with JS as a UUID string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(Uuid);
+
+impl NodeId {
+    pub fn new_v4() -> Self {
+        NodeId(Uuid::new_v4())
+    }
+
+    pub fn parse(id: &str) -> Result<Self, Error> {
+        Uuid::parse_str(id)
+            .map(NodeId)
+            .map_err(|_| Error::UnknownNode(id.to_string()))
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+
+/// An entry of the scene graph: a local transform relative to the parent,
+/// and optionally something to draw with it.
+pub struct Node {
+    pub(super) parent: Option<NodeId>,
+    pub(super) children: Vec<NodeId>,
+    pub(super) translation: Vector3<f32>,
+    pub(super) rotation: UnitQuaternion<f32>,
+    pub(super) scale: Vector3<f32>,
+    pub(super) visible: bool,
+    pub(super) renderable: Option<Rc<dyn Render>>,
+    /// Parent world matrix times the local matrix, valid unless `dirty`.
+    pub(super) world: Cell<Matrix4<f32>>,
+    pub(super) dirty: Cell<bool>
+}
+
+impl Node {
+    /// An empty node, useful to group and move other nodes.
+    pub fn new() -> Self {
+        Node {
+            parent: None,
+            children: Vec::new(),
+            translation: Vector3::zeros(),
+            rotation: UnitQuaternion::identity(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            visible: true,
+            renderable: None,
+            world: Cell::new(Matrix4::identity()),
+            dirty: Cell::new(true)
+        }
+    }
+
+    pub fn with_renderable(renderable: Rc<dyn Render>) -> Self {
+        Node { renderable: Some(renderable), ..Node::new() }
+    }
+
+    /// Translation * rotation * scale.
+    pub fn local_matrix(&self) -> Matrix4<f32> {
+        Translation3::from(self.translation).to_homogeneous()
+            * self.rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self::new()
+    }
+}
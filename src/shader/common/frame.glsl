@@ -0,0 +1,11 @@
+// Per-frame values, see FrameUniforms
+layout(std140) uniform FrameUniforms {
+    mat4 u_view;
+    mat4 u_projection;
+    mat4 u_viewProjection;
+    vec3 u_cameraPosition;
+    float u_time;
+    vec2 u_viewportSize;
+    // Direction towards the sun, in world space
+    vec3 u_sunDirection;
+};
@@ -0,0 +1,13 @@
+// Bits of u_layers, see GlobeLayer
+const int LAYER_LIGHTING = 1;
+const int LAYER_SPECULAR = 2;
+const int LAYER_BUMP = 4;
+const int LAYER_NIGHT_LIGHTS = 8;
+const int LAYER_CLOUDS = 16;
+const int LAYER_ATMOSPHERE = 32;
+
+uniform int u_layers;
+
+bool enabled(int layer) {
+    return (u_layers & layer) != 0;
+}
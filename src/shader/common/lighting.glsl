@@ -0,0 +1,11 @@
+#include "common/frame.glsl"
+
+// Direction towards the sun, in view space
+vec3 viewSunDirection() {
+    return normalize(mat3(u_view) * u_sunDirection);
+}
+
+// Diffuse light with a little ambient so the night side is not pitch black
+float diffuse(vec3 normal, vec3 sun) {
+    return 0.03 + max(dot(normal, sun), 0.0);
+}
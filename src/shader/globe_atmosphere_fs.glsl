@@ -3,12 +3,16 @@
 precision highp float;
 precision highp int;
 
+uniform vec3 u_atmosphereColor;
+// Higher values keep the glow closer to the limb
+uniform float u_atmosphereFalloff;
+
 in vec3 v_normal;
 
 out vec4 outColor;
 
 void main() {
     vec3 normal = normalize(v_normal);
-    float intensity = pow(1.6 - dot(normal, vec3(0.0, 0.0, 1.0)), 12.0);
-    outColor = vec4(1.0, 1.0, 1.0, 1.0) * intensity;
+    float intensity = pow(1.6 - dot(normal, vec3(0.0, 0.0, 1.0)), u_atmosphereFalloff);
+    outColor = vec4(u_atmosphereColor, 1.0) * intensity;
 }
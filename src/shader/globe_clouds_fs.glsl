@@ -0,0 +1,36 @@
+#version 300 es
+
+precision highp float;
+
+#include "common/layers.glsl"
+#include "common/lighting.glsl"
+
+uniform sampler2D s_clouds;
+// Cloud cover, black where the sky is overcast
+uniform sampler2D s_cloudAlpha;
+
+// Drift of the cloud cover, in texture widths
+uniform float u_cloudOffset;
+
+in vec3 v_normal;
+in vec2 v_uv;
+
+out vec4 outColor;
+
+void main() {
+    // Wrap around the seam, using the gradients of the unwrapped coordinates
+    // so the mip level does not jump there.
+    vec2 uv = vec2(v_uv.x + u_cloudOffset, v_uv.y);
+    vec2 dx = dFdx(uv);
+    vec2 dy = dFdy(uv);
+    uv.x = fract(uv.x);
+
+    vec3 color = textureGrad(s_clouds, uv, dx, dy).rgb;
+    float alpha = 1.0 - textureGrad(s_cloudAlpha, uv, dx, dy).r;
+
+    if (enabled(LAYER_LIGHTING)) {
+        color *= diffuse(normalize(v_normal), viewSunDirection());
+    }
+
+    outColor = vec4(color, alpha);
+}
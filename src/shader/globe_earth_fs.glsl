@@ -2,17 +2,109 @@
 
 precision highp float;
 
+// HAS_SPECULAR_MAP, HAS_BUMP_MAP and HAS_NIGHT_LIGHTS are defined when the
+// matching map is loaded.
+
+#include "common/layers.glsl"
+#include "common/lighting.glsl"
+
 uniform sampler2D s_texture;
+#ifdef HAS_SPECULAR_MAP
+uniform sampler2D s_specular;
+#endif
+#ifdef HAS_BUMP_MAP
+uniform sampler2D s_bump;
+uniform float u_bumpScale;
+#endif
+#ifdef HAS_NIGHT_LIGHTS
+uniform sampler2D s_nightLights;
+#endif
 
+// Data drawn over the surface, e.g. a temperature grid. One channel maps
+// u_overlayRange to the colors from u_overlayLow to u_overlayHigh, two
+// channels are such a value and an alpha, four channels a color.
+uniform sampler2D s_overlay;
+uniform int u_overlayComponents;
+uniform vec2 u_overlayRange;
+uniform vec4 u_overlayLow;
+uniform vec4 u_overlayHigh;
+uniform float u_overlayOpacity;
+
+in vec3 v_position;
 in vec3 v_normal;
+in vec4 v_tangent;
 in vec2 v_uv;
 
 out vec4 outColor;
 
+#ifdef HAS_BUMP_MAP
+vec3 bumpedNormal(vec3 normal) {
+    vec2 texel = 1.0 / vec2(textureSize(s_bump, 0));
+    float height = texture(s_bump, v_uv).r;
+    float dhdu = texture(s_bump, v_uv + vec2(texel.x, 0.0)).r - height;
+    float dhdv = texture(s_bump, v_uv + vec2(0.0, texel.y)).r - height;
+
+    vec3 tangent = normalize(v_tangent.xyz - normal * dot(normal, v_tangent.xyz));
+    vec3 bitangent = cross(normal, tangent) * v_tangent.w;
+    return normalize(normal - u_bumpScale * (dhdu * tangent + dhdv * bitangent));
+}
+#endif
+
+vec4 overlayColor() {
+    vec4 texel = texture(s_overlay, v_uv);
+    if (u_overlayComponents == 4) {
+        return texel;
+    }
+    float value = (texel.r - u_overlayRange.x) / max(u_overlayRange.y - u_overlayRange.x, 1e-6);
+    vec4 color = mix(u_overlayLow, u_overlayHigh, clamp(value, 0.0, 1.0));
+    if (u_overlayComponents == 2) {
+        color.a *= texel.g;
+    }
+    return color;
+}
+
 void main() {
     vec3 normal = normalize(v_normal);
-    vec3 diffuse = texture(s_texture, v_uv).xyz;
-    float atm_intensity = pow(1.05 - dot(normal, vec3(0.0, 0.0, 1.0)), 3.0);
-    vec3 atmosphere = vec3(1.0, 1.0, 1.0) * atm_intensity;
-    outColor = vec4(diffuse + atmosphere, 1.0);
+    vec3 color = texture(s_texture, v_uv).rgb;
+
+    if (enabled(LAYER_LIGHTING)) {
+        vec3 sun = viewSunDirection();
+        vec3 surfaceNormal = normal;
+#ifdef HAS_BUMP_MAP
+        if (enabled(LAYER_BUMP)) {
+            surfaceNormal = bumpedNormal(normal);
+        }
+#endif
+
+        // 1 on the day side, 0 on the night side. Uses the smooth normal so
+        // relief does not speckle the terminator.
+        float day = smoothstep(-0.1, 0.1, dot(normal, sun));
+        vec3 lit = color * diffuse(surfaceNormal, sun);
+
+#ifdef HAS_SPECULAR_MAP
+        if (enabled(LAYER_SPECULAR)) {
+            vec3 halfway = normalize(sun - normalize(v_position));
+            float shininess = pow(max(dot(surfaceNormal, halfway), 0.0), 32.0);
+            lit += vec3(0.6) * shininess * texture(s_specular, v_uv).r * day;
+        }
+#endif
+
+#ifdef HAS_NIGHT_LIGHTS
+        if (enabled(LAYER_NIGHT_LIGHTS)) {
+            lit += texture(s_nightLights, v_uv).rgb * (1.0 - day);
+        }
+#endif
+
+        color = lit;
+    }
+
+    vec4 overlay = overlayColor();
+    color = mix(color, overlay.rgb, overlay.a * u_overlayOpacity);
+
+    if (enabled(LAYER_ATMOSPHERE)) {
+        float atm_intensity = pow(1.05 - dot(normal, vec3(0.0, 0.0, 1.0)), 3.0);
+        color += vec3(1.0, 1.0, 1.0) * atm_intensity;
+    }
+
+    outColor = vec4(color, 1.0);
 }
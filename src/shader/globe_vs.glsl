@@ -0,0 +1,40 @@
+#version 300 es
+
+// Shared by the Earth, clouds and atmosphere. HAS_UV and HAS_TANGENTS
+// enable the texture coordinates and tangents.
+
+#include "common/frame.glsl"
+
+uniform mat4 u_modelViewMatrix;
+uniform mat3 u_normalMatrix;
+
+in vec4 a_position;
+in vec3 a_normal;
+#ifdef HAS_TANGENTS
+in vec4 a_tangent;
+#endif
+#ifdef HAS_UV
+in vec2 a_uv;
+#endif
+
+out vec3 v_position;
+out vec3 v_normal;
+#ifdef HAS_TANGENTS
+out vec4 v_tangent;
+#endif
+#ifdef HAS_UV
+out vec2 v_uv;
+#endif
+
+void main() {
+    vec4 position = u_modelViewMatrix * a_position;
+    gl_Position = u_projection * position;
+    v_position = position.xyz;
+    v_normal = normalize(u_normalMatrix * a_normal);
+#ifdef HAS_TANGENTS
+    v_tangent = vec4(normalize(u_normalMatrix * a_tangent.xyz), a_tangent.w);
+#endif
+#ifdef HAS_UV
+    v_uv = a_uv;
+#endif
+}
@@ -0,0 +1,16 @@
+#version 300 es
+
+precision highp float;
+
+in vec2 v_corner;
+in vec4 v_color;
+
+out vec4 outColor;
+
+void main() {
+    // Round off the square
+    if (dot(v_corner, v_corner) > 1.0) {
+        discard;
+    }
+    outColor = v_color;
+}
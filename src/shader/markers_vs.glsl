@@ -0,0 +1,25 @@
+#version 300 es
+
+// Markers, drawn as squares facing the camera around their position.
+
+#include "common/frame.glsl"
+
+uniform mat4 u_modelViewMatrix;
+// Width of a marker, in view space units
+uniform float u_markerSize;
+
+in vec4 a_position;
+// Corner of the square, in [-1, 1]
+in vec2 a_corner;
+in vec4 a_color;
+
+out vec2 v_corner;
+out vec4 v_color;
+
+void main() {
+    vec4 position = u_modelViewMatrix * a_position;
+    position.xy += a_corner * u_markerSize * 0.5;
+    gl_Position = u_projection * position;
+    v_corner = a_corner;
+    v_color = a_color;
+}
@@ -1,91 +1,303 @@
+mod preprocessor;
+mod reflection;
+mod uniform;
+
 use std::collections::HashMap;
-use web_sys::*;
+use std::fmt;
 use web_sys::WebGl2RenderingContext as GL;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+
+use crate::backend::{GlBackend, Handle, ProgramId, ShaderId, UniformLocationId};
+use crate::error::{Error, Result};
+
+pub(in crate) use self::preprocessor::*;
+pub(in crate) use self::reflection::*;
+pub(in crate) use self::uniform::*;
+
+
+/// Uniform block holding the per-frame values, see `FrameUniforms`.
+pub const FRAME_UNIFORMS_BLOCK: &str = "FrameUniforms";
+/// Uniform buffer binding point `FRAME_UNIFORMS_BLOCK` is attached to.
+pub const FRAME_UNIFORMS_BINDING: u32 = 0;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment
+}
+
+impl ShaderStage {
+    fn gl_type(self) -> u32 {
+        match self {
+            ShaderStage::Vertex => GL::VERTEX_SHADER,
+            ShaderStage::Fragment => GL::FRAGMENT_SHADER
+        }
+    }
+}
+
+impl fmt::Display for ShaderStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderStage::Vertex => write!(f, "vertex"),
+            ShaderStage::Fragment => write!(f, "fragment")
+        }
+    }
+}
 
 
 #[derive(Clone)]
 pub struct Shader {
-    pub program: WebGlProgram,
-    vertex_attrs: RefCell<HashMap<String, Option<u32>>>,
-    uniforms: RefCell<HashMap<String, Option<WebGlUniformLocation>>>
+    program: RefCell<Handle<ProgramId>>,
+    reflection: RefCell<Reflection>,
+    /// Id of the `Material` whose values the program currently holds.
+    bound_material: Cell<u64>
 }
 
 impl Shader {
     pub fn new(
-        gl: &GL,
+        gl: &dyn GlBackend,
         vert_shader: &str,
         frag_shader: &str
-    ) -> Result<Shader, String> {
+    ) -> Result<Shader> {
+
+        let (program, reflection) = build_program(gl, vert_shader, frag_shader, &HashMap::new())?;
+
+        Ok(Shader {
+            program: RefCell::new(program),
+            reflection: RefCell::new(reflection),
+            bound_material: Cell::new(0)
+        })
+    }
 
-        let vs = compile_shader(&gl, GL::VERTEX_SHADER, vert_shader)?;
-        let fs = compile_shader(&gl, GL::FRAGMENT_SHADER, frag_shader)?;
-        let program = link_program(&gl, &vs, &fs)?;
+    pub fn program(&self) -> ProgramId {
+        self.program.borrow().id()
+    }
+
+    pub fn attribute(&self, name: &str) -> Result<ActiveAttribute> {
+        self.reflection.borrow().attributes.get(name)
+            .cloned()
+            .ok_or_else(|| Error::MissingAttribute(name.to_string()))
+    }
 
-        let vertex_attrs = RefCell::new(HashMap::new());
-        let uniforms = RefCell::new(HashMap::new());
+    pub fn uniform(&self, name: &str) -> Result<ActiveUniform> {
+        self.reflection.borrow().uniforms.get(name)
+            .cloned()
+            .ok_or_else(|| Error::MissingUniform(name.to_string()))
+    }
 
-        Ok(Shader { program, vertex_attrs, uniforms })
+    /// Location of a uniform the program may not use, such as the normal
+    /// matrix for shaders without lighting.
+    pub fn uniform_location(&self, name: &str) -> Option<UniformLocationId> {
+        self.reflection.borrow().uniforms.get(name).and_then(|uniform| uniform.location)
     }
 
-    pub fn get_attrib_location(&self, gl: &GL, name: &str) -> Option<u32> {
-        let mut vertex_attrs = self.vertex_attrs.borrow_mut();
-        *vertex_attrs.entry(name.to_string())
-            .or_insert(match gl.get_attrib_location(&self.program, name) {
-                x if x < 0 => None,
-                x => Some(x as u32)
-            })
+    /// Records that the program holds the values of the material
+    /// `material_id` from now on, and returns whether they all have to be
+    /// uploaded, because another material or a new program came before.
+    pub fn mark_bound(&self, material_id: u64) -> bool {
+        self.bound_material.replace(material_id) != material_id
     }
 
-    pub fn get_uniform_location(&self, gl: &GL, name: &str) -> Option<WebGlUniformLocation> {
-        let mut uniforms = self.uniforms.borrow_mut();
-        match uniforms.entry(name.to_string())
-            .or_insert(gl.get_uniform_location(&self.program, name)) {
-            Some(x) => Some((*x).clone()),
-            _ => None
+    /// Builds a program from new sources that can take the place of this
+    /// one. Attributes keep their locations, so vertex arrays set up for
+    /// this shader still work with it.
+    pub fn build_replacement(&self, gl: &dyn GlBackend, vert_shader: &str, frag_shader: &str) -> Result<Handle<ProgramId>> {
+        let locations = self.reflection.borrow().attributes.values()
+            .map(|attribute| (attribute.name.clone(), attribute.location))
+            .collect();
+        build_program(gl, vert_shader, frag_shader, &locations).map(|(program, _)| program)
+    }
+
+    /// Swaps in a program made by `build_replacement` and releases the
+    /// current one. Every renderable sharing this shader draws with the new
+    /// program from then on.
+    pub fn replace_program(&self, gl: &dyn GlBackend, program: Handle<ProgramId>) {
+        self.reflection.replace(Reflection::of(gl, program.id()));
+        self.program.replace(program);
+        // The new program has none of the material values yet.
+        self.bound_material.set(0);
+    }
+}
+
+
+/// Compiles and links a program, binding the attributes in `locations` and
+/// the `FrameUniforms` block.
+fn build_program(
+    gl: &dyn GlBackend,
+    vert_shader: &str,
+    frag_shader: &str,
+    locations: &HashMap<String, u32>
+) -> Result<(Handle<ProgramId>, Reflection)> {
+
+    let vs = compile_shader(gl, ShaderStage::Vertex, vert_shader)?;
+    let fs = match compile_shader(gl, ShaderStage::Fragment, frag_shader) {
+        Ok(fs) => fs,
+        Err(error) => {
+            gl.delete_shader(vs);
+            return Err(error);
         }
+    };
+    let program = link_program(gl, vs, fs, locations);
+    // The program keeps what it needs from the shaders once linked.
+    gl.delete_shader(vs);
+    gl.delete_shader(fs);
+    let program = program?;
+    let reflection = Reflection::of(gl, program.id());
+
+    // Blocks are numbered in the order they are reported.
+    if let Some(index) = reflection.uniform_blocks.iter().position(|block| block == FRAME_UNIFORMS_BLOCK) {
+        gl.uniform_block_binding(program.id(), index as u32, FRAME_UNIFORMS_BINDING);
     }
+
+    Ok((program, reflection))
 }
 
 
 fn compile_shader(
-    gl: &GL,
-    shader_type: u32,
+    gl: &dyn GlBackend,
+    stage: ShaderStage,
     source: &str
-) -> Result<WebGlShader, String> {
+) -> Result<ShaderId> {
 
-    let shader = gl.create_shader(shader_type)
-        .ok_or_else(|| "Could not create shader".to_string())?;
+    let shader = gl.create_shader(stage.gl_type())
+        .ok_or(Error::ObjectCreation("shader"))?;
 
-    gl.shader_source(&shader, source);
-    gl.compile_shader(&shader);
+    gl.shader_source(shader, source);
+    gl.compile_shader(shader);
 
-    if gl.get_shader_parameter(&shader, GL::COMPILE_STATUS).as_bool().unwrap_or(false) {
+    if gl.shader_compile_status(shader) {
         Ok(shader)
     } else {
-        Err(gl.get_shader_info_log(&shader)
-            .unwrap_or_else(|| "Unknown error creating shader".to_string()))
+        let log = gl.shader_info_log(shader)
+            .unwrap_or_else(|| "Unknown error creating shader".to_string());
+        gl.delete_shader(shader);
+        Err(Error::ShaderCompile { stage, log })
     }
 }
 
 
 fn link_program(
-    gl: &GL,
-    vert_shader: &WebGlShader,
-    frag_shader: &WebGlShader
-) -> Result<WebGlProgram, String> {
+    gl: &dyn GlBackend,
+    vert_shader: ShaderId,
+    frag_shader: ShaderId,
+    locations: &HashMap<String, u32>
+) -> Result<Handle<ProgramId>> {
 
     let program = gl.create_program()
-        .ok_or_else(|| "unable to create shader program".to_string())?;
+        .ok_or(Error::ObjectCreation("shader program"))?;
+    // Released again when linking fails.
+    let handle = gl.resources().track(program);
 
-    gl.attach_shader(&program, &vert_shader);
-    gl.attach_shader(&program, &frag_shader);
-    gl.link_program(&program);
+    gl.attach_shader(program, vert_shader);
+    gl.attach_shader(program, frag_shader);
+    for (name, location) in locations {
+        gl.bind_attrib_location(program, *location, name);
+    }
+    gl.link_program(program);
 
-    if gl.get_program_parameter(&program, GL::LINK_STATUS).as_bool().unwrap_or(false) {
-        Ok(program)
+    if gl.program_link_status(program) {
+        Ok(handle)
     } else {
-        Err(gl.get_program_info_log(&program)
-            .unwrap_or_else(|| "Unknown error creating program".to_string()))
+        Err(Error::ProgramLink(gl.program_info_log(program)
+            .unwrap_or_else(|| "Unknown error creating program".to_string())))
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{GlCall, MockBackend};
+
+    static VS: &str = "#version 300 es\nin vec4 a_position;\nout vec3 v_normal;\nvoid main() {}\n";
+    static FS: &str = "#version 300 es\nin vec3 v_normal;\nout vec4 outColor;\nvoid main() {}\n";
+
+    #[test]
+    fn compile_errors_report_the_stage_and_log() {
+        let gl = MockBackend::new();
+        let broken = "#version 300 es\n#error missing semicolon\n";
+
+        let error = Shader::new(&gl, VS, broken).err().unwrap();
+        assert_eq!(error, Error::ShaderCompile {
+            stage: ShaderStage::Fragment,
+            log: "ERROR: 0:2: '#error' : missing semicolon".to_string()
+        });
+        assert_eq!(
+            error.to_string(),
+            "fragment shader failed to compile: ERROR: 0:2: '#error' : missing semicolon"
+        );
+
+        let error = Shader::new(&gl, broken, FS).err().unwrap();
+        assert!(matches!(error, Error::ShaderCompile { stage: ShaderStage::Vertex, .. }));
+    }
+
+    #[test]
+    fn link_errors_are_reported() {
+        let gl = MockBackend::new();
+        let vs = "#version 300 es\nin vec4 a_position;\nvoid main() {}\n";
+
+        let error = Shader::new(&gl, vs, FS).err().unwrap();
+        assert!(matches!(error, Error::ProgramLink(log) if log.contains("v_normal")));
+    }
+
+    #[test]
+    fn frame_uniforms_are_bound_when_declared() {
+        let gl = MockBackend::new();
+        let vs = "#version 300 es\nlayout(std140) uniform FrameUniforms {\n    mat4 u_view;\n};\nout vec3 v_normal;\nvoid main() {}\n";
+
+        let shader = Shader::new(&gl, vs, FS).unwrap();
+        assert!(gl.calls().contains(&GlCall::UniformBlockBinding(shader.program(), 0, FRAME_UNIFORMS_BINDING)));
+
+        gl.clear_calls();
+        Shader::new(&gl, VS, FS).unwrap();
+        assert!(!gl.calls().iter().any(|c| matches!(c, GlCall::UniformBlockBinding(..))));
+    }
+
+    #[test]
+    fn shader_objects_are_deleted_once_linked() {
+        let gl = MockBackend::new();
+        Shader::new(&gl, VS, FS).unwrap();
+        let deleted = gl.calls().iter().filter(|c| matches!(c, GlCall::DeleteShader(_))).count();
+        assert_eq!(deleted, 2);
+
+        gl.clear_calls();
+        Shader::new(&gl, VS, "#version 300 es\n#error broken\n").err().unwrap();
+        let deleted = gl.calls().iter().filter(|c| matches!(c, GlCall::DeleteShader(_))).count();
+        assert_eq!(deleted, 2);
+        // Nothing is left of the failed attempt.
+        assert_eq!(gl.resources().stats().programs, 0);
+    }
+
+    #[test]
+    fn missing_names_are_typed_errors() {
+        let gl = MockBackend::new();
+        let shader = Shader::new(&gl, VS, FS).unwrap();
+
+        assert_eq!(shader.attribute("a_position").map(|a| a.location), Ok(0));
+        assert_eq!(shader.attribute("a_positon"), Err(Error::MissingAttribute("a_positon".to_string())));
+        assert_eq!(shader.uniform("u_color"), Err(Error::MissingUniform("u_color".to_string())));
+        assert_eq!(shader.uniform_location("u_color"), None);
+    }
+
+    #[test]
+    fn active_names_are_reflected_with_their_types() {
+        let gl = MockBackend::new();
+        let vs = "#version 300 es\nin vec4 a_position;\nin vec2 a_uv;\n\
+                  layout(std140) uniform FrameUniforms {\n    mat4 u_view;\n};\n\
+                  uniform mat4 u_modelViewMatrix;\nuniform float u_weights[4];\nout vec3 v_normal;\nvoid main() {}\n";
+        let fs = "#version 300 es\nuniform sampler2D s_color;\nin vec3 v_normal;\nout vec4 outColor;\nvoid main() {}\n";
+        let shader = Shader::new(&gl, vs, fs).unwrap();
+
+        let uv = shader.attribute("a_uv").unwrap();
+        assert_eq!((uv.gl_type, uv.size, uv.location), (GL::FLOAT_VEC2, 1, 1));
+
+        let weights = shader.uniform("u_weights").unwrap();
+        assert_eq!((weights.gl_type, weights.size), (GL::FLOAT, 4));
+        assert!(weights.location.is_some());
+        assert_eq!(shader.uniform("u_modelViewMatrix").map(|u| u.gl_type), Ok(GL::FLOAT_MAT4));
+        assert_eq!(shader.uniform("s_color").map(|u| u.gl_type), Ok(GL::SAMPLER_2D));
+        // Block members are set through the uniform buffer, not one by one.
+        assert!(shader.uniform("u_view").is_err());
+    }
+}
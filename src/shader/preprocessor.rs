@@ -0,0 +1,496 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::backend::GlBackend;
+use crate::error::{Error, Result};
+use super::{Shader, ShaderStage};
+
+
+/// GLSL files embedded in the binary, by the name used to include them.
+static EMBEDDED: &[(&str, &str)] = &[
+    ("common/frame.glsl", include_str!("common/frame.glsl")),
+    ("common/layers.glsl", include_str!("common/layers.glsl")),
+    ("common/lighting.glsl", include_str!("common/lighting.glsl")),
+    ("globe_vs.glsl", include_str!("globe_vs.glsl")),
+    ("globe_earth_fs.glsl", include_str!("globe_earth_fs.glsl")),
+    ("globe_clouds_fs.glsl", include_str!("globe_clouds_fs.glsl")),
+    ("globe_atmosphere_fs.glsl", include_str!("globe_atmosphere_fs.glsl")),
+    ("markers_vs.glsl", include_str!("markers_vs.glsl")),
+    ("markers_fs.glsl", include_str!("markers_fs.glsl"))
+];
+
+/// Programs shipped with the crate: name, vertex file and fragment file.
+static EMBEDDED_PROGRAMS: &[(&str, &str, &str)] = &[
+    ("globe_earth", "globe_vs.glsl", "globe_earth_fs.glsl"),
+    ("globe_clouds", "globe_vs.glsl", "globe_clouds_fs.glsl"),
+    ("globe_atmosphere", "globe_vs.glsl", "globe_atmosphere_fs.glsl"),
+    ("markers", "markers_vs.glsl", "markers_fs.glsl")
+];
+
+/// Name reported for the lines of injected `#define`s.
+const DEFINES_FILE: &str = "<defines>";
+
+
+/// GLSL sources that can be compiled or included by name, and the
+/// programs made of them.
+#[derive(Clone, Debug, Default)]
+pub struct ShaderRegistry {
+    sources: HashMap<String, String>,
+    programs: HashMap<String, (String, String)>
+}
+
+impl ShaderRegistry {
+    pub fn new() -> Self {
+        ShaderRegistry::default()
+    }
+
+    /// The shaders shipped with the crate.
+    pub fn embedded() -> Self {
+        let mut registry = ShaderRegistry::new();
+        for (name, source) in EMBEDDED {
+            registry.insert(name, source);
+        }
+        for (name, vertex, fragment) in EMBEDDED_PROGRAMS {
+            registry.add_program(name, vertex, fragment);
+        }
+        registry
+    }
+
+    pub fn insert(&mut self, name: &str, source: &str) {
+        self.sources.insert(name.to_string(), source.to_string());
+    }
+
+    /// Declares the program `name`, built from two files of the registry.
+    pub fn add_program(&mut self, name: &str, vertex: &str, fragment: &str) {
+        self.programs.insert(name.to_string(), (vertex.to_string(), fragment.to_string()));
+    }
+
+    /// Vertex and fragment files of the program `name`.
+    pub fn program(&self, name: &str) -> Result<(&str, &str)> {
+        self.programs.get(name)
+            .map(|(vertex, fragment)| (vertex.as_str(), fragment.as_str()))
+            .ok_or_else(|| Error::ShaderSource(format!("unknown program \"{}\"", name)))
+    }
+
+    pub fn get(&self, name: &str) -> Result<&str> {
+        self.sources.get(name)
+            .map(|source| source.as_str())
+            .ok_or_else(|| Error::ShaderSource(format!("unknown file \"{}\"", name)))
+    }
+
+    /// Expands the `#include "name"` directives of `name` and adds a
+    /// `#define` for each of `defines` right after the `#version` line.
+    ///
+    /// Each file is included at most once, so common files can include
+    /// what they depend on.
+    pub fn preprocess(&self, name: &str, defines: &[(&str, &str)]) -> Result<Preprocessed> {
+        let mut output = Preprocessed::default();
+        let mut included = Vec::new();
+        let mut stack = Vec::new();
+        self.expand(name, defines, &mut output, &mut included, &mut stack)?;
+        Ok(output)
+    }
+
+    fn expand(
+        &self,
+        name: &str,
+        defines: &[(&str, &str)],
+        output: &mut Preprocessed,
+        included: &mut Vec<String>,
+        stack: &mut Vec<String>
+    ) -> Result<()> {
+        if stack.iter().any(|file| file == name) {
+            return Err(Error::ShaderSource(format!("\"{}\" includes itself", name)));
+        }
+        if included.iter().any(|file| file == name) {
+            return Ok(());
+        }
+        let source = self.get(name)?;
+        included.push(name.to_string());
+        stack.push(name.to_string());
+
+        for (i, line) in source.lines().enumerate() {
+            let trimmed = line.trim();
+            if let Some(rest) = trimmed.strip_prefix("#include") {
+                let include = rest.trim()
+                    .strip_prefix('"')
+                    .and_then(|rest| rest.strip_suffix('"'))
+                    .ok_or_else(|| Error::ShaderSource(
+                        format!("{}:{}: expected #include \"file\"", name, i + 1)
+                    ))?;
+                if !self.sources.contains_key(include) {
+                    return Err(Error::ShaderSource(
+                        format!("{}:{}: unknown file \"{}\"", name, i + 1, include)
+                    ));
+                }
+                self.expand(include, defines, output, included, stack)?;
+                continue;
+            }
+
+            output.push(line, name, i as u32 + 1);
+
+            // Defines go after the #version line, which has to come first.
+            if stack.len() == 1 && trimmed.starts_with("#version") {
+                for (n, (define, value)) in defines.iter().enumerate() {
+                    let define = if value.is_empty() {
+                        format!("#define {}", define)
+                    } else {
+                        format!("#define {} {}", define, value)
+                    };
+                    output.push(&define, DEFINES_FILE, n as u32 + 1);
+                }
+            }
+        }
+
+        stack.pop();
+        Ok(())
+    }
+}
+
+
+/// Output of `ShaderRegistry::preprocess`, with the origin of each line.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Preprocessed {
+    pub source: String,
+    /// File and 1-based line number of each output line.
+    origins: Vec<(String, u32)>
+}
+
+impl Preprocessed {
+    fn push(&mut self, line: &str, file: &str, line_number: u32) {
+        self.source.push_str(line);
+        self.source.push('\n');
+        self.origins.push((file.to_string(), line_number));
+    }
+
+    /// File and line an output line (1-based) came from.
+    pub fn origin(&self, line: u32) -> Option<(&str, u32)> {
+        line.checked_sub(1)
+            .and_then(|i| self.origins.get(i as usize))
+            .map(|(file, line)| (file.as_str(), *line))
+    }
+
+    /// Rewrites the `ERROR: 0:12:` prefixes of a compile log to point at the
+    /// original files, e.g. `ERROR: common/lighting.glsl:4:`.
+    pub fn map_log(&self, log: &str) -> String {
+        log.lines()
+            .map(|line| self.map_log_line(line).unwrap_or_else(|| line.to_string()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn map_log_line(&self, line: &str) -> Option<String> {
+        let (severity, rest) = line.split_once(": 0:")?;
+        let (number, message) = rest.split_once(':')?;
+        let (file, original) = self.origin(number.parse().ok()?)?;
+        Some(format!("{}: {}:{}:{}", severity, file, original, message))
+    }
+}
+
+
+/// Compiled variants of the registry programs, one for each set of
+/// defines they are used with.
+pub struct ShaderCache {
+    registry: ShaderRegistry,
+    variants: HashMap<(String, Vec<(String, String)>), Variant>
+}
+
+struct Variant {
+    shader: Rc<Shader>,
+    /// Preprocessed vertex and fragment sources, to tell whether a reload
+    /// changes them.
+    sources: (String, String)
+}
+
+impl ShaderCache {
+    pub fn new(registry: ShaderRegistry) -> Self {
+        ShaderCache { registry, variants: HashMap::new() }
+    }
+
+    /// Returns the variant of `program` with `defines`, compiling it on
+    /// first use. Line numbers in compile errors refer to the original
+    /// files.
+    pub fn get(&mut self, gl: &dyn GlBackend, program: &str, defines: &[(&str, &str)]) -> Result<Rc<Shader>> {
+        let key = (
+            program.to_string(),
+            defines.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+        );
+        if let Some(variant) = self.variants.get(&key) {
+            return Ok(variant.shader.clone());
+        }
+
+        let (vs, fs) = self.preprocess(program, defines)?;
+        let shader = Rc::new(with_mapped_log(Shader::new(gl, &vs.source, &fs.source), &vs, &fs)?);
+        let sources = (vs.source, fs.source);
+        self.variants.insert(key, Variant { shader: shader.clone(), sources });
+        Ok(shader)
+    }
+
+    /// Replaces the sources of `program` and rebuilds every cached variant
+    /// they change, including other programs sharing or including the same
+    /// files. Renderables using those variants pick up the new programs.
+    ///
+    /// Nothing changes when a variant fails to build: the previous sources
+    /// and programs are kept and the error carries the info log.
+    pub fn reload(&mut self, gl: &dyn GlBackend, program: &str, vs_source: &str, fs_source: &str) -> Result<()> {
+        let (vertex, fragment) = self.registry.program(program)?;
+        let (vertex, fragment) = (vertex.to_string(), fragment.to_string());
+        let previous = self.registry.clone();
+        self.registry.insert(&vertex, vs_source);
+        self.registry.insert(&fragment, fs_source);
+
+        let mut replacements = Vec::new();
+        let mut result = Ok(());
+        for (key, variant) in &self.variants {
+            let defines: Vec<_> = key.1.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect();
+            let built = self.preprocess(&key.0, &defines).and_then(|(vs, fs)| {
+                let sources = (vs.source.clone(), fs.source.clone());
+                if sources == variant.sources {
+                    return Ok(None);
+                }
+                let replacement = variant.shader.build_replacement(gl, &vs.source, &fs.source);
+                with_mapped_log(replacement, &vs, &fs).map(|program| Some((program, sources)))
+            });
+            match built {
+                Ok(Some((program, sources))) => replacements.push((key.clone(), program, sources)),
+                Ok(None) => {},
+                Err(error) => {
+                    result = Err(error);
+                    break;
+                }
+            }
+        }
+
+        if result.is_err() {
+            // Dropping the replacements releases their programs.
+            self.registry = previous;
+            return result;
+        }
+
+        for (key, program, sources) in replacements {
+            if let Some(variant) = self.variants.get_mut(&key) {
+                variant.shader.replace_program(gl, program);
+                variant.sources = sources;
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds every cached variant again from its sources, after the
+    /// context holding the programs was lost. Shaders keep their identity,
+    /// so renderables pick up the new programs.
+    pub fn restore(&mut self, gl: &dyn GlBackend) -> Result<()> {
+        for variant in self.variants.values() {
+            let (vs, fs) = &variant.sources;
+            let program = variant.shader.build_replacement(gl, vs, fs)?;
+            variant.shader.replace_program(gl, program);
+        }
+        Ok(())
+    }
+
+    fn preprocess(&self, program: &str, defines: &[(&str, &str)]) -> Result<(Preprocessed, Preprocessed)> {
+        let (vertex, fragment) = self.registry.program(program)?;
+        Ok((self.registry.preprocess(vertex, defines)?, self.registry.preprocess(fragment, defines)?))
+    }
+}
+
+/// Points the line numbers of a compile error at the original files.
+fn with_mapped_log<T>(result: Result<T>, vs: &Preprocessed, fs: &Preprocessed) -> Result<T> {
+    result.map_err(|error| match error {
+        Error::ShaderCompile { stage, log } => {
+            let log = match stage {
+                ShaderStage::Vertex => vs.map_log(&log),
+                ShaderStage::Fragment => fs.map_log(&log)
+            };
+            Error::ShaderCompile { stage, log }
+        },
+        error => error
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{GlCall, MockBackend};
+
+    fn registry() -> ShaderRegistry {
+        let mut registry = ShaderRegistry::new();
+        registry.insert("common/math.glsl", "const float PI = 3.14159;\n");
+        registry.insert("common/light.glsl", "#include \"common/math.glsl\"\nfloat light() { return PI; }\n");
+        registry.insert("main.glsl", "#version 300 es\n#include \"common/math.glsl\"\n#include \"common/light.glsl\"\nvoid main() {}\n");
+        registry
+    }
+
+    #[test]
+    fn includes_are_expanded_once() {
+        let output = registry().preprocess("main.glsl", &[]).unwrap();
+        assert_eq!(
+            output.source,
+            "#version 300 es\nconst float PI = 3.14159;\nfloat light() { return PI; }\nvoid main() {}\n"
+        );
+        assert_eq!(output.origin(1), Some(("main.glsl", 1)));
+        assert_eq!(output.origin(2), Some(("common/math.glsl", 1)));
+        assert_eq!(output.origin(3), Some(("common/light.glsl", 2)));
+        assert_eq!(output.origin(4), Some(("main.glsl", 4)));
+        assert_eq!(output.origin(5), None);
+    }
+
+    #[test]
+    fn defines_follow_the_version() {
+        let output = registry().preprocess("main.glsl", &[("NIGHT_LIGHTS", ""), ("MAX_LIGHTS", "4")]).unwrap();
+        let lines: Vec<_> = output.source.lines().take(3).collect();
+        assert_eq!(lines, vec!["#version 300 es", "#define NIGHT_LIGHTS", "#define MAX_LIGHTS 4"]);
+        assert_eq!(output.origin(3), Some(("<defines>", 2)));
+        assert_eq!(output.origin(4), Some(("common/math.glsl", 1)));
+    }
+
+    #[test]
+    fn bad_includes_are_errors() {
+        let mut registry = registry();
+        registry.insert("loop.glsl", "#include \"loop.glsl\"\n");
+        registry.insert("missing.glsl", "\n#include \"nowhere.glsl\"\n");
+        registry.insert("unquoted.glsl", "#include nowhere.glsl\n");
+
+        assert_eq!(
+            registry.preprocess("loop.glsl", &[]),
+            Err(Error::ShaderSource("\"loop.glsl\" includes itself".to_string()))
+        );
+        assert_eq!(
+            registry.preprocess("missing.glsl", &[]),
+            Err(Error::ShaderSource("missing.glsl:2: unknown file \"nowhere.glsl\"".to_string()))
+        );
+        assert!(registry.preprocess("unquoted.glsl", &[]).is_err());
+        assert!(registry.preprocess("other.glsl", &[]).is_err());
+    }
+
+    #[test]
+    fn compile_logs_point_at_the_original_files() {
+        let output = registry().preprocess("main.glsl", &[("NIGHT_LIGHTS", "")]).unwrap();
+        let log = "ERROR: 0:4: 'PI' : redefinition\nERROR: 0:99: 'x' : syntax error\nWARNING: 0:2: extension";
+
+        assert_eq!(output.map_log(log), [
+            "ERROR: common/light.glsl:2: 'PI' : redefinition",
+            "ERROR: 0:99: 'x' : syntax error",
+            "WARNING: <defines>:1: extension"
+        ].join("\n"));
+    }
+
+    #[test]
+    fn embedded_shaders_preprocess() {
+        let registry = ShaderRegistry::embedded();
+        for (name, _) in EMBEDDED {
+            let output = registry.preprocess(name, &[]).unwrap();
+            assert!(!output.source.contains("#include"));
+        }
+    }
+
+    #[test]
+    fn variants_are_cached_per_defines() {
+        let gl = MockBackend::new();
+        let mut registry = registry();
+        registry.insert("vs.glsl", "#version 300 es\nvoid main() {}\n");
+        registry.insert("broken.glsl", "#version 300 es\n#include \"common/math.glsl\"\n#error no lights\n");
+        registry.add_program("main", "vs.glsl", "main.glsl");
+        registry.add_program("broken", "vs.glsl", "broken.glsl");
+        let mut cache = ShaderCache::new(registry);
+
+        let a = cache.get(&gl, "main", &[]).unwrap();
+        let b = cache.get(&gl, "main", &[]).unwrap();
+        let c = cache.get(&gl, "main", &[("NIGHT_LIGHTS", "")]).unwrap();
+        assert!(Rc::ptr_eq(&a, &b));
+        assert!(!Rc::ptr_eq(&a, &c));
+        let programs = gl.calls().iter().filter(|c| matches!(c, GlCall::CreateProgram(_))).count();
+        assert_eq!(programs, 2);
+
+        assert_eq!(cache.get(&gl, "broken", &[]).err(), Some(Error::ShaderCompile {
+            stage: ShaderStage::Fragment,
+            log: "ERROR: broken.glsl:3: '#error' : no lights".to_string()
+        }));
+        assert!(cache.get(&gl, "missing", &[]).is_err());
+    }
+
+    fn reload_cache() -> ShaderCache {
+        let mut registry = ShaderRegistry::new();
+        registry.insert("common/color.glsl", "const vec4 COLOR = vec4(1.0);\n");
+        registry.insert("vs.glsl", "#version 300 es\nin vec4 a_position;\nin vec3 a_normal;\nvoid main() {}\n");
+        registry.insert("red.glsl", "#version 300 es\n#include \"common/color.glsl\"\nvoid main() {}\n");
+        registry.insert("blue.glsl", "#version 300 es\nvoid main() {}\n");
+        registry.add_program("red", "vs.glsl", "red.glsl");
+        registry.add_program("blue", "vs.glsl", "blue.glsl");
+        ShaderCache::new(registry)
+    }
+
+    #[test]
+    fn reload_swaps_the_programs_in_place() {
+        let gl = MockBackend::new();
+        let mut cache = reload_cache();
+        let red = cache.get(&gl, "red", &[]).unwrap();
+        let blue = cache.get(&gl, "blue", &[]).unwrap();
+        assert_eq!(red.attribute("a_normal").map(|a| a.location), Ok(1));
+        let (old_red, old_blue) = (red.program(), blue.program());
+
+        // a_normal moves to the front, but keeps the location the vertex
+        // arrays were set up with.
+        let vs = "#version 300 es\nin vec3 a_normal;\nin vec4 a_position;\nvoid main() {}\n";
+        cache.reload(&gl, "red", vs, "#version 300 es\n#include \"common/color.glsl\"\nvoid main() { }\n").unwrap();
+
+        assert_ne!(red.program(), old_red);
+        assert_eq!(red.attribute("a_normal").map(|a| a.location), Ok(1));
+        gl.delete_released();
+        assert!(gl.calls().contains(&GlCall::DeleteProgram(old_red)));
+        // The vertex shader is shared with the blue program.
+        assert_ne!(blue.program(), old_blue);
+
+        // Sources that do not change keep their program.
+        let blue_program = blue.program();
+        cache.reload(&gl, "red", vs, "#version 300 es\nvoid main() {}\n").unwrap();
+        assert_eq!(blue.program(), blue_program);
+    }
+
+    #[test]
+    fn failed_reloads_keep_the_previous_program() {
+        let gl = MockBackend::new();
+        let mut cache = reload_cache();
+        let red = cache.get(&gl, "red", &[]).unwrap();
+        let blue = cache.get(&gl, "blue", &[]).unwrap();
+        let (old_red, old_blue) = (red.program(), blue.program());
+        let vs = "#version 300 es\nin vec4 a_position;\nvoid main() { }\n";
+
+        let error = cache.reload(&gl, "red", vs, "#version 300 es\n\n#error missing semicolon\n").err();
+        assert_eq!(error, Some(Error::ShaderCompile {
+            stage: ShaderStage::Fragment,
+            log: "ERROR: red.glsl:3: '#error' : missing semicolon".to_string()
+        }));
+        assert_eq!((red.program(), blue.program()), (old_red, old_blue));
+        gl.delete_released();
+        assert!(!gl.calls().contains(&GlCall::DeleteProgram(old_red)));
+        assert!(!gl.calls().contains(&GlCall::DeleteProgram(old_blue)));
+
+        // The sources are back to the previous ones as well, so new variants
+        // still build.
+        cache.get(&gl, "red", &[("DEBUG", "")]).unwrap();
+        assert!(cache.reload(&gl, "green", vs, vs).is_err());
+    }
+
+    #[test]
+    fn restoring_rebuilds_every_variant() {
+        let gl = MockBackend::new();
+        let mut cache = reload_cache();
+        let red = cache.get(&gl, "red", &[]).unwrap();
+        let debug = cache.get(&gl, "red", &[("DEBUG", "")]).unwrap();
+        let (old_red, old_debug) = (red.program(), debug.program());
+
+        gl.reset();
+        cache.restore(&gl).unwrap();
+
+        assert_ne!(red.program(), old_red);
+        assert_ne!(debug.program(), old_debug);
+        assert_eq!(red.attribute("a_normal").map(|a| a.location), Ok(1));
+        assert_eq!(gl.resources().stats().programs, 2);
+        // The lost programs went with the context.
+        gl.clear_calls();
+        gl.delete_released();
+        assert!(gl.calls().is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use web_sys::WebGl2RenderingContext as GL;
+
+use crate::backend::{GlBackend, ProgramId, UniformLocationId};
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ActiveAttribute {
+    pub name: String,
+    pub gl_type: u32,
+    pub size: i32,
+    pub location: u32
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ActiveUniform {
+    pub name: String,
+    pub gl_type: u32,
+    pub size: i32,
+    pub location: Option<UniformLocationId>
+}
+
+
+/// What a linked program declares, read once after linking.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Reflection {
+    pub attributes: HashMap<String, ActiveAttribute>,
+    pub uniforms: HashMap<String, ActiveUniform>,
+    pub uniform_blocks: Vec<String>
+}
+
+impl Reflection {
+    pub fn of(gl: &dyn GlBackend, program: ProgramId) -> Self {
+        let attributes = gl.active_attributes(program).into_iter()
+            .filter_map(|info| {
+                let location = gl.get_attrib_location(program, &info.name);
+                // Built-ins such as gl_VertexID have no location.
+                if location < 0 {
+                    return None;
+                }
+                Some((info.name.clone(), ActiveAttribute {
+                    location: location as u32,
+                    name: info.name,
+                    gl_type: info.gl_type,
+                    size: info.size
+                }))
+            })
+            .collect();
+
+        let uniforms = gl.active_uniforms(program).into_iter()
+            .map(|info| (info.name.clone(), ActiveUniform {
+                location: gl.get_uniform_location(program, &info.name),
+                name: info.name,
+                gl_type: info.gl_type,
+                size: info.size
+            }))
+            .collect();
+
+        Reflection {
+            attributes,
+            uniforms,
+            uniform_blocks: gl.active_uniform_blocks(program)
+        }
+    }
+}
+
+
+/// GLSL name of a type reported by `getActiveAttrib` / `getActiveUniform`.
+pub fn glsl_type_name(gl_type: u32) -> &'static str {
+    match gl_type {
+        GL::FLOAT => "float",
+        GL::FLOAT_VEC2 => "vec2",
+        GL::FLOAT_VEC3 => "vec3",
+        GL::FLOAT_VEC4 => "vec4",
+        GL::INT => "int",
+        GL::INT_VEC2 => "ivec2",
+        GL::INT_VEC3 => "ivec3",
+        GL::INT_VEC4 => "ivec4",
+        GL::UNSIGNED_INT => "uint",
+        GL::UNSIGNED_INT_VEC2 => "uvec2",
+        GL::UNSIGNED_INT_VEC3 => "uvec3",
+        GL::UNSIGNED_INT_VEC4 => "uvec4",
+        GL::BOOL => "bool",
+        GL::BOOL_VEC2 => "bvec2",
+        GL::BOOL_VEC3 => "bvec3",
+        GL::BOOL_VEC4 => "bvec4",
+        GL::FLOAT_MAT2 => "mat2",
+        GL::FLOAT_MAT3 => "mat3",
+        GL::FLOAT_MAT4 => "mat4",
+        GL::SAMPLER_2D => "sampler2D",
+        GL::SAMPLER_3D => "sampler3D",
+        GL::SAMPLER_CUBE => "samplerCube",
+        GL::SAMPLER_2D_ARRAY => "sampler2DArray",
+        GL::SAMPLER_2D_SHADOW => "sampler2DShadow",
+        GL::INT_SAMPLER_2D => "isampler2D",
+        GL::UNSIGNED_INT_SAMPLER_2D => "usampler2D",
+        _ => "unknown type"
+    }
+}
+
+/// Number of components of a float attribute type, which can be fed from
+/// float or normalized integer buffers.
+pub fn float_components(gl_type: u32) -> Option<i32> {
+    match gl_type {
+        GL::FLOAT => Some(1),
+        GL::FLOAT_VEC2 => Some(2),
+        GL::FLOAT_VEC3 => Some(3),
+        GL::FLOAT_VEC4 => Some(4),
+        _ => None
+    }
+}
+
+pub fn is_sampler(gl_type: u32) -> bool {
+    sampler_target(gl_type).is_some()
+}
+
+/// Whether a sampler type reads integer textures.
+pub fn is_integer_sampler(gl_type: u32) -> bool {
+    matches!(
+        gl_type,
+        GL::INT_SAMPLER_2D | GL::INT_SAMPLER_3D | GL::INT_SAMPLER_CUBE | GL::INT_SAMPLER_2D_ARRAY
+            | GL::UNSIGNED_INT_SAMPLER_2D | GL::UNSIGNED_INT_SAMPLER_3D | GL::UNSIGNED_INT_SAMPLER_CUBE
+            | GL::UNSIGNED_INT_SAMPLER_2D_ARRAY
+    )
+}
+
+/// Texture target a sampler type reads from.
+pub fn sampler_target(gl_type: u32) -> Option<u32> {
+    match gl_type {
+        GL::SAMPLER_2D | GL::SAMPLER_2D_SHADOW | GL::INT_SAMPLER_2D | GL::UNSIGNED_INT_SAMPLER_2D => Some(GL::TEXTURE_2D),
+        GL::SAMPLER_3D | GL::INT_SAMPLER_3D | GL::UNSIGNED_INT_SAMPLER_3D => Some(GL::TEXTURE_3D),
+        GL::SAMPLER_CUBE | GL::SAMPLER_CUBE_SHADOW | GL::INT_SAMPLER_CUBE | GL::UNSIGNED_INT_SAMPLER_CUBE => {
+            Some(GL::TEXTURE_CUBE_MAP)
+        },
+        GL::SAMPLER_2D_ARRAY | GL::SAMPLER_2D_ARRAY_SHADOW | GL::INT_SAMPLER_2D_ARRAY | GL::UNSIGNED_INT_SAMPLER_2D_ARRAY => {
+            Some(GL::TEXTURE_2D_ARRAY)
+        },
+        _ => None
+    }
+}
@@ -0,0 +1,147 @@
+use web_sys::WebGl2RenderingContext as GL;
+
+use crate::backend::{GlBackend, UniformLocationId};
+use crate::error::{Error, Result};
+use super::{glsl_type_name, is_sampler};
+
+
+/// Value of a uniform, typed after its GLSL declaration.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UniformValue {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    Int(i32),
+    IVec2([i32; 2]),
+    IVec3([i32; 3]),
+    IVec4([i32; 4]),
+    /// Column-major.
+    Mat3([f32; 9]),
+    /// Column-major.
+    Mat4([f32; 16]),
+    /// Texture unit the sampler reads from.
+    Sampler(i32)
+}
+
+impl UniformValue {
+    /// Builds a value from plain numbers, as received from JS, for a
+    /// uniform declared with `gl_type`. Integer and bool types take the
+    /// numbers truncated; samplers are set through their textures instead.
+    pub fn from_slice(data: &[f32], gl_type: u32) -> Result<UniformValue> {
+        let ints = || data.iter().map(|x| *x as i32).collect::<Vec<_>>();
+
+        let value = match (gl_type, data.len()) {
+            (GL::FLOAT, 1) => UniformValue::Float(data[0]),
+            (GL::FLOAT_VEC2, 2) => UniformValue::Vec2([data[0], data[1]]),
+            (GL::FLOAT_VEC3, 3) => UniformValue::Vec3([data[0], data[1], data[2]]),
+            (GL::FLOAT_VEC4, 4) => UniformValue::Vec4([data[0], data[1], data[2], data[3]]),
+            (GL::INT | GL::BOOL, 1) => UniformValue::Int(ints()[0]),
+            (GL::INT_VEC2 | GL::BOOL_VEC2, 2) => UniformValue::IVec2([ints()[0], ints()[1]]),
+            (GL::INT_VEC3 | GL::BOOL_VEC3, 3) => UniformValue::IVec3([ints()[0], ints()[1], ints()[2]]),
+            (GL::INT_VEC4 | GL::BOOL_VEC4, 4) => UniformValue::IVec4([ints()[0], ints()[1], ints()[2], ints()[3]]),
+            (GL::FLOAT_MAT3, 9) => {
+                let mut m = [0.0; 9];
+                m.copy_from_slice(data);
+                UniformValue::Mat3(m)
+            },
+            (GL::FLOAT_MAT4, 16) => {
+                let mut m = [0.0; 16];
+                m.copy_from_slice(data);
+                UniformValue::Mat4(m)
+            },
+            _ => {
+                return Err(Error::InvalidUniform(format!(
+                    "cannot use {} numbers for {}", data.len(), glsl_type_name(gl_type)
+                )));
+            }
+        };
+        Ok(value)
+    }
+
+    /// Whether the value can be uploaded to a uniform of `gl_type`, as
+    /// reported by `getActiveUniform`.
+    pub fn matches(&self, gl_type: u32) -> bool {
+        match self {
+            UniformValue::Float(_) => gl_type == GL::FLOAT,
+            UniformValue::Vec2(_) => gl_type == GL::FLOAT_VEC2,
+            UniformValue::Vec3(_) => gl_type == GL::FLOAT_VEC3,
+            UniformValue::Vec4(_) => gl_type == GL::FLOAT_VEC4,
+            UniformValue::Int(_) => gl_type == GL::INT || gl_type == GL::BOOL,
+            UniformValue::IVec2(_) => gl_type == GL::INT_VEC2 || gl_type == GL::BOOL_VEC2,
+            UniformValue::IVec3(_) => gl_type == GL::INT_VEC3 || gl_type == GL::BOOL_VEC3,
+            UniformValue::IVec4(_) => gl_type == GL::INT_VEC4 || gl_type == GL::BOOL_VEC4,
+            UniformValue::Mat3(_) => gl_type == GL::FLOAT_MAT3,
+            UniformValue::Mat4(_) => gl_type == GL::FLOAT_MAT4,
+            UniformValue::Sampler(_) => is_sampler(gl_type)
+        }
+    }
+
+    pub fn glsl_type(&self) -> &'static str {
+        match self {
+            UniformValue::Float(_) => "float",
+            UniformValue::Vec2(_) => "vec2",
+            UniformValue::Vec3(_) => "vec3",
+            UniformValue::Vec4(_) => "vec4",
+            UniformValue::Int(_) => "int",
+            UniformValue::IVec2(_) => "ivec2",
+            UniformValue::IVec3(_) => "ivec3",
+            UniformValue::IVec4(_) => "ivec4",
+            UniformValue::Mat3(_) => "mat3",
+            UniformValue::Mat4(_) => "mat4",
+            UniformValue::Sampler(_) => "sampler"
+        }
+    }
+
+    /// Uploads the value to `location` of the program in use.
+    pub fn upload(&self, gl: &dyn GlBackend, location: Option<UniformLocationId>) {
+        match *self {
+            UniformValue::Float(x) => gl.uniform1f(location, x),
+            UniformValue::Vec2([x, y]) => gl.uniform2f(location, x, y),
+            UniformValue::Vec3([x, y, z]) => gl.uniform3f(location, x, y, z),
+            UniformValue::Vec4([x, y, z, w]) => gl.uniform4f(location, x, y, z, w),
+            UniformValue::Int(x) | UniformValue::Sampler(x) => gl.uniform1i(location, x),
+            UniformValue::IVec2([x, y]) => gl.uniform2i(location, x, y),
+            UniformValue::IVec3([x, y, z]) => gl.uniform3i(location, x, y, z),
+            UniformValue::IVec4([x, y, z, w]) => gl.uniform4i(location, x, y, z, w),
+            UniformValue::Mat3(ref m) => gl.uniform_matrix3fv(location, false, m),
+            UniformValue::Mat4(ref m) => gl.uniform_matrix4fv(location, false, m)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_take_the_declared_type() {
+        assert_eq!(UniformValue::from_slice(&[0.5], GL::FLOAT), Ok(UniformValue::Float(0.5)));
+        assert_eq!(UniformValue::from_slice(&[1.0, 0.5, 0.0], GL::FLOAT_VEC3), Ok(UniformValue::Vec3([1.0, 0.5, 0.0])));
+        assert!(matches!(UniformValue::from_slice(&[0.0; 16], GL::FLOAT_MAT4), Ok(UniformValue::Mat4(_))));
+        assert_eq!(UniformValue::from_slice(&[7.0], GL::INT), Ok(UniformValue::Int(7)));
+        assert_eq!(UniformValue::from_slice(&[1.0, 2.0], GL::INT_VEC2), Ok(UniformValue::IVec2([1, 2])));
+        assert_eq!(UniformValue::from_slice(&[1.0, 0.0, 1.0], GL::BOOL_VEC3), Ok(UniformValue::IVec3([1, 0, 1])));
+    }
+
+    #[test]
+    fn values_match_glsl_types() {
+        assert!(UniformValue::Vec3([0.0; 3]).matches(GL::FLOAT_VEC3));
+        assert!(!UniformValue::Vec3([0.0; 3]).matches(GL::FLOAT_VEC4));
+        assert!(UniformValue::Int(1).matches(GL::BOOL));
+        assert!(!UniformValue::Int(1).matches(GL::FLOAT));
+        assert!(UniformValue::Sampler(0).matches(GL::SAMPLER_CUBE));
+        assert!(!UniformValue::Sampler(0).matches(GL::INT));
+    }
+
+    #[test]
+    fn numbers_have_to_fit_the_declaration() {
+        assert_eq!(
+            UniformValue::from_slice(&[1.0, 1.0], GL::FLOAT_VEC3),
+            Err(Error::InvalidUniform("cannot use 2 numbers for vec3".to_string()))
+        );
+        assert!(UniformValue::from_slice(&[], GL::FLOAT).is_err());
+        assert!(UniformValue::from_slice(&[0.0], GL::SAMPLER_2D).is_err());
+    }
+}